@@ -0,0 +1,83 @@
+use crate::render::{Clock, RenderError, VideoSink};
+use png::{BitDepth, ColorType, Encoder, Writer};
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Writes frames as an animated PNG, which unlike MP4 is lossless and needs no seeking.
+pub struct ApngSink<W: Write> {
+    writer: Writer<W>,
+    clock: Clock,
+    /// Whether the image is animated, as opposed to a still.
+    animated: bool,
+    /// How many frames have been written so far.
+    nb_frames: u64,
+}
+
+impl<W: Write> ApngSink<W> {
+    /// The APNG header contains the amount of frames, so it must be known upfront; if it is zero,
+    /// a still image is written instead, since APNG forbids empty animations.
+    pub fn new(
+        out: W,
+        width: u32,
+        height: u32,
+        nb_frames: usize,
+        clock: Clock,
+    ) -> Result<Self, RenderError> {
+        let mut encoder = Encoder::new(out, width, height);
+        encoder.set_color(ColorType::Rgb);
+        encoder.set_depth(BitDepth::Eight);
+        if nb_frames != 0 {
+            encoder.set_animated(nb_frames.try_into().unwrap(), 0)?;
+            let (num, den) = clock.delay(0);
+            encoder.set_frame_delay(num, den)?;
+        }
+
+        Ok(Self {
+            writer: encoder.write_header()?,
+            clock,
+            animated: nb_frames != 0,
+            nb_frames: 0,
+        })
+    }
+}
+
+impl<W: Write> VideoSink for ApngSink<W> {
+    fn write_frame(&mut self, pixels: &[u8]) -> Result<(), RenderError> {
+        // Frames may not all last the same when fitting a duration
+        if self.animated {
+            let (num, den) = self.clock.delay(self.nb_frames);
+            self.writer.set_frame_delay(num, den)?;
+        }
+        self.nb_frames += 1;
+        self.writer.write_image_data(pixels)?;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), RenderError> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Writes a single frame as a still PNG.
+pub fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), RenderError> {
+    encode_png(BufWriter::new(File::create(path)?), width, height, pixels)
+}
+
+/// Like `write_png`, but into any writer, e.g. a buffer.
+pub fn encode_png<W: Write>(
+    out: W,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+) -> Result<(), RenderError> {
+    let mut encoder = Encoder::new(out, width, height);
+    encoder.set_color(ColorType::Rgb);
+    encoder.set_depth(BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()?;
+    Ok(())
+}
@@ -0,0 +1,299 @@
+//! Checkpoints, so that long renders can be resumed if interrupted.
+//!
+//! The checkpoint file is a sequence of records, appended to as rendering progresses:
+//! - `S`, the frame's index (u64), the sample's size (u32), and the encoded sample;
+//! - `C`, how many frames were rendered so far (u64), the canvas' size (u64), and its pixels.
+//!
+//! All integers are little-endian.
+//! Resuming writes back the samples up to the last `C` record, then continues rendering from there.
+//! `C` records are only written when the encoder restarts, so that encoding can carry on as if it
+//! had never been interrupted.
+
+use crate::encode::{Mp4Sink, RESTART_INTERVAL};
+use crate::render::{Frames, RenderError};
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+pub struct Checkpoint {
+    file: BufWriter<File>,
+    path: PathBuf,
+    nb_frames: usize,
+}
+
+enum Record {
+    Sample { index: u64, size: u64 },
+    Checkpoint { nb_frames: u64, size: u64 },
+}
+
+impl Record {
+    /// Reads a record's header, or returns `None` at the end of the file.
+    fn read_header<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut tag = [0];
+        if reader.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+
+        let mut u64_buf = [0; 8];
+        reader.read_exact(&mut u64_buf)?;
+        let first = u64::from_le_bytes(u64_buf);
+        match &tag {
+            b"S" => {
+                let mut u32_buf = [0; 4];
+                reader.read_exact(&mut u32_buf)?;
+                Ok(Some(Self::Sample {
+                    index: first,
+                    size: u32::from_le_bytes(u32_buf).into(),
+                }))
+            }
+            b"C" => {
+                reader.read_exact(&mut u64_buf)?;
+                Ok(Some(Self::Checkpoint {
+                    nb_frames: first,
+                    size: u64::from_le_bytes(u64_buf),
+                }))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Bad checkpoint record type {:#04x}", tag[0]),
+            )),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match self {
+            Self::Sample { size, .. } | Self::Checkpoint { size, .. } => *size,
+        }
+    }
+}
+
+impl Checkpoint {
+    /// How many frames are rendered between checkpoints (one minute of video).
+    const INTERVAL: usize = RESTART_INTERVAL * 60;
+
+    pub fn path_for(out_path: &Path) -> PathBuf {
+        let mut path = out_path.as_os_str().to_owned();
+        path.push(".checkpoint");
+        path.into()
+    }
+
+    pub fn create(path: &Path) -> Result<Self, RenderError> {
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+            path: path.to_owned(),
+            nb_frames: 0,
+        })
+    }
+
+    /// Writes the samples saved in the checkpoint file to the sink, and skips as many frames.
+    /// If there is no checkpoint file, this starts from scratch instead.
+    pub fn resume<W: Write + Seek>(
+        path: &Path,
+        frames: &mut Frames,
+        sink: &mut Mp4Sink<W>,
+    ) -> Result<Self, RenderError> {
+        let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                eprintln!("No checkpoint found at {}, starting over", path.display());
+                return Self::create(path);
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(&mut file);
+
+        // First, find the last complete checkpoint; anything past it is discarded
+        let mut last_checkpoint = None;
+        loop {
+            let record = match Record::read_header(&mut reader) {
+                Ok(Some(record)) => record,
+                // A truncated header means the render was interrupted while writing it
+                Ok(None) => break,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            };
+            let payload_ofs = reader.stream_position()?;
+            let end_ofs = payload_ofs + record.size();
+            if end_ofs > file_len {
+                break;
+            }
+            if let Record::Checkpoint { nb_frames, size } = record {
+                last_checkpoint = Some((end_ofs, nb_frames, payload_ofs, size));
+            }
+            reader.seek(SeekFrom::Start(end_ofs))?;
+        }
+
+        let (end_ofs, nb_frames, canvas_ofs, canvas_size) = match last_checkpoint {
+            Some(checkpoint) => checkpoint,
+            None => {
+                eprintln!("No checkpoint found in {}, starting over", path.display());
+                drop(reader);
+                return Self::create(path);
+            }
+        };
+
+        // Then, write back all the samples up to it
+        reader.rewind()?;
+        let mut nb_samples = 0;
+        while reader.stream_position()? < end_ofs {
+            match Record::read_header(&mut reader)?.unwrap() {
+                // Samples are written back in order, so their index is only a sanity check
+                Record::Sample { index, size } => {
+                    if index != nb_samples {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "Checkpoint {} is corrupted (expected sample {}, got {})",
+                                path.display(),
+                                nb_samples,
+                                index
+                            ),
+                        )
+                        .into());
+                    }
+                    let mut bytes = vec![0; size.try_into().unwrap()];
+                    reader.read_exact(&mut bytes)?;
+                    sink.write_sample(bytes)?;
+                    nb_samples += 1;
+                }
+                Record::Checkpoint { size, .. } => {
+                    reader.seek(SeekFrom::Current(size.try_into().unwrap()))?;
+                }
+            }
+        }
+
+        // Catch up to the checkpoint, and check that we're in the same state as back then
+        let nb_frames = nb_frames.try_into().unwrap();
+        frames.fast_forward(nb_frames);
+        let mut canvas = vec![0; canvas_size.try_into().unwrap()];
+        reader.seek(SeekFrom::Start(canvas_ofs))?;
+        reader.read_exact(&mut canvas)?;
+        if canvas != frames.pixels() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "The checkpoint in {} does not match this input or these options",
+                    path.display()
+                ),
+            )
+            .into());
+        }
+        drop(reader);
+        eprintln!("Resuming from frame {}", nb_frames);
+
+        // Drop whatever came after the checkpoint, and continue from there
+        file.set_len(end_ofs)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            file: BufWriter::new(file),
+            path: path.to_owned(),
+            nb_frames,
+        })
+    }
+
+    /// How many frames have been rendered so far.
+    pub fn nb_frames(&self) -> usize {
+        self.nb_frames
+    }
+
+    pub fn record_sample(&mut self, index: usize, bytes: &[u8]) -> io::Result<()> {
+        let index: u64 = index.try_into().unwrap();
+        let size: u32 = bytes.len().try_into().unwrap();
+
+        self.file.write_all(b"S")?;
+        self.file.write_all(&index.to_le_bytes())?;
+        self.file.write_all(&size.to_le_bytes())?;
+        self.file.write_all(bytes)
+    }
+
+    /// Must be called after each frame has been recorded, with the canvas at that point.
+    pub fn frame_done(&mut self, pixels: &[u8]) -> io::Result<()> {
+        self.nb_frames += 1;
+        if !self.nb_frames.is_multiple_of(Self::INTERVAL) {
+            return Ok(());
+        }
+
+        let nb_frames: u64 = self.nb_frames.try_into().unwrap();
+        let size: u64 = pixels.len().try_into().unwrap();
+
+        self.file.write_all(b"C")?;
+        self.file.write_all(&nb_frames.to_le_bytes())?;
+        self.file.write_all(&size.to_le_bytes())?;
+        self.file.write_all(pixels)?;
+        self.file.flush()
+    }
+
+    /// Deletes the checkpoint file, once rendering is complete.
+    pub fn remove(self) -> io::Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use crate::render::{self, Options, RenderErrorKind};
+    use std::env;
+
+    #[test]
+    fn resumed_samples_count_towards_max_output_bytes() {
+        let mut log = String::from("[ROMX @ 01:ffff & 0 + 0] 16 A\n");
+        for i in 0..Checkpoint::INTERVAL {
+            log.push_str(&format!("01:{:04x}\n", 0x4000 + i));
+        }
+        let sequence =
+            parse::parse_input(log.as_bytes(), &Default::default(), &mut Vec::new()).unwrap();
+
+        // Interrupted right after a checkpoint, which happens to be at the end
+        let out_path = env::temp_dir().join("gb-packing-visualizer-resumed-too-large.mp4");
+        let path = Checkpoint::path_for(&out_path);
+        let mut frames = Frames::new(&sequence, &Options::default());
+        let mut checkpoint = Checkpoint::create(&path).unwrap();
+        for i in 0..Checkpoint::INTERVAL {
+            frames.next().unwrap();
+            checkpoint.record_sample(i, &[0; 100]).unwrap();
+            checkpoint.frame_done(frames.pixels()).unwrap();
+        }
+        drop(checkpoint);
+
+        let options = Options {
+            resume: true,
+            max_output_bytes: Some(100 * Checkpoint::INTERVAL as u64 - 1),
+            ..Options::default()
+        };
+        let result = render::render_with_progress(
+            &sequence,
+            &out_path,
+            &options,
+            &mut Vec::new(),
+            &mut |_, _| {},
+        );
+        assert!(matches!(
+            result.unwrap_err().kind(),
+            RenderErrorKind::TooLarge(_)
+        ));
+        assert!(!out_path.exists());
+        // Kept, to resume with a higher limit
+        assert!(path.exists());
+
+        let options = Options {
+            max_output_bytes: Some(100 * Checkpoint::INTERVAL as u64),
+            ..options
+        };
+        render::render_with_progress(
+            &sequence,
+            &out_path,
+            &options,
+            &mut Vec::new(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert!(out_path.exists());
+        assert!(!path.exists());
+        fs::remove_file(&out_path).unwrap();
+    }
+}
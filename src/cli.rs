@@ -0,0 +1,435 @@
+//! The command-line interface.
+
+use clap::{ArgAction, Parser, ValueEnum};
+use gb_packing_visualizer::{encode, parse, render, AddrStyle, MemType, Region};
+use regex::Regex;
+use std::convert::TryInto;
+use std::path::PathBuf;
+
+/// Renders an animation of RGBLINK packing sections, from its log.
+#[derive(Parser, Debug)]
+#[command(version, after_help = "See the README for details about each option.")]
+pub struct Args {
+    /// Where to write the output
+    #[arg(value_name = "OUTPUT FILE", required_unless_present_any = ["list_sections", "benchmark_render", "format"])]
+    pub out_path: Option<PathBuf>,
+
+    /// Read default settings from this TOML file
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Read the log from this file instead of standard input
+    #[arg(short, long, value_name = "PATH")]
+    pub input: Option<PathBuf>,
+    /// Read the log from this base64 text, optionally of gzipped data, or from the file after an `@`
+    #[arg(long, value_name = "DATA", conflicts_with = "input")]
+    pub input_base64: Option<String>,
+    /// What the input is, trace or csv [default: guessed from its extension]
+    #[arg(long, value_name = "FORMAT")]
+    pub input_format: Option<parse::InputFormat>,
+    /// Reject logs with longer lines
+    #[arg(long = "max-line-length", value_name = "BYTES", default_value_t = parse::DEFAULT_MAX_LINE_LEN)]
+    pub max_line_len: usize,
+    /// Turn all warnings into an error, e.g. about addresses outside of their section's memory type
+    #[arg(long)]
+    pub strict: bool,
+    /// Skip up to this many malformed lines of the log, instead of rejecting it [default: any amount]
+    #[arg(long, value_name = "N", num_args = 0..=1, require_equals = true)]
+    pub keep_going: Option<Option<u64>>,
+    /// Count location attempts before any section, instead of rejecting the log at the first one
+    #[arg(long)]
+    pub skip_orphan_attempts: bool,
+    /// Read all sections first, so that attempts may come before them, naming their section
+    #[arg(long, conflicts_with = "live")]
+    pub two_pass: bool,
+    /// What to do with sections defined again under the same name, merge, last, or error [default: keep both]
+    #[arg(long, value_name = "POLICY", conflicts_with = "two_pass")]
+    pub on_duplicate: Option<parse::OnDuplicate>,
+    /// Only keep the first attempts and the last one of sections attempted more than this
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_attempts_per_section: Option<u32>,
+    /// While reading the input, keep this PNG showing the latest frame, or the terminal with --format term
+    #[arg(long, value_name = "PATH", num_args = 0..=1, require_equals = true)]
+    pub live: Option<Option<PathBuf>>,
+    /// Render again whenever the input file changes
+    #[arg(long)]
+    pub watch: bool,
+    /// Report more details, such as the layout picked
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Encode a monochrome video
+    #[arg(long)]
+    pub grayscale: bool,
+    /// Give the video scanlines and a slight color shift, like a CRT screen
+    #[arg(long)]
+    pub crt: bool,
+    /// H.264 profile to declare, baseline, main, or high [default: baseline]
+    #[arg(long, value_name = "PROFILE")]
+    pub profile: Option<encode::Profile>,
+    /// H.264 level to declare, e.g. 4.1 [default: the lowest that fits]
+    #[arg(long, value_name = "X.Y", value_parser = parse_level)]
+    pub level: Option<u8>,
+    /// MP4 major brand to declare, four ASCII characters [default: isom]
+    #[arg(long, value_name = "BRAND", value_parser = parse_brand)]
+    pub mp4_brand: Option<[u8; 4]>,
+    /// MP4 brands to declare compatibility with, comma-separated [default: isom,iso2,avc1,mp41]
+    #[arg(long, value_name = "BRANDS", value_parser = parse_brand, value_delimiter = ',')]
+    pub mp4_compatible: Vec<[u8; 4]>,
+    /// Color placed sections based on their name
+    #[arg(long)]
+    pub color_by_name: bool,
+    /// Perturb the name-based colors (implies --color-by-name)
+    #[arg(long, value_name = "SEED")]
+    pub color_seed: Option<u64>,
+    /// Display this memory region, or all of them [default: guessed from the log; may be repeated]
+    #[arg(long = "region", value_name = "REGION", value_parser = parse_region, action = ArgAction::Append)]
+    pub regions: Vec<RegionArg>,
+    /// Only render sections of this memory type [default: all; may be repeated]
+    #[arg(long = "filter-type", value_name = "TYPE", value_parser = parse_mem_type, action = ArgAction::Append)]
+    pub mem_types: Vec<MemType>,
+    /// Frames per second
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u32).range(1..))]
+    pub fps: u32,
+    /// Make the output last this many seconds, however many frames it has (overrides --fps)
+    #[arg(long, value_name = "SECONDS", value_parser = parse_duration)]
+    pub duration: Option<f64>,
+    /// What each frame corresponds to
+    #[arg(long, value_enum, default_value_t = PaceBy::Attempts)]
+    pub pace_by: PaceBy,
+    /// With --pace-by bytes, bytes placed per frame
+    #[arg(long, value_name = "BYTES", default_value_t = 256, value_parser = clap::value_parser!(u64).range(1..))]
+    pub bytes_per_frame: u64,
+    /// How to display ROM0 past $4000, fold, romx, clamp, or tall [default: fold]
+    #[arg(long, value_name = "MODE")]
+    pub rom0_half: Option<render::Rom0Half>,
+    /// Which banks to show in which order, index, used, or utilization [default: index]
+    #[arg(long, value_name = "ORDER")]
+    pub bank_order: Option<render::BankOrder>,
+    /// Which way banks are laid out, vertical (as columns) or horizontal (as rows) [default: vertical]
+    #[arg(long, value_name = "ORIENTATION")]
+    pub orientation: Option<render::Orientation>,
+    /// Split sections into labelled panels, by type or by name prefix (up to the first `_`)
+    #[arg(long, value_name = "KEY")]
+    pub group_by: Option<render::GroupBy>,
+    /// Always show this section (or regex) in a distinct color [may be repeated]
+    #[arg(long, value_name = "NAME", value_parser = parse_highlight, action = ArgAction::Append)]
+    pub highlight: Vec<Regex>,
+    /// Highlight sections over this range, e.g. 03:5000-5200, and report when [may be repeated]
+    #[arg(long, value_name = "BANK:START-END", value_parser = parse_watch_range, action = ArgAction::Append)]
+    pub watch_range: Vec<render::WatchRange>,
+    /// Color sections by name, as listed in this file
+    #[arg(long, value_name = "PATH")]
+    pub palette_file: Option<PathBuf>,
+    /// Draw faint lines every this many bytes (in hex)
+    #[arg(long, value_name = "STEP", value_parser = parse_grid)]
+    pub grid: Option<u32>,
+    /// How to draw attempts, rect or dots [default: rect]
+    #[arg(long, value_name = "STYLE")]
+    pub attempt_style: Option<render::AttemptStyle>,
+    /// Opacity of the moving overlay, from 0.0 to 1.0
+    #[arg(long, value_name = "ALPHA", default_value_t = 1.0, value_parser = parse_alpha)]
+    pub overlay_alpha: f32,
+    /// Draw every section at least this many rows tall
+    #[arg(long, value_name = "ROWS", default_value_t = 1)]
+    pub min_section_px: u32,
+    /// Blend sections' edges in proportion to how much of their rows they cover
+    #[arg(long)]
+    pub antialias: bool,
+    /// Color free space, and carve sections out of it as they get placed
+    #[arg(long)]
+    pub show_free: bool,
+    /// On the last frame, color the largest free block of each bank
+    #[arg(long)]
+    pub show_largest_gap: bool,
+    /// Write each bank's number above it
+    #[arg(long)]
+    pub bank_labels: bool,
+    /// Mark banks once they are completely full
+    #[arg(long)]
+    pub mark_full: bool,
+    /// Outline the overlay with this many pixels
+    #[arg(long, value_name = "WIDTH", default_value_t = 0)]
+    pub overlay_border: u32,
+    /// Flash sections as they get placed
+    #[arg(long)]
+    pub flash_on_settle: bool,
+    /// How many frames flashes last [default: 6; implies --flash-on-settle]
+    #[arg(long, value_name = "N")]
+    pub flash_frames: Option<u32>,
+    /// Insert this many frames drawing each section growing into place, as it gets settled
+    #[arg(long, value_name = "FRAMES", default_value_t = 0)]
+    pub grow_on_settle: u32,
+    /// Instead of the animation, hold on each section as it's settled, along with all its attempts
+    #[arg(long)]
+    pub invert_attempts_settled: bool,
+    /// Only show the frames around sections settled over others or out of bounds
+    #[arg(long)]
+    pub only_problems: bool,
+    /// Drop attempts that would be drawn exactly like the frame before them
+    #[arg(long)]
+    pub coalesce_identical: bool,
+    /// Never settle sections, keeping every attempt drawn instead, to show everywhere they were tried
+    #[arg(long)]
+    pub no_settle: bool,
+    /// Output format, mp4, apng, webp, svg, or term [default: guessed from the output's extension]
+    #[arg(long, value_name = "FORMAT")]
+    pub format: Option<render::Format>,
+    /// Scale the output to this resolution
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_scale)]
+    pub scale: Option<(u32, u32)>,
+    /// How to scale to a different aspect ratio, letterbox or stretch [default: letterbox]
+    #[arg(long, value_name = "MODE")]
+    pub scale_mode: Option<render::ScaleMode>,
+    /// With --scale, only show a window as big as the output, panning along with the latest attempt
+    #[arg(long)]
+    pub follow: bool,
+    /// Drop this many frames after each one rendered
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub frame_skip: u32,
+    /// Only keep the last of every N frames of the output, flashes and title card included
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub sample_every: u32,
+    /// Print how full and fragmented each bank ends up
+    #[arg(long)]
+    pub stats: bool,
+    /// Also write this frame of the output as a PNG [may be repeated]
+    #[arg(long = "snapshot", value_name = "FRAME:PATH", value_parser = parse_snapshot, action = ArgAction::Append)]
+    pub snapshots: Vec<(usize, PathBuf)>,
+    /// Also write the last frame of the output as a PNG
+    #[arg(long, value_name = "PATH")]
+    pub still: Option<PathBuf>,
+    /// Write the frame each section was settled at over it, on --still and --megastill
+    #[arg(long)]
+    pub annotate_settle_frame: bool,
+    /// Also write every frame of the output as a PNG, in this directory
+    #[arg(long, value_name = "DIR")]
+    pub png_dir: Option<PathBuf>,
+    /// Also write every frame of the output as a PNG, into this zip archive
+    #[arg(long, value_name = "PATH")]
+    pub png_zip: Option<PathBuf>,
+    /// Embed this frame of the output (the last one by default) as the MP4's cover art
+    #[arg(long, value_name = "N", num_args = 0..=1, require_equals = true)]
+    pub poster_frame: Option<Option<usize>>,
+    /// Also write how many bytes each frame of the MP4 takes
+    #[arg(long, value_name = "PATH")]
+    pub frame_sizes: Option<PathBuf>,
+    /// Abort, removing the MP4, once it takes more than this many bytes
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_output_bytes: Option<u64>,
+    /// Keep up to this many encoded keyframes, to reuse for frames drawn exactly the same
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub frame_cache: usize,
+    /// Also write a table of the frames where each section is attempted and settled
+    #[arg(long, value_name = "PATH")]
+    pub index: Option<PathBuf>,
+    /// Also write a Graphviz graph of the sections sharing each bank
+    #[arg(long, value_name = "PATH")]
+    pub dot: Option<PathBuf>,
+    /// Also write an overview of evenly-spaced frames as a PNG
+    #[arg(long, value_name = "PATH")]
+    pub contact_sheet: Option<PathBuf>,
+    /// How many frames the contact sheet shows, at most
+    #[arg(long, value_name = "N", default_value_t = 16, value_parser = clap::value_parser!(u32).range(1..))]
+    pub contact_sheet_frames: u32,
+    /// Also write an image of what changed between the first frame and the last as a PNG
+    #[arg(long, value_name = "PATH")]
+    pub net_diff: Option<PathBuf>,
+    /// Also write the last frame's raw RGB8 pixels, after its width and height
+    #[arg(long, value_name = "PATH")]
+    pub dump_rgb: Option<PathBuf>,
+    /// Also write the last frame as a PNG with banks at full width, however wide that makes it
+    #[arg(long, value_name = "PATH")]
+    pub megastill: Option<PathBuf>,
+    /// Also write a PNG of what each color means, including those picked per section
+    #[arg(long, value_name = "PATH")]
+    pub legend_png: Option<PathBuf>,
+    /// Also export the parsed log and layout as JSON
+    #[arg(long, value_name = "PATH")]
+    pub export_json: Option<PathBuf>,
+    /// Also write WebVTT subtitles naming the current section
+    #[arg(long, value_name = "PATH")]
+    pub vtt: Option<PathBuf>,
+    /// Also write a timeline of the sections for chrome://tracing or Perfetto, as JSON
+    #[arg(long, value_name = "PATH")]
+    pub trace_json: Option<PathBuf>,
+    /// Add an audio track to the MP4 that clicks whenever sections get settled
+    #[arg(long)]
+    pub click_track: bool,
+    /// Tag the MP4 with this ROM's title and checksum
+    #[arg(long, value_name = "PATH")]
+    pub rom: Option<PathBuf>,
+    /// Display as many ROM banks as the --rom's header says it has, instead of only what the log uses
+    #[arg(long, requires = "rom")]
+    pub banks_from_rom: bool,
+    /// Begin with a card showing the title, date, and amount of sections and banks
+    #[arg(long)]
+    pub title_card: bool,
+    /// Leave the input's file name and modification date out of the output
+    #[arg(long)]
+    pub deterministic: bool,
+    /// Periodically save progress, to be able to resume
+    #[arg(long)]
+    pub checkpoint: bool,
+    /// Resume from a checkpoint, if any
+    #[arg(long)]
+    pub resume: bool,
+    /// Only list the sections and where they end up
+    #[arg(long)]
+    pub list_sections: bool,
+    /// Render without writing the output, and report how long that takes
+    #[arg(long, conflicts_with_all = ["checkpoint", "resume"])]
+    pub benchmark_render: bool,
+    /// How to show addresses, absolute or relative to their bank
+    #[arg(long, value_name = "STYLE", default_value = "absolute")]
+    pub addr_style: AddrStyle,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaceBy {
+    Attempts,
+    Bytes,
+}
+
+/// A `--region`'s value.
+#[derive(Debug, Clone, Copy)]
+pub enum RegionArg {
+    All,
+    One(Region),
+}
+
+pub fn parse_region(value: &str) -> Result<RegionArg, String> {
+    if value == "all" {
+        Ok(RegionArg::All)
+    } else {
+        value
+            .parse()
+            .map(RegionArg::One)
+            .map_err(|_| "unknown region".to_string())
+    }
+}
+
+pub fn parse_mem_type(value: &str) -> Result<MemType, String> {
+    value
+        .to_ascii_uppercase()
+        .parse()
+        .map_err(|_| "unknown memory type".to_string())
+}
+
+pub fn parse_highlight(pattern: &str) -> Result<Regex, String> {
+    // Match the exact name, or the pattern as a regex if it is one
+    let exact = regex::escape(pattern);
+    Ok(Regex::new(&format!("^(?:{}|{})$", exact, pattern))
+        .unwrap_or_else(|_| Regex::new(&format!("^{}$", exact)).unwrap()))
+}
+
+pub fn parse_watch_range(value: &str) -> Result<render::WatchRange, String> {
+    fn hex(digits: &str) -> &str {
+        digits.trim_start_matches('$').trim_start_matches("0x")
+    }
+    let (bank, range) = value
+        .split_once(':')
+        .ok_or_else(|| "expected <bank>:<start>-<end>".to_string())?;
+    let (start, end) = range
+        .split_once('-')
+        .ok_or_else(|| "expected <bank>:<start>-<end>".to_string())?;
+    let range = render::WatchRange {
+        bank: u32::from_str_radix(hex(bank), 16).map_err(|err| err.to_string())?,
+        start: u16::from_str_radix(hex(start), 16).map_err(|err| err.to_string())?,
+        end: u16::from_str_radix(hex(end), 16).map_err(|err| err.to_string())?,
+    };
+    if range.start > range.end {
+        return Err("the range must not end before it starts".to_string());
+    }
+    Ok(range)
+}
+
+pub fn parse_grid(step: &str) -> Result<u32, String> {
+    let digits = step.trim_start_matches('$').trim_start_matches("0x");
+    match u32::from_str_radix(digits, 16) {
+        Ok(0) => Err("must not be zero".to_string()),
+        Ok(step) => Ok(step),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn parse_alpha(value: &str) -> Result<f32, String> {
+    match value.parse() {
+        Ok(alpha) if (0.0..=1.0).contains(&alpha) => Ok(alpha),
+        Ok(_) => Err("must be between 0.0 and 1.0".to_string()),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+pub fn parse_duration(value: &str) -> Result<f64, String> {
+    match value.parse() {
+        Ok(duration) if duration > 0.0 && f64::is_finite(duration) => Ok(duration),
+        Ok(_) => Err("must be positive".to_string()),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+pub fn parse_scale(value: &str) -> Result<(u32, u32), String> {
+    match value
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+    {
+        Some((width, height)) if width != 0 && height != 0 => Ok((width, height)),
+        _ => Err("expected <width>x<height>, both non-zero".to_string()),
+    }
+}
+
+/// Parses an H.264 level into its `level_idc`.
+pub fn parse_level(value: &str) -> Result<u8, String> {
+    const LEVELS: [u8; 19] = [
+        10, 11, 12, 13, 20, 21, 22, 30, 31, 32, 40, 41, 42, 50, 51, 52, 60, 61, 62,
+    ];
+    let (major, minor) = value.split_once('.').unwrap_or((value, "0"));
+    match (major.parse::<u8>(), minor.parse::<u8>()) {
+        (Ok(major), Ok(minor)) if minor < 10 && LEVELS.contains(&(major * 10 + minor)) => {
+            Ok(major * 10 + minor)
+        }
+        _ => Err("not an H.264 level, e.g. 3.1 or 4".to_string()),
+    }
+}
+
+/// Parses an MP4 brand, which is a four-character code.
+pub fn parse_brand(value: &str) -> Result<[u8; 4], String> {
+    match value.as_bytes().try_into() {
+        Ok(brand) if value.is_ascii() => Ok(brand),
+        _ => Err(format!(
+            "brands must be exactly 4 ASCII characters, not \"{}\"",
+            value
+        )),
+    }
+}
+
+fn parse_snapshot(value: &str) -> Result<(usize, PathBuf), String> {
+    value
+        .split_once(':')
+        .and_then(|(frame, path)| Some((frame.parse().ok()?, PathBuf::from(path))))
+        .ok_or_else(|| "expected <frame>:<path>".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn live_leaves_the_output_alone() {
+        let args = Args::try_parse_from(["gbpv", "-i", "x.log", "--live", "out.mp4"]).unwrap();
+        assert_eq!(args.live, Some(None));
+        assert_eq!(args.out_path, Some(PathBuf::from("out.mp4")));
+        let args = Args::try_parse_from(["gbpv", "--live=now.png", "out.mp4"]).unwrap();
+        assert_eq!(args.live, Some(Some(PathBuf::from("now.png"))));
+        assert_eq!(args.out_path, Some(PathBuf::from("out.mp4")));
+    }
+
+    #[test]
+    fn poster_frame_leaves_the_output_alone() {
+        let args = Args::try_parse_from(["gbpv", "--poster-frame", "10"]).unwrap();
+        assert_eq!(args.poster_frame, Some(None));
+        assert_eq!(args.out_path, Some(PathBuf::from("10")));
+        let args = Args::try_parse_from(["gbpv", "--poster-frame=10", "out.mp4"]).unwrap();
+        assert_eq!(args.poster_frame, Some(Some(10)));
+    }
+}
@@ -0,0 +1,281 @@
+//! Defaults read from a configuration file, so that settings can be shared without long command
+//! lines. Anything given on the command line takes precedence over the file.
+
+use crate::cli::{self, Args};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde::Deserialize;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The file's contents; keys are named like the corresponding flags, and values are written the
+/// same way as on the command line, except for numbers and booleans.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    input_format: Option<String>,
+    max_line_length: Option<usize>,
+    strict: Option<bool>,
+    skip_orphan_attempts: Option<bool>,
+    two_pass: Option<bool>,
+    on_duplicate: Option<String>,
+    max_attempts_per_section: Option<u32>,
+    verbose: Option<bool>,
+    grayscale: Option<bool>,
+    crt: Option<bool>,
+    profile: Option<String>,
+    level: Option<f64>,
+    mp4_brand: Option<String>,
+    mp4_compatible: Option<String>,
+    color_by_name: Option<bool>,
+    color_seed: Option<u64>,
+    region: Option<Vec<String>>,
+    filter_type: Option<Vec<String>>,
+    banks_from_rom: Option<bool>,
+    fps: Option<u32>,
+    duration: Option<f64>,
+    pace_by: Option<String>,
+    bytes_per_frame: Option<u64>,
+    rom0_half: Option<String>,
+    bank_order: Option<String>,
+    orientation: Option<String>,
+    group_by: Option<String>,
+    highlight: Option<Vec<String>>,
+    watch_range: Option<Vec<String>>,
+    palette_file: Option<PathBuf>,
+    grid: Option<String>,
+    attempt_style: Option<String>,
+    overlay_alpha: Option<f32>,
+    overlay_border: Option<u32>,
+    min_section_px: Option<u32>,
+    antialias: Option<bool>,
+    show_free: Option<bool>,
+    show_largest_gap: Option<bool>,
+    mark_full: Option<bool>,
+    bank_labels: Option<bool>,
+    flash_on_settle: Option<bool>,
+    flash_frames: Option<u32>,
+    grow_on_settle: Option<u32>,
+    invert_attempts_settled: Option<bool>,
+    only_problems: Option<bool>,
+    coalesce_identical: Option<bool>,
+    no_settle: Option<bool>,
+    format: Option<String>,
+    scale: Option<String>,
+    scale_mode: Option<String>,
+    follow: Option<bool>,
+    frame_skip: Option<u32>,
+    sample_every: Option<u32>,
+    max_output_bytes: Option<u64>,
+    annotate_settle_frame: Option<bool>,
+    title_card: Option<bool>,
+    deterministic: Option<bool>,
+    addr_style: Option<String>,
+}
+
+impl Config {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        toml::from_str(&text).map_err(|err| err.to_string())
+    }
+
+    /// Fills in the arguments that were not given on the command line.
+    pub fn apply(self, args: &mut Args, matches: &ArgMatches) -> Result<(), String> {
+        let unset = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+        if let (Some(value), true) = (self.input_format, unset("input_format")) {
+            args.input_format = Some(parsed("input-format", &value)?);
+        }
+        if let (Some(value), true) = (self.max_line_length, unset("max_line_len")) {
+            args.max_line_len = value;
+        }
+        args.strict |= self.strict.unwrap_or(false);
+        args.skip_orphan_attempts |= self.skip_orphan_attempts.unwrap_or(false);
+        args.two_pass |= self.two_pass.unwrap_or(false);
+        if let (Some(value), true) = (self.on_duplicate, unset("on_duplicate")) {
+            args.on_duplicate = Some(parsed("on-duplicate", &value)?);
+        }
+        if let (Some(value), true) = (
+            self.max_attempts_per_section,
+            unset("max_attempts_per_section"),
+        ) {
+            if value == 0 {
+                return Err("max-attempts-per-section: must not be zero".to_string());
+            }
+            args.max_attempts_per_section = Some(value);
+        }
+        args.verbose |= self.verbose.unwrap_or(false);
+        args.grayscale |= self.grayscale.unwrap_or(false);
+        args.crt |= self.crt.unwrap_or(false);
+        if let (Some(value), true) = (self.profile, unset("profile")) {
+            args.profile = Some(parsed("profile", &value)?);
+        }
+        if let (Some(value), true) = (self.level, unset("level")) {
+            args.level = Some(
+                cli::parse_level(&value.to_string()).map_err(|err| format!("level: {}", err))?,
+            );
+        }
+        if let (Some(value), true) = (self.mp4_brand, unset("mp4_brand")) {
+            args.mp4_brand =
+                Some(cli::parse_brand(&value).map_err(|err| format!("mp4-brand: {}", err))?);
+        }
+        if let (Some(value), true) = (self.mp4_compatible, unset("mp4_compatible")) {
+            args.mp4_compatible = value
+                .split(',')
+                .map(|brand| {
+                    cli::parse_brand(brand).map_err(|err| format!("mp4-compatible: {}", err))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        args.color_by_name |= self.color_by_name.unwrap_or(false);
+        if let (Some(value), true) = (self.color_seed, unset("color_seed")) {
+            args.color_seed = Some(value);
+        }
+        if let (Some(values), true) = (self.region, unset("regions")) {
+            args.regions = values
+                .iter()
+                .map(|value| cli::parse_region(value).map_err(|err| format!("region: {}", err)))
+                .collect::<Result<_, _>>()?;
+        }
+        if let (Some(values), true) = (self.filter_type, unset("mem_types")) {
+            args.mem_types = values
+                .iter()
+                .map(|value| {
+                    cli::parse_mem_type(value).map_err(|err| format!("filter-type: {}", err))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        args.banks_from_rom |= self.banks_from_rom.unwrap_or(false);
+        if let (Some(value), true) = (self.fps, unset("fps")) {
+            if value == 0 {
+                return Err("fps: must not be zero".to_string());
+            }
+            args.fps = value;
+        }
+        if let (Some(value), true) = (self.duration, unset("duration")) {
+            if !(value > 0.0 && value.is_finite()) {
+                return Err("duration: must be positive".to_string());
+            }
+            args.duration = Some(value);
+        }
+        if let (Some(value), true) = (self.pace_by, unset("pace_by")) {
+            args.pace_by = match value.as_str() {
+                "attempts" => cli::PaceBy::Attempts,
+                "bytes" => cli::PaceBy::Bytes,
+                _ => return Err("pace-by: expected \"attempts\" or \"bytes\"".to_string()),
+            };
+        }
+        if let (Some(value), true) = (self.bytes_per_frame, unset("bytes_per_frame")) {
+            if value == 0 {
+                return Err("bytes-per-frame: must not be zero".to_string());
+            }
+            args.bytes_per_frame = value;
+        }
+        if let (Some(value), true) = (self.rom0_half, unset("rom0_half")) {
+            args.rom0_half = Some(parsed("rom0-half", &value)?);
+        }
+        if let (Some(value), true) = (self.bank_order, unset("bank_order")) {
+            args.bank_order = Some(parsed("bank-order", &value)?);
+        }
+        if let (Some(value), true) = (self.orientation, unset("orientation")) {
+            args.orientation = Some(parsed("orientation", &value)?);
+        }
+        if let (Some(value), true) = (self.group_by, unset("group_by")) {
+            args.group_by = Some(parsed("group-by", &value)?);
+        }
+        if let (Some(values), true) = (self.highlight, unset("highlight")) {
+            args.highlight = values
+                .iter()
+                .map(|value| cli::parse_highlight(value))
+                .collect::<Result<_, _>>()?;
+        }
+        if let (Some(values), true) = (self.watch_range, unset("watch_range")) {
+            args.watch_range = values
+                .iter()
+                .map(|value| {
+                    cli::parse_watch_range(value).map_err(|err| format!("watch-range: {}", err))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let (Some(value), true) = (self.palette_file, unset("palette_file")) {
+            args.palette_file = Some(value);
+        }
+        if let (Some(value), true) = (self.grid, unset("grid")) {
+            args.grid = Some(cli::parse_grid(&value).map_err(|err| format!("grid: {}", err))?);
+        }
+        if let (Some(value), true) = (self.attempt_style, unset("attempt_style")) {
+            args.attempt_style = Some(parsed("attempt-style", &value)?);
+        }
+        if let (Some(value), true) = (self.overlay_alpha, unset("overlay_alpha")) {
+            if !(0.0..=1.0).contains(&value) {
+                return Err("overlay-alpha: must be between 0.0 and 1.0".to_string());
+            }
+            args.overlay_alpha = value;
+        }
+        if let (Some(value), true) = (self.overlay_border, unset("overlay_border")) {
+            args.overlay_border = value;
+        }
+        if let (Some(value), true) = (self.min_section_px, unset("min_section_px")) {
+            args.min_section_px = value;
+        }
+        args.antialias |= self.antialias.unwrap_or(false);
+        args.show_free |= self.show_free.unwrap_or(false);
+        args.show_largest_gap |= self.show_largest_gap.unwrap_or(false);
+        args.mark_full |= self.mark_full.unwrap_or(false);
+        args.bank_labels |= self.bank_labels.unwrap_or(false);
+        args.flash_on_settle |= self.flash_on_settle.unwrap_or(false);
+        if let (Some(value), true) = (self.flash_frames, unset("flash_frames")) {
+            args.flash_frames = Some(value);
+        }
+        if let (Some(value), true) = (self.grow_on_settle, unset("grow_on_settle")) {
+            args.grow_on_settle = value;
+        }
+        args.invert_attempts_settled |= self.invert_attempts_settled.unwrap_or(false);
+        args.only_problems |= self.only_problems.unwrap_or(false);
+        args.coalesce_identical |= self.coalesce_identical.unwrap_or(false);
+        args.no_settle |= self.no_settle.unwrap_or(false);
+        if let (Some(value), true) = (self.format, unset("format")) {
+            args.format = Some(parsed("format", &value)?);
+        }
+        if let (Some(value), true) = (self.scale, unset("scale")) {
+            args.scale = Some(cli::parse_scale(&value).map_err(|err| format!("scale: {}", err))?);
+        }
+        if let (Some(value), true) = (self.scale_mode, unset("scale_mode")) {
+            args.scale_mode = Some(parsed("scale-mode", &value)?);
+        }
+        args.follow |= self.follow.unwrap_or(false);
+        if let (Some(value), true) = (self.frame_skip, unset("frame_skip")) {
+            args.frame_skip = value;
+        }
+        if let (Some(value), true) = (self.sample_every, unset("sample_every")) {
+            if value == 0 {
+                return Err("sample-every: must not be zero".to_string());
+            }
+            args.sample_every = value;
+        }
+        if let (Some(value), true) = (self.max_output_bytes, unset("max_output_bytes")) {
+            if value == 0 {
+                return Err("max-output-bytes: must not be zero".to_string());
+            }
+            args.max_output_bytes = Some(value);
+        }
+        args.annotate_settle_frame |= self.annotate_settle_frame.unwrap_or(false);
+        args.title_card |= self.title_card.unwrap_or(false);
+        args.deterministic |= self.deterministic.unwrap_or(false);
+        if let (Some(value), true) = (self.addr_style, unset("addr_style")) {
+            args.addr_style = parsed("addr-style", &value)?;
+        }
+        Ok(())
+    }
+}
+
+fn parsed<T: FromStr>(key: &str, value: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|err| format!("{}: bad value ({}): {}", key, value, err))
+}
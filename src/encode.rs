@@ -0,0 +1,734 @@
+use crate::render::{Clock, Options, RenderError, VideoSink};
+use mp4::{
+    AvcConfig, FourCC, MediaConfig, Mp4Config, Mp4Sample, Mp4Writer, TrackConfig, TrackType,
+};
+use openh264::encoder::{EncodedBitStream, Encoder, EncoderConfig};
+use openh264::formats::{RBGYUVConverter, YUVSource};
+use parse_display::FromStr;
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::hash::{Hash, Hasher};
+use std::io::{Seek, Write};
+
+/// Which H.264 profile the video declares. OpenH264 always encodes Constrained Baseline, which
+/// satisfies each of these, so picking another only changes the declaration, for decoders that
+/// insist on one.
+#[derive(FromStr, Debug, Clone, Copy, PartialEq, Eq)]
+#[display(style = "lowercase")]
+pub enum Profile {
+    Baseline,
+    Main,
+    High,
+}
+
+impl Profile {
+    /// The `profile_idc` that declares the profile.
+    fn idc(self) -> u8 {
+        match self {
+            Profile::Baseline => 66,
+            Profile::Main => 77,
+            Profile::High => 100,
+        }
+    }
+}
+
+/// Formats a `level_idc` like levels are usually written, e.g. `3.1` for 31.
+pub fn level_name(level_idc: u8) -> String {
+    format!("{}.{}", level_idc / 10, level_idc % 10)
+}
+
+/// The brand the MP4 declares it's best used with, unless told otherwise.
+pub const DEFAULT_BRAND: [u8; 4] = *b"isom";
+/// The brands the MP4 declares it's compatible with, unless told otherwise.
+pub const DEFAULT_COMPATIBLE_BRANDS: [[u8; 4]; 4] = [*b"isom", *b"iso2", *b"avc1", *b"mp41"];
+
+/// The NAL unit type of sequence parameter sets.
+const NAL_TYPE_SPS: u8 = 7;
+/// The NAL unit type of picture parameter sets.
+const NAL_TYPE_PPS: u8 = 8;
+
+/// Strips the start code off of a NAL unit, as OpenH264 emits them.
+fn nal_payload(nal: &[u8]) -> &[u8] {
+    let start = nal.iter().position(|&byte| byte != 0).unwrap();
+    debug_assert_eq!(nal[start], 1);
+    &nal[start + 1..]
+}
+
+/// Which kind of NAL unit this is, without its start code.
+fn nal_type(payload: &[u8]) -> u8 {
+    payload[0] & 0x1f
+}
+
+/// Removes emulation prevention bytes, turning a NAL unit's payload into its raw bytes.
+fn unescape(payload: &[u8]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(payload.len());
+    let mut nb_zeros = 0;
+    for &byte in payload {
+        if nb_zeros >= 2 && byte == 3 {
+            nb_zeros = 0;
+            continue;
+        }
+        nb_zeros = if byte == 0 { nb_zeros + 1 } else { 0 };
+        raw.push(byte);
+    }
+    raw
+}
+
+/// Inserts emulation prevention bytes, so that raw bytes can't be mistaken for a start code.
+fn escape(raw: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(raw.len() + raw.len() / 16);
+    let mut nb_zeros = 0;
+    for &byte in raw {
+        if nb_zeros >= 2 && byte <= 3 {
+            payload.push(3);
+            nb_zeros = 0;
+        }
+        nb_zeros = if byte == 0 { nb_zeros + 1 } else { 0 };
+        payload.push(byte);
+    }
+    payload
+}
+
+/// Rewrites a sequence parameter set (without its start code) to declare this profile and level.
+fn declare(sps: &[u8], profile: Profile, level_idc: Option<u8>) -> Result<Vec<u8>, RenderError> {
+    // The header byte, then `profile_idc`, the constraint flags, and `level_idc`
+    let mut raw = unescape(sps);
+    if let Some(level_idc) = level_idc {
+        if level_idc < raw[3] {
+            return Err(RenderError::level_too_low(level_idc, raw[3]));
+        }
+        raw[3] = level_idc;
+    }
+    if raw[1] == profile.idc() {
+        return Ok(escape(&raw));
+    }
+    // Constrained Baseline also satisfies Main, which High is a superset of
+    assert_eq!(
+        raw[1],
+        Profile::Baseline.idc(),
+        "OpenH264 emitted an unexpected profile"
+    );
+    raw[1] = profile.idc();
+    if profile == Profile::High {
+        raw = add_high_fields(&raw);
+    }
+    Ok(escape(&raw))
+}
+
+/// Inserts the fields that High profile SPSes have after `seq_parameter_set_id`, as Baseline ones
+/// implicitly have: 4:2:0 chroma, 8-bit samples, and no transform bypass nor scaling matrices.
+fn add_high_fields(raw: &[u8]) -> Vec<u8> {
+    let mut bits: Vec<bool> = raw
+        .iter()
+        .flat_map(|&byte| (0..8).rev().map(move |i| byte >> i & 1 != 0))
+        .collect();
+    // Drop the stop bit and the alignment after it, which are added back at the end
+    let stop = bits.iter().rposition(|&bit| bit).unwrap();
+    bits.truncate(stop);
+
+    // `seq_parameter_set_id` is an Exp-Golomb code, as many zeros as bits after its first one
+    let id_start = 32;
+    let nb_zeros = bits[id_start..].iter().position(|&bit| bit).unwrap();
+    let id_end = id_start + 2 * nb_zeros + 1;
+    // `chroma_format_idc` = 1, both `bit_depth_*_minus8` = 0, and clear flags
+    let fields = [false, true, false, true, true, false, false];
+    bits.splice(id_end..id_end, fields.iter().copied());
+
+    bits.push(true);
+    while !bits.len().is_multiple_of(8) {
+        bits.push(false);
+    }
+    bits.chunks(8)
+        .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+        .collect()
+}
+
+/// Converts RGB to a monochrome YUV frame: only luma is computed, and both chroma planes stay flat.
+struct LumaConverter {
+    yuv: Vec<u8>,
+    width: usize,
+    height: usize,
+}
+
+impl LumaConverter {
+    fn new(width: usize, height: usize) -> Self {
+        let luma_size = width * height;
+        let mut yuv = vec![0; luma_size + luma_size / 2];
+        // Neutral chroma; this never changes, so it only needs to be written once
+        yuv[luma_size..].fill(128);
+        Self { yuv, width, height }
+    }
+
+    fn convert(&mut self, rgb: &[u8]) {
+        assert_eq!(rgb.len(), self.width * self.height * 3);
+
+        for (y, pixel) in self.yuv.iter_mut().zip(rgb.chunks_exact(3)) {
+            let luma =
+                66 * u32::from(pixel[0]) + 129 * u32::from(pixel[1]) + 25 * u32::from(pixel[2]);
+            *y = ((luma + 128) / 256 + 16) as u8;
+        }
+    }
+}
+
+impl YUVSource for LumaConverter {
+    fn width(&self) -> i32 {
+        self.width.try_into().unwrap()
+    }
+
+    fn height(&self) -> i32 {
+        self.height.try_into().unwrap()
+    }
+
+    fn y(&self) -> &[u8] {
+        &self.yuv[..self.width * self.height]
+    }
+
+    fn u(&self) -> &[u8] {
+        let luma_size = self.width * self.height;
+        &self.yuv[luma_size..luma_size + luma_size / 4]
+    }
+
+    fn v(&self) -> &[u8] {
+        let luma_size = self.width * self.height;
+        &self.yuv[luma_size + luma_size / 4..]
+    }
+
+    fn y_stride(&self) -> i32 {
+        self.width()
+    }
+
+    fn u_stride(&self) -> i32 {
+        self.width() / 2
+    }
+
+    fn v_stride(&self) -> i32 {
+        self.width() / 2
+    }
+}
+
+/// Gives frames the look of a CRT screen: every other row is darkened like a scanline, and red and
+/// blue are shifted a pixel apart, to either side of green.
+struct CrtFilter {
+    rgb: Vec<u8>,
+    width: usize,
+}
+
+impl CrtFilter {
+    /// How bright scanlines are kept, out of 256.
+    const SCANLINE_LEVEL: u16 = 176;
+
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            rgb: vec![0; width * height * 3],
+            width,
+        }
+    }
+
+    fn apply(&mut self, rgb: &[u8]) -> &[u8] {
+        assert_eq!(rgb.len(), self.rgb.len());
+
+        let stride = self.width * 3;
+        let rows = self
+            .rgb
+            .chunks_exact_mut(stride)
+            .zip(rgb.chunks_exact(stride));
+        for (y, (out, row)) in rows.enumerate() {
+            for (x, pixel) in out.chunks_exact_mut(3).enumerate() {
+                pixel[0] = row[x.saturating_sub(1) * 3];
+                pixel[1] = row[x * 3 + 1];
+                pixel[2] = row[cmp::min(x + 1, self.width - 1) * 3 + 2];
+                if y % 2 == 1 {
+                    for channel in pixel {
+                        *channel = (u16::from(*channel) * Self::SCANLINE_LEVEL / 256) as u8;
+                    }
+                }
+            }
+        }
+        &self.rgb
+    }
+}
+
+/// Runs frames through the CRT filter if there is one, and passes them through unchanged otherwise.
+fn filtered<'p>(crt: &'p mut Option<CrtFilter>, pixels: &'p [u8]) -> &'p [u8] {
+    match crt {
+        Some(crt) => crt.apply(pixels),
+        None => pixels,
+    }
+}
+
+enum YUVConverter {
+    Color(RBGYUVConverter),
+    Grayscale(LumaConverter),
+}
+
+impl YUVConverter {
+    fn new(width: usize, height: usize, grayscale: bool) -> Self {
+        if grayscale {
+            Self::Grayscale(LumaConverter::new(width, height))
+        } else {
+            Self::Color(RBGYUVConverter::new(width, height))
+        }
+    }
+
+    fn convert(&mut self, rgb: &[u8]) {
+        match self {
+            Self::Color(converter) => converter.convert(rgb),
+            Self::Grayscale(converter) => converter.convert(rgb),
+        }
+    }
+
+    fn source(&self) -> &dyn YUVSource {
+        match self {
+            Self::Color(converter) => converter,
+            Self::Grayscale(converter) => converter,
+        }
+    }
+}
+
+impl YUVSource for YUVConverter {
+    fn width(&self) -> i32 {
+        self.source().width()
+    }
+
+    fn height(&self) -> i32 {
+        self.source().height()
+    }
+
+    fn y(&self) -> &[u8] {
+        self.source().y()
+    }
+
+    fn u(&self) -> &[u8] {
+        self.source().u()
+    }
+
+    fn v(&self) -> &[u8] {
+        self.source().v()
+    }
+
+    fn y_stride(&self) -> i32 {
+        self.source().y_stride()
+    }
+
+    fn u_stride(&self) -> i32 {
+        self.source().u_stride()
+    }
+
+    fn v_stride(&self) -> i32 {
+        self.source().v_stride()
+    }
+}
+
+/// Encoding restarts from scratch every this many frames, which makes these good points to resume
+/// encoding from.
+pub const RESTART_INTERVAL: usize = OneSecEncoder::NB_FRAMES as usize;
+
+struct OneSecEncoder {
+    config: EncoderConfig,
+    encoder: Encoder,
+    encodes: u8,
+}
+
+impl OneSecEncoder {
+    /// How many frames are encoded before the encoder is reset.
+    const NB_FRAMES: u8 = 60;
+
+    fn new(config: EncoderConfig) -> Result<Self, openh264::Error> {
+        Ok(Self {
+            config,
+            encoder: Encoder::with_config(config)?,
+            encodes: 0,
+        })
+    }
+
+    fn encode<T: YUVSource>(
+        &mut self,
+        source: &T,
+    ) -> Result<EncodedBitStream<'_>, openh264::Error> {
+        if self.encodes == Self::NB_FRAMES {
+            self.encodes = 0;
+            self.encoder = Encoder::with_config(self.config)?;
+        }
+
+        let encoded = self.encoder.encode(source);
+
+        self.encodes += 1;
+        encoded
+    }
+
+    /// Whether the next frame encoded will be a keyframe, which doesn't depend on any other.
+    fn at_keyframe(&self) -> bool {
+        self.encodes == 0 || self.encodes == Self::NB_FRAMES
+    }
+
+    /// Makes the next frame encoded a keyframe, as the one before it wasn't encoded by this
+    /// encoder, so it can't be predicted from.
+    fn restart(&mut self) {
+        self.encodes = Self::NB_FRAMES;
+    }
+}
+
+/// Keyframes encoded so far, by a hash of their pixels, so that frames drawn exactly like one of
+/// them can reuse it instead of being encoded; other frames depend on the one before them, so
+/// they can't be reused elsewhere.
+///
+/// As the encoder can't predict the frame after a reused one, that frame must be a keyframe too,
+/// which makes the video bigger; so only frames that would be keyframes anyway reuse any, and
+/// only if they differ from the frame before them, as runs of identical frames otherwise take
+/// next to nothing.
+struct FrameCache {
+    /// How many keyframes are kept, at most; the least recently used one makes room for others.
+    capacity: usize,
+    /// The encoded keyframes, each with when it was last used.
+    entries: HashMap<u64, (Vec<u8>, u64)>,
+    /// The key of the last frame encoded.
+    last_key: Option<u64>,
+    /// How many keyframes were looked up so far, which dates the uses.
+    nb_lookups: u64,
+    nb_hits: u64,
+}
+
+impl FrameCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            last_key: None,
+            nb_lookups: 0,
+            nb_hits: 0,
+        }
+    }
+
+    /// The frame's key, and whether it differs from the last frame's.
+    fn key(&mut self, pixels: &[u8]) -> (u64, bool) {
+        let mut hasher = DefaultHasher::new();
+        pixels.hash(&mut hasher);
+        let key = hasher.finish();
+        (key, self.last_key.replace(key) != Some(key))
+    }
+
+    fn get(&mut self, key: u64) -> Option<Vec<u8>> {
+        self.nb_lookups += 1;
+        let (bytes, last_used) = self.entries.get_mut(&key)?;
+        *last_used = self.nb_lookups;
+        self.nb_hits += 1;
+        Some(bytes.clone())
+    }
+
+    fn insert(&mut self, key: u64, bytes: &[u8]) {
+        if self.entries.len() == self.capacity {
+            let oldest = *self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| last_used)
+                .unwrap()
+                .0;
+            self.entries.remove(&oldest);
+        }
+        self.entries.insert(key, (bytes.to_vec(), self.nb_lookups));
+    }
+}
+
+/// Encodes frames into an H.264 video track, stored in an MP4 container.
+pub struct Mp4Sink<W: Write + Seek> {
+    writer: Mp4Writer<W>,
+    encoder: OneSecEncoder,
+    yuv: YUVConverter,
+    crt: Option<CrtFilter>,
+    /// The sequence parameter set the track declares, which replaces those of the stream, if it
+    /// declares another profile or level than the encoder picked.
+    sps: Option<Vec<u8>>,
+    clock: Clock,
+    cache: Option<FrameCache>,
+    /// Whether the last frame encoded is a keyframe.
+    is_keyframe: bool,
+    /// How many samples have been written so far.
+    nb_samples: u64,
+    /// How many bytes they take.
+    nb_bytes: u64,
+}
+
+impl<W: Write + Seek> Mp4Sink<W> {
+    pub fn new(
+        out: W,
+        width: u32,
+        height: u32,
+        clock: Clock,
+        options: &Options,
+    ) -> Result<Self, RenderError> {
+        let config = EncoderConfig::new(width, height);
+        let encoder = OneSecEncoder::new(config)?;
+        let mut yuv = YUVConverter::new(
+            width.try_into().unwrap(),
+            height.try_into().unwrap(),
+            options.grayscale,
+        );
+
+        // The track must declare the parameter sets of the stream, which only the encoder knows;
+        // they don't depend on the frames, so they're taken from a throwaway blank one
+        let (mut sps, mut pps) = (None, None);
+        yuv.convert(&vec![0; (width * height * 3).try_into().unwrap()]);
+        let mut scratch = Encoder::with_config(config)?;
+        let encoded = scratch.encode(&yuv)?;
+        for layer in (0..encoded.num_layers()).filter_map(|i| encoded.layer(i)) {
+            for nal in (0..layer.nal_count()).filter_map(|i| layer.nal_unit(i)) {
+                let payload = nal_payload(nal);
+                match nal_type(payload) {
+                    NAL_TYPE_SPS => sps = Some(payload.to_vec()),
+                    NAL_TYPE_PPS => pps = Some(payload.to_vec()),
+                    _ => {}
+                }
+            }
+        }
+        let (sps, pps) = (sps.unwrap(), pps.unwrap());
+        let declared_sps = declare(&sps, options.profile, options.level)?;
+
+        // The movie and the track must share the timescale, as players compute the video's
+        // duration with the former, but the track's timing with the latter
+        let timescale = clock.timescale;
+
+        let fcc = |code: &[u8; 4]| FourCC { value: *code };
+        let mut writer = Mp4Writer::write_start(
+            out,
+            &Mp4Config {
+                major_brand: fcc(&options.mp4_brand),
+                minor_version: 512,
+                compatible_brands: options.mp4_compatible_brands.iter().map(fcc).collect(),
+                timescale,
+            },
+        )?;
+
+        writer.add_track(&TrackConfig {
+            track_type: TrackType::Video,
+            timescale,
+            language: "eng".to_string(), // No real language so to speak...
+            media_conf: MediaConfig::AvcConfig(AvcConfig {
+                width: width.try_into().unwrap(),
+                height: height.try_into().unwrap(),
+                // This is where the declared profile and level are taken from
+                seq_param_set: declared_sps.clone(),
+                pic_param_set: pps,
+            }),
+        })?;
+
+        Ok(Self {
+            writer,
+            encoder,
+            yuv,
+            crt: options
+                .crt
+                .then(|| CrtFilter::new(width.try_into().unwrap(), height.try_into().unwrap())),
+            sps: (declared_sps != sps).then_some(declared_sps),
+            clock,
+            cache: (options.frame_cache != 0).then(|| FrameCache::new(options.frame_cache)),
+            is_keyframe: false,
+            nb_samples: 0,
+            nb_bytes: 0,
+        })
+    }
+
+    pub fn encode(&mut self, pixels: &[u8]) -> Result<Vec<u8>, RenderError> {
+        self.is_keyframe = self.encoder.at_keyframe();
+        let key = match &mut self.cache {
+            Some(cache) => {
+                let (key, changed) = cache.key(pixels);
+                if self.is_keyframe && changed {
+                    if let Some(bytes) = cache.get(key) {
+                        self.encoder.restart();
+                        return Ok(bytes);
+                    }
+                }
+                Some(key)
+            }
+            None => None,
+        };
+        let bytes = self.encode_uncached(pixels)?;
+        if let (Some(cache), Some(key), true) = (&mut self.cache, key, self.is_keyframe) {
+            cache.insert(key, &bytes);
+        }
+        Ok(bytes)
+    }
+
+    fn encode_uncached(&mut self, pixels: &[u8]) -> Result<Vec<u8>, RenderError> {
+        self.yuv.convert(filtered(&mut self.crt, pixels));
+
+        let mut bytes = vec![];
+        let encoded = self.encoder.encode(&self.yuv)?;
+        match &self.sps {
+            None => encoded.write_vec(&mut bytes),
+            // The stream's parameter sets must match the track's
+            Some(sps) => {
+                for layer in (0..encoded.num_layers()).filter_map(|i| encoded.layer(i)) {
+                    for nal in (0..layer.nal_count()).filter_map(|i| layer.nal_unit(i)) {
+                        if nal_type(nal_payload(nal)) == NAL_TYPE_SPS {
+                            bytes.extend_from_slice(&[0, 0, 0, 1]);
+                            bytes.extend_from_slice(sps);
+                        } else {
+                            bytes.extend_from_slice(nal);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Writes an already-encoded frame, right after the previous one.
+    pub fn write_sample(&mut self, bytes: Vec<u8>) -> Result<(), RenderError> {
+        // Past 64 bits of ticks, samples would start over from 0, or never end
+        let start = |nb_samples: Option<u64>| {
+            nb_samples
+                .and_then(|nb_samples| self.clock.checked_start(nb_samples))
+                .ok_or_else(RenderError::too_long)
+        };
+        let start_time = start(Some(self.nb_samples))?;
+        let end_time = start(self.nb_samples.checked_add(1))?;
+        let duration = (end_time - start_time)
+            .try_into()
+            .map_err(|_| RenderError::too_long())?;
+        self.nb_samples += 1;
+        self.nb_bytes += bytes.len() as u64;
+        self.writer.write_sample(
+            1,
+            &Mp4Sample {
+                start_time,
+                duration,
+                rendering_offset: 0,
+                is_sync: true,
+                bytes: bytes.into(),
+            },
+        )?;
+        Ok(())
+    }
+
+    /// How many bytes the samples written so far take, which is most of the file.
+    pub fn nb_bytes(&self) -> u64 {
+        self.nb_bytes
+    }
+
+    /// Whether the last frame encoded is a keyframe.
+    pub fn is_keyframe(&self) -> bool {
+        self.is_keyframe
+    }
+
+    /// With `--frame-cache`, how many keyframes were reused, out of how many were looked up.
+    pub fn cache_hits(&self) -> Option<(u64, u64)> {
+        self.cache
+            .as_ref()
+            .map(|cache| (cache.nb_hits, cache.nb_lookups))
+    }
+}
+
+impl<W: Write + Seek> VideoSink for Mp4Sink<W> {
+    fn write_frame(&mut self, pixels: &[u8]) -> Result<(), RenderError> {
+        let bytes = self.encode(pixels)?;
+        self.write_sample(bytes)
+    }
+
+    fn finish(mut self) -> Result<(), RenderError> {
+        self.writer.write_end()?;
+        Ok(())
+    }
+}
+
+/// Encodes frames like `Mp4Sink`, but throws the result away, to time rendering without any I/O.
+pub struct NullSink {
+    encoder: OneSecEncoder,
+    yuv: YUVConverter,
+    crt: Option<CrtFilter>,
+}
+
+impl NullSink {
+    pub fn new(width: u32, height: u32, options: &Options) -> Result<Self, RenderError> {
+        Ok(Self {
+            encoder: OneSecEncoder::new(EncoderConfig::new(width, height))?,
+            yuv: YUVConverter::new(
+                width.try_into().unwrap(),
+                height.try_into().unwrap(),
+                options.grayscale,
+            ),
+            crt: options
+                .crt
+                .then(|| CrtFilter::new(width.try_into().unwrap(), height.try_into().unwrap())),
+        })
+    }
+}
+
+impl VideoSink for NullSink {
+    fn write_frame(&mut self, pixels: &[u8]) -> Result<(), RenderError> {
+        self.yuv.convert(filtered(&mut self.crt, pixels));
+
+        let mut bytes = vec![];
+        self.encoder.encode(&self.yuv)?.write_vec(&mut bytes);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), RenderError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::RenderErrorKind;
+    use std::io::Cursor;
+
+    fn sink(options: &Options, nb_frames: usize) -> Mp4Sink<Cursor<Vec<u8>>> {
+        let clock = Clock::counted(options, nb_frames);
+        Mp4Sink::new(Cursor::new(Vec::new()), 16, 16, clock, options).unwrap()
+    }
+
+    #[test]
+    fn frame_cache_reuses_keyframes() {
+        let options = Options {
+            frame_cache: 4,
+            ..Options::default()
+        };
+        let mut sink = sink(&options, 0);
+        let (black, white) = (vec![0; 16 * 16 * 3], vec![255; 16 * 16 * 3]);
+
+        let first = sink.encode(&black).unwrap();
+        for _ in 1..RESTART_INTERVAL {
+            sink.encode(&white).unwrap();
+            assert!(!sink.is_keyframe());
+        }
+        // Back to black, right when the encoder would restart
+        assert!(sink.encode(&black).unwrap() == first);
+        // The encoder didn't see that frame, so it can't predict the next one from it
+        sink.encode(&black).unwrap();
+        assert!(sink.is_keyframe());
+        assert_eq!(sink.cache_hits(), Some((1, 2)));
+    }
+
+    fn is_too_long(result: Result<(), RenderError>) -> bool {
+        matches!(result, Err(err) if matches!(err.kind(), RenderErrorKind::TooLong))
+    }
+
+    #[test]
+    fn samples_past_64_bits_of_ticks_are_too_long() {
+        let mut sink = sink(&Options::default(), 0);
+        // The last sample that ends within 64 bits
+        sink.nb_samples = u64::MAX - 1;
+        assert!(sink.write_sample(vec![]).is_ok());
+        assert!(is_too_long(sink.write_sample(vec![])));
+        assert_eq!(sink.nb_samples, u64::MAX);
+    }
+
+    #[test]
+    fn samples_past_32_bits_of_duration_are_too_long() {
+        // A single frame lasting over a day is more ticks than its duration may take
+        let options = Options {
+            duration: Some(100_000.0),
+            ..Options::default()
+        };
+        assert!(is_too_long(sink(&options, 1).write_sample(vec![])));
+        let options = Options {
+            duration: Some(10_000.0),
+            ..Options::default()
+        };
+        assert!(sink(&options, 1).write_sample(vec![]).is_ok());
+    }
+}
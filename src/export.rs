@@ -0,0 +1,510 @@
+//! Exporting the parsed data, for use by other tools.
+
+use crate::render::{Clock, Frames, Options, Orientation, Shown};
+use crate::{AddrStyle, FrameKind, Location, Section, Sequence};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::ptr;
+
+/// Writes the sequence as JSON, along with the layout it would be rendered with, so that it can
+/// be reproduced exactly without re-deriving anything.
+pub fn export_json(sequence: &Sequence, options: &Options, path: &Path) -> io::Result<()> {
+    eprint!("Exporting JSON...\r");
+
+    let sections: Vec<Value> = sequence
+        .sections
+        .iter()
+        .map(|section| {
+            json!({
+                "type": section.mem_type.to_string(),
+                "region": section.mem_type.region().to_string(),
+                // Floating positions are reported as `null`, instead of using magic values
+                "bank": (!section.is_floating_bank()).then_some(section.location.bank),
+                "addr": (!section.is_floating()).then_some(section.location.addr),
+                "align_mask": section.align_mask,
+                "align_ofs": section.align_ofs,
+                "size": section.size,
+                "name": section.name,
+            })
+        })
+        .collect();
+
+    let frames: Vec<Value> = sequence
+        .frames
+        .iter()
+        .map(|frame| {
+            json!({
+                "kind": match frame.kind {
+                    FrameKind::Attempt => "attempt",
+                    FrameKind::Placement => "placement",
+                    FrameKind::Removal => "removal",
+                },
+                "section": frame.section_id,
+                "bank": frame.location.bank,
+                "addr": frame.location.addr,
+            })
+        })
+        .collect();
+
+    let nb_banks: serde_json::Map<String, Value> = crate::Region::ALL
+        .iter()
+        .map(|&region| (region.to_string(), sequence.nb_banks(region).into()))
+        .collect();
+
+    let layout = Frames::new(sequence, options).layout();
+    let regions: Vec<Value> = layout
+        .regions
+        .iter()
+        .map(|group| {
+            json!({
+                "region": group.region.to_string(),
+                "panel": group.panel,
+                "nb_banks": group.nb_banks,
+                "banks": group.banks,
+                "bank_size": group.region.bank_size(),
+                "nb_rows": group.nb_rows,
+                "nb_columns": group.nb_columns,
+                "x": group.x,
+                "bank_height": group.bank_height,
+                "label_height": group.label_height,
+            })
+        })
+        .collect();
+
+    let out = json!({
+        "nb_banks": nb_banks,
+        "has_placements": sequence.has_placements,
+        "sections": sections,
+        "frames": frames,
+        "layout": {
+            "width": layout.width,
+            "height": layout.height,
+            "orientation": match layout.orientation {
+                Orientation::Vertical => "vertical",
+                Orientation::Horizontal => "horizontal",
+            },
+            "bank_width": layout.bank_width,
+            "spacer_width": layout.spacer_width,
+            "region_separator_width": layout.region_separator_width,
+            "regions": regions,
+        },
+    });
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut writer, &out)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+
+    eprintln!("Exporting JSON - Done.");
+    Ok(())
+}
+
+/// Writes WebVTT subtitles naming the section being placed at each point of the video.
+pub fn export_vtt(sequence: &Sequence, options: &Options, path: &Path) -> io::Result<()> {
+    eprint!("Exporting subtitles...\r");
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "WEBVTT")?;
+
+    // Timestamps are `HH:MM:SS.mmm`
+    let clock = Clock::new(sequence, options);
+    let timestamp = |nb_frames: u64| {
+        let ms = clock.start_in(nb_frames, 1000);
+        format!(
+            "{:02}:{:02}:{:02}.{:03}",
+            ms / 3_600_000,
+            ms / 60_000 % 60,
+            ms / 1000 % 60,
+            ms % 1000
+        )
+    };
+    let mut write_cue = |section: &Section, start: u64, end: u64| {
+        // Cue text may contain markup, which section names are not meant as
+        let name = section
+            .name
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        write!(
+            writer,
+            "\n{} --> {}\nSection: {}\n",
+            timestamp(start),
+            timestamp(end),
+            name
+        )
+    };
+
+    // Each cue spans all consecutive frames showing the same section
+    let mut frames = Frames::new(sequence, options);
+    let mut current: Option<(&Section, u64)> = None;
+    let mut nb_frames = 0;
+    while let Some(section) = frames.skip_frame() {
+        match (current, section) {
+            (Some((prev, _)), Some(section)) if ptr::eq(prev, section) => {}
+            _ => {
+                if let Some((prev, start)) = current {
+                    write_cue(prev, start, nb_frames)?;
+                }
+                current = section.map(|section| (section, nb_frames));
+            }
+        }
+        nb_frames += 1;
+    }
+    if let Some((prev, start)) = current {
+        write_cue(prev, start, nb_frames)?;
+    }
+    writer.flush()?;
+
+    eprintln!("Exporting subtitles - Done.");
+    Ok(())
+}
+
+/// Writes the sections as a timeline in Chrome's trace event format, for `chrome://tracing` or
+/// Perfetto: each region is a process, each bank a thread, and each section a slice spanning from
+/// its first attempt shown to when it's shown settled.
+pub fn export_trace_json(
+    sequence: &Sequence,
+    options: &Options,
+    addr_style: AddrStyle,
+    path: &Path,
+) -> io::Result<()> {
+    eprint!("Exporting trace...\r");
+
+    let settling = settling_frames(sequence);
+    let (attempts, settled) = shown_frames(sequence, options, &settling);
+    let nb_frames = Frames::count(sequence, options);
+    // Timestamps are in microseconds
+    let clock = Clock::counted(options, nb_frames);
+    let nb_frames = nb_frames as u64;
+    let timestamp = |nb_frames: u64| clock.start_in(nb_frames, 1_000_000);
+
+    let mut events = Vec::new();
+    let mut banks = BTreeSet::new();
+    let placements = sequence.final_placements();
+    for (section_id, section) in sequence.sections.iter().enumerate() {
+        // Sections that are never shown, or never settled, don't span anything
+        let (first, location) = match (attempts[section_id], placements[section_id]) {
+            (Some((first, _)), Some(location)) => (first, location),
+            _ => continue,
+        };
+        // Sections settling after the last frame last until the end
+        let end = settled[section_id].unwrap_or(nb_frames);
+        let region = section.mem_type.region();
+        banks.insert((region, location.bank));
+        events.push(json!({
+            "name": section.name,
+            "cat": section.mem_type.to_string(),
+            "ph": "X",
+            "pid": region as usize,
+            "tid": location.bank,
+            "ts": timestamp(first),
+            "dur": timestamp(end) - timestamp(first),
+            "args": {
+                "location": section.format_location(location, addr_style),
+                "size": section.size,
+                "first_frame": first,
+                "settled_frame": end,
+            },
+        }));
+    }
+    // Name the tracks, which would otherwise only be numbers
+    let mut last_region = None;
+    for (region, bank) in banks {
+        if last_region != Some(region) {
+            last_region = Some(region);
+            events.push(json!({
+                "name": "process_name",
+                "ph": "M",
+                "pid": region as usize,
+                "args": { "name": region.to_string().to_uppercase() },
+            }));
+        }
+        events.push(json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": region as usize,
+            "tid": bank,
+            "args": { "name": format!("Bank {:02x}", bank) },
+        }));
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(
+        &mut writer,
+        &json!({ "traceEvents": events, "displayTimeUnit": "ms" }),
+    )?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+
+    eprintln!("Exporting trace - Done.");
+    Ok(())
+}
+
+/// Which of the sequence's frames settles each section, if any.
+fn settling_frames(sequence: &Sequence) -> Vec<Option<usize>> {
+    let mut settling = vec![None; sequence.sections.len()];
+    for (i, frame) in sequence.frames.iter().enumerate() {
+        match frame.kind {
+            FrameKind::Placement => settling[frame.section_id] = Some(i),
+            FrameKind::Attempt if !sequence.has_placements => settling[frame.section_id] = Some(i),
+            _ => {}
+        }
+    }
+    settling
+}
+
+/// Writes a table of when each section is shown in the video: the first and last frames showing
+/// it being attempted, the frame from which it's shown settled, and where it ends up.
+pub fn export_index(
+    sequence: &Sequence,
+    options: &Options,
+    addr_style: AddrStyle,
+    path: &Path,
+) -> io::Result<()> {
+    eprint!("Exporting index...\r");
+
+    let settling = settling_frames(sequence);
+    let (attempts, settled) = shown_frames(sequence, options, &settling);
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(
+        writer,
+        "Name\tFirst attempt\tLast attempt\tSettled\tLocation"
+    )?;
+    let placements = sequence.final_placements();
+    for (section_id, section) in sequence.sections.iter().enumerate() {
+        let (first, last) = match attempts[section_id] {
+            Some((first, last)) => (first.to_string(), last.to_string()),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        // Sections settling after the last frame are never shown settled
+        let settled = match (settled[section_id], settling[section_id]) {
+            (Some(frame), _) => frame.to_string(),
+            (None, Some(_)) => "end".to_string(),
+            (None, None) => "-".to_string(),
+        };
+        let location = match placements[section_id] {
+            Some(location) => section.format_location(location, addr_style),
+            None => "-".to_string(),
+        };
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}",
+            section.name, first, last, settled, location
+        )?;
+    }
+    writer.flush()?;
+
+    eprintln!("Exporting index - Done.");
+    Ok(())
+}
+
+/// Reports each time a section is shown attempted over a `--watch-range`, and where sections end
+/// up settled over one, along with the frame of the output that shows it.
+pub fn report_watched(sequence: &Sequence, options: &Options, addr_style: AddrStyle) {
+    let watched = |section: &Section, location: &Location| {
+        options
+            .watch_ranges
+            .iter()
+            .find(|range| range.overlaps(section, location))
+    };
+    let mut events = Vec::new();
+    let mut push = |frame: Option<u64>, section: &Section, location, what| {
+        if let Some(range) = watched(section, location) {
+            let line = format!(
+                "{} {} at {}, over {}",
+                section.name,
+                what,
+                section.format_location(location, addr_style),
+                range
+            );
+            events.push((frame, line));
+        }
+    };
+
+    let mut frames = Frames::new(sequence, options);
+    let mut nb_frames = 0;
+    while let Some(shown) = frames.skip_shown() {
+        if let Shown::Frame(_, frame) = shown {
+            push(
+                Some(nb_frames),
+                &sequence.sections[frame.section_id],
+                &frame.location,
+                "attempted",
+            );
+        }
+        nb_frames += 1;
+    }
+    let settled = settled_frames(sequence, options);
+    for (section_id, location) in sequence.final_placements().into_iter().enumerate() {
+        let section = &sequence.sections[section_id];
+        if let (Some(location), true) = (location, frames.is_rendered(section)) {
+            push(settled[section_id], section, location, "settled");
+        }
+    }
+
+    // Sections settled after the last frame are never shown so, thus they come last
+    events.sort_by_key(|&(frame, _)| frame.unwrap_or(u64::MAX));
+    for (frame, line) in events {
+        match frame {
+            Some(frame) => eprintln!("Frame {}: {}", frame, line),
+            None => eprintln!("End: {}", line),
+        }
+    }
+}
+
+/// The frame of the output from which each section is shown settled, if it is before the end.
+pub fn settled_frames(sequence: &Sequence, options: &Options) -> Vec<Option<u64>> {
+    shown_frames(sequence, options, &settling_frames(sequence)).1
+}
+
+/// Goes through the output's frames to find, for each section, the first and last ones showing it
+/// being attempted, and the first one showing it settled, if any.
+#[allow(clippy::type_complexity)]
+fn shown_frames(
+    sequence: &Sequence,
+    options: &Options,
+    settling: &[Option<usize>],
+) -> (Vec<Option<(u64, u64)>>, Vec<Option<u64>>) {
+    let mut attempts: Vec<Option<(u64, u64)>> = vec![None; sequence.sections.len()];
+    let mut settled = vec![None; sequence.sections.len()];
+    // Sections by when they settle, so that they can be checked off as frames go by
+    let mut pending: Vec<_> = settling
+        .iter()
+        .enumerate()
+        .filter_map(|(section_id, &i)| Some((i?, section_id)))
+        .collect();
+    pending.sort_unstable();
+    let mut pending = pending.into_iter().peekable();
+
+    let mut frames = Frames::new(sequence, options);
+    let mut nb_frames = 0;
+    while let Some(shown) = frames.skip_shown() {
+        if let Shown::Frame(i, frame) = shown {
+            // Sections are settled by the time a later frame is shown
+            while let Some((_, section_id)) = pending.next_if(|&(at, _)| at < i) {
+                settled[section_id] = Some(nb_frames);
+            }
+            let range = attempts[frame.section_id].get_or_insert((nb_frames, nb_frames));
+            range.1 = nb_frames;
+        }
+        nb_frames += 1;
+    }
+    (attempts, settled)
+}
+
+/// How many samples per second the click track has.
+pub const CLICK_TRACK_RATE: u32 = 48000;
+
+/// 16-bit mono PCM audio as long as the video, silent except for a click whenever sections get
+/// settled, to be added to it as its audio track.
+pub fn click_track(sequence: &Sequence, options: &Options) -> Vec<i16> {
+    /// How long each click lasts, in samples.
+    const CLICK_LEN: usize = CLICK_TRACK_RATE as usize / 200;
+
+    let mut pending: Vec<_> = settling_frames(sequence).into_iter().flatten().collect();
+    pending.sort_unstable();
+    let mut pending = pending.into_iter().peekable();
+
+    // Which frames of the output show sections having just been settled
+    let mut clicks = Vec::new();
+    let mut frames = Frames::new(sequence, options);
+    let mut nb_frames = 0;
+    while let Some(shown) = frames.skip_shown() {
+        let i = match shown {
+            Shown::Frame(i, _) | Shown::Grow(i, _) | Shown::Flash(i, _) | Shown::Settled(i, _) => i,
+            Shown::Title => 0,
+        };
+        // Several sections settling at once still make a single click
+        let mut nb_settled = 0;
+        while pending.next_if(|&at| at < i).is_some() {
+            nb_settled += 1;
+        }
+        if nb_settled != 0 {
+            clicks.push(nb_frames);
+        }
+        nb_frames += 1;
+    }
+    // Sections settling after the last frame are never shown settled, so click on the last frame
+    if pending.next().is_some() && nb_frames != 0 {
+        clicks.push(nb_frames - 1);
+    }
+
+    let clock = Clock::new(sequence, options);
+    let to_sample =
+        |nb_frames: u64| usize::try_from(clock.start_in(nb_frames, CLICK_TRACK_RATE)).unwrap();
+    let mut samples = vec![0_i16; to_sample(nb_frames)];
+    for frame in clicks {
+        let start = to_sample(frame);
+        // A short, quickly-decaying 2 kHz blip
+        for (i, sample) in samples.iter_mut().skip(start).take(CLICK_LEN).enumerate() {
+            let t = i as f32 / CLICK_TRACK_RATE as f32;
+            let envelope = (-(i as f32) / (CLICK_LEN as f32 / 5.0)).exp();
+            *sample = (f32::sin(t * 2000.0 * std::f32::consts::TAU) * envelope * 24000.0) as i16;
+        }
+    }
+
+    samples
+}
+
+/// Writes a Graphviz graph of the sections, grouped by the bank they end up in, with an edge
+/// between each pair sharing a bank; the closer they are, the heavier the edge.
+pub fn export_dot(sequence: &Sequence, path: &Path) -> io::Result<()> {
+    eprint!("Exporting graph...\r");
+
+    // Each bank's sections, sorted by where they start
+    let mut banks: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for (section_id, location) in sequence.final_placements().into_iter().enumerate() {
+        if let Some(location) = location {
+            let section = &sequence.sections[section_id];
+            let start = u32::from(location.bank_offset(&section.mem_type));
+            banks
+                .entry((section.mem_type.region(), location.bank))
+                .or_default()
+                .push((start, start.saturating_add(section.size), section_id));
+        }
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "graph sections {{")?;
+    for ((region, bank), sections) in &mut banks {
+        sections.sort_unstable();
+        writeln!(writer, "\tsubgraph \"cluster_{}_{}\" {{", region, bank)?;
+        writeln!(writer, "\t\tlabel = \"{} bank {}\";", region, bank)?;
+        for &(_, _, section_id) in sections.iter() {
+            writeln!(
+                writer,
+                "\t\ts{} [label = \"{}\"];",
+                section_id,
+                dot_escape(&sequence.sections[section_id].name)
+            )?;
+        }
+        for (i, &(_, end, section_id)) in sections.iter().enumerate() {
+            for &(start, _, other_id) in &sections[i + 1..] {
+                // Overlapping sections are as close as it gets
+                let gap = start.saturating_sub(end);
+                let weight = u32::from(region.bank_size()).saturating_sub(gap).max(1);
+                writeln!(
+                    writer,
+                    "\t\ts{} -- s{} [weight = {}];",
+                    section_id, other_id, weight
+                )?;
+            }
+        }
+        writeln!(writer, "\t}}")?;
+    }
+    writeln!(writer, "}}")?;
+    writer.flush()?;
+
+    eprintln!("Exporting graph - Done.");
+    Ok(())
+}
+
+/// Escapes text to be put in a DOT string.
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
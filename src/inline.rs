@@ -0,0 +1,70 @@
+//! Input passed inline, for web frontends, which can't easily hand over files: the log is
+//! optionally gzipped, then base64-encoded.
+
+use flate2::read::MultiGzDecoder;
+use std::io::Read;
+
+/// The first bytes of any gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Decodes the log from its inline form.
+pub fn decode(text: &str) -> Result<Vec<u8>, String> {
+    let data = decode_base64(text)?;
+    if !data.starts_with(&GZIP_MAGIC) {
+        return Ok(data);
+    }
+
+    let mut log = Vec::new();
+    MultiGzDecoder::new(&data[..])
+        .read_to_end(&mut log)
+        .map_err(|err| format!("Bad gzip data: {}", err))?;
+    Ok(log)
+}
+
+/// Decodes standard base64 (with `+` and `/`); whitespace, such as line breaks, is ignored, and
+/// the trailing padding is optional.
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut bits, mut nb_bits) = (0_u32, 0);
+    let (mut nb_digits, mut nb_padding) = (0, 0);
+
+    for (ofs, c) in text.char_indices() {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            '=' => {
+                nb_padding += 1;
+                continue;
+            }
+            _ if c.is_ascii_whitespace() => continue,
+            _ => return Err(format!("Bad base64: unexpected {:?} at offset {}", c, ofs)),
+        };
+        if nb_padding != 0 {
+            return Err(format!(
+                "Bad base64: {:?} after padding at offset {}",
+                c, ofs
+            ));
+        }
+
+        bits = bits << 6 | value;
+        nb_bits += 6;
+        if nb_bits >= 8 {
+            nb_bits -= 8;
+            data.push((bits >> nb_bits) as u8);
+            bits &= (1 << nb_bits) - 1;
+        }
+        nb_digits += 1;
+    }
+
+    // Each group of 4 digits encodes 3 bytes, so a lone digit can't encode anything
+    if nb_digits % 4 == 1 {
+        return Err("Bad base64: truncated".to_string());
+    }
+    if nb_padding != 0 && (nb_padding > 2 || (nb_digits + nb_padding) % 4 != 0) {
+        return Err(format!("Bad base64: {} padding character(s)", nb_padding));
+    }
+    Ok(data)
+}
@@ -0,0 +1,402 @@
+//! Renders how RGBLINK packs sections into banks, from the log of its placement attempts.
+//!
+//! The `gb-packing-visualizer` binary is a command-line front-end to this; the log can also be
+//! parsed and rendered from other programs:
+//!
+//! ```
+//! use gb_packing_visualizer::{parse, render};
+//! use std::io::Cursor;
+//!
+//! let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n";
+//! let mut warnings = Vec::new();
+//! let parse_options = parse::ParseOptions {
+//!     skip_orphans: true,
+//!     ..Default::default()
+//! };
+//! let sequence = parse::parse_input(log.as_bytes(), &parse_options, &mut warnings)?;
+//! let mut mp4 = Cursor::new(Vec::new());
+//! let options = render::Options::default();
+//! render::render_to(&sequence, &mut mp4, &options, &mut warnings, &mut |_, _| {})?;
+//! assert!(!mp4.into_inner().is_empty());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use parse_display::{Display, FromStr};
+use std::collections::BTreeMap;
+use std::ops::{Range, RangeInclusive};
+
+mod apng;
+mod checkpoint;
+pub mod encode;
+pub mod export;
+mod font;
+pub mod palette;
+pub mod parse;
+pub mod render;
+pub mod stats;
+pub mod term;
+pub mod warning;
+mod webp;
+mod zip;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub bank: u32,
+    pub addr: u16,
+}
+
+#[derive(Display, FromStr, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[display(style = "UPPERCASE")]
+pub enum MemType {
+    Rom0,
+    Romx,
+    Vram,
+    Sram,
+    Wram0,
+    Wramx,
+    Oam,
+    Hram,
+}
+
+/// A group of memory types that are displayed together.
+#[derive(Display, FromStr, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[display(style = "lowercase")]
+pub enum Region {
+    Rom,
+    Vram,
+    Sram,
+    Wram,
+    Oam,
+    Hram,
+}
+
+#[derive(Debug)]
+pub struct Section {
+    pub mem_type: MemType,
+    pub location: Location,
+    pub align_mask: u16,
+    pub align_ofs: u16,
+    pub size: u32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    /// The linker attempted to place the section there.
+    Attempt,
+    /// The linker placed the section there, for good.
+    Placement,
+    /// The linker took the section back out of there, e.g. to backtrack.
+    Removal,
+}
+
+#[derive(Debug)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub location: Location,
+    pub section_id: usize,
+}
+
+#[derive(Debug)]
+pub struct Sequence {
+    /// How many banks each region spans, indexed by `Region as usize`.
+    pub nb_banks: [u32; Region::ALL.len()],
+    pub frames: Vec<Frame>,
+    pub sections: Vec<Section>,
+    /// Whether placements are explicitly marked; if not, they are inferred from the attempts.
+    pub has_placements: bool,
+}
+
+impl Region {
+    /// All regions, in address order.
+    pub const ALL: [Region; 6] = [
+        Region::Rom,
+        Region::Vram,
+        Region::Sram,
+        Region::Wram,
+        Region::Oam,
+        Region::Hram,
+    ];
+
+    /// How many bytes a single bank of this region spans.
+    fn bank_size(self) -> u16 {
+        match self {
+            Region::Rom => MemType::Romx.bank_size(),
+            Region::Vram => MemType::Vram.bank_size(),
+            Region::Sram => MemType::Sram.bank_size(),
+            Region::Wram => MemType::Wramx.bank_size(),
+            Region::Oam => MemType::Oam.bank_size(),
+            Region::Hram => MemType::Hram.bank_size(),
+        }
+    }
+
+    /// How many banks are displayed for this region, at minimum.
+    fn min_nb_banks(self) -> u32 {
+        match self {
+            // The unbanked half always comes with at least one bank's worth of the banked one
+            Region::Rom | Region::Wram => 2,
+            Region::Vram | Region::Sram | Region::Oam | Region::Hram => 1,
+        }
+    }
+}
+
+impl MemType {
+    pub fn region(&self) -> Region {
+        match self {
+            MemType::Rom0 | MemType::Romx => Region::Rom,
+            MemType::Vram => Region::Vram,
+            MemType::Sram => Region::Sram,
+            MemType::Wram0 | MemType::Wramx => Region::Wram,
+            MemType::Oam => Region::Oam,
+            MemType::Hram => Region::Hram,
+        }
+    }
+
+    /// The address at which this memory type's banks begin.
+    fn start_addr(&self) -> u16 {
+        match self {
+            MemType::Rom0 => 0x0000,
+            MemType::Romx => 0x4000,
+            MemType::Vram => 0x8000,
+            MemType::Sram => 0xA000,
+            MemType::Wram0 => 0xC000,
+            MemType::Wramx => 0xD000,
+            MemType::Oam => 0xFE00,
+            MemType::Hram => 0xFF80,
+        }
+    }
+
+    /// How far an address is from the beginning of this memory type's first bank; this may span
+    /// several banks' worth for ROM0 and WRAM0 (see `addr_range`), and wraps around for addresses
+    /// before it.
+    fn offset_of(&self, addr: u16) -> u16 {
+        addr.wrapping_sub(self.start_addr())
+    }
+
+    /// The addresses that sections of this memory type may be placed at.
+    fn addr_range(&self) -> RangeInclusive<u16> {
+        match self {
+            // With `-t` and `-w` respectively, these extend over their banked counterpart
+            MemType::Rom0 => 0x0000..=0x7FFF,
+            MemType::Wram0 => 0xC000..=0xDFFF,
+            MemType::Hram => 0xFF80..=0xFFFE,
+            _ => self.start_addr()..=self.start_addr() + (self.bank_size() - 1),
+        }
+    }
+
+    /// Whether sections of this memory type may be in other banks than bank 0.
+    fn is_banked(&self) -> bool {
+        match self {
+            MemType::Romx | MemType::Vram | MemType::Sram | MemType::Wramx => true,
+            MemType::Rom0 | MemType::Wram0 | MemType::Oam | MemType::Hram => false,
+        }
+    }
+
+    /// How many bytes a single bank of this memory type spans.
+    fn bank_size(&self) -> u16 {
+        match self {
+            MemType::Rom0 | MemType::Romx => 0x4000,
+            MemType::Vram | MemType::Sram => 0x2000,
+            MemType::Wram0 | MemType::Wramx => 0x1000,
+            MemType::Oam => 0xA0,
+            MemType::Hram => 0x7F,
+        }
+    }
+}
+
+impl Sequence {
+    pub fn nb_banks(&self, region: Region) -> u32 {
+        self.nb_banks[region as usize]
+    }
+
+    /// Drops attempts of sections attempted more than `max` times, keeping only their first ones
+    /// and their last one, so that sections are still seen landing.
+    /// Returns how many attempts were dropped for each section that had some, by ID.
+    pub fn trim_attempts(&mut self, max: usize) -> Vec<(usize, usize)> {
+        let mut nb_attempts = vec![0; self.sections.len()];
+        for frame in &self.frames {
+            if frame.kind == FrameKind::Attempt {
+                nb_attempts[frame.section_id] += 1;
+            }
+        }
+
+        let mut nb_seen = vec![0; self.sections.len()];
+        self.frames.retain(|frame| {
+            if frame.kind != FrameKind::Attempt {
+                return true;
+            }
+            let (seen, total) = (
+                &mut nb_seen[frame.section_id],
+                nb_attempts[frame.section_id],
+            );
+            *seen += 1;
+            *seen < max || *seen == total
+        });
+
+        nb_attempts
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, nb)| nb > max)
+            .map(|(section_id, nb)| (section_id, nb - max))
+            .collect()
+    }
+
+    /// Which memory types the sections are in, each only once, in declaration order.
+    fn mem_types(&self) -> Vec<&MemType> {
+        let mut mem_types = Vec::new();
+        for section in &self.sections {
+            if !mem_types.contains(&&section.mem_type) {
+                mem_types.push(&section.mem_type);
+            }
+        }
+        mem_types.sort();
+        mem_types
+    }
+
+    /// Picks which region to display, when not told: the only one the sections are in, if so.
+    /// Otherwise, ROM is preferred, as it's usually the most interesting.
+    pub fn pick_region(&self) -> Region {
+        let mem_types = self.mem_types();
+        let mut regions: Vec<_> = mem_types.iter().map(|mem_type| mem_type.region()).collect();
+        regions.dedup();
+        match regions[..] {
+            [] => Region::Rom,
+            [region] => region,
+            _ => {
+                let region = if regions.contains(&Region::Rom) {
+                    Region::Rom
+                } else {
+                    regions[0]
+                };
+                let names: Vec<_> = mem_types.iter().map(ToString::to_string).collect();
+                eprintln!(
+                    "Note: the log contains {} sections; only displaying {} (pick others with `--region`, or `--region all`)",
+                    names.join(", "),
+                    region
+                );
+                region
+            }
+        }
+    }
+
+    /// The frames that settle a section over another one, or past the end of its memory type,
+    /// which a well-behaved linker never does.
+    pub fn problems(&self) -> Vec<usize> {
+        let mut problems = Vec::new();
+        // The sections settled in each bank, by the addresses they span
+        let mut settled = BTreeMap::new();
+        let unsettle = |settled: &mut BTreeMap<_, Vec<(Range<u32>, usize)>>, section_id| {
+            for sections in settled.values_mut() {
+                sections.retain(|&(_, id)| id != section_id);
+            }
+        };
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            let settles = match frame.kind {
+                FrameKind::Placement => true,
+                // Without explicit placements, sections are settled at their last attempt
+                FrameKind::Attempt => {
+                    !self.has_placements
+                        && self
+                            .frames
+                            .get(i + 1)
+                            .is_none_or(|next| next.section_id != frame.section_id)
+                }
+                FrameKind::Removal => {
+                    unsettle(&mut settled, frame.section_id);
+                    false
+                }
+            };
+            if !settles {
+                continue;
+            }
+
+            let section = &self.sections[frame.section_id];
+            let start = u32::from(frame.location.addr);
+            let range = start..start.saturating_add(section.size);
+            unsettle(&mut settled, frame.section_id);
+            let bank = settled
+                .entry((section.mem_type.region(), frame.location.bank))
+                .or_default();
+            let overflows = range.end > u32::from(*section.mem_type.addr_range().end()) + 1;
+            let collides = bank
+                .iter()
+                .any(|(other, _)| other.start < range.end && range.start < other.end);
+            if overflows || collides {
+                problems.push(i);
+            }
+            bank.push((range, frame.section_id));
+        }
+        problems
+    }
+
+    /// The highest bank that anything of this region is attempted or placed in, if any.
+    pub fn max_bank(&self, region: Region) -> Option<u32> {
+        self.frames
+            .iter()
+            .filter(|frame| self.sections[frame.section_id].mem_type.region() == region)
+            .map(|frame| frame.location.bank)
+            .max()
+    }
+
+    /// Where each section ended up, indexed like `sections`; `None` if it was never placed.
+    /// Without explicit placements, this is each section's last attempt.
+    fn final_placements(&self) -> Vec<Option<&Location>> {
+        let mut placements = vec![None; self.sections.len()];
+        for frame in &self.frames {
+            match frame.kind {
+                FrameKind::Removal => placements[frame.section_id] = None,
+                kind if self.has_placements == (kind == FrameKind::Placement) => {
+                    placements[frame.section_id] = Some(&frame.location);
+                }
+                _ => (),
+            }
+        }
+        placements
+    }
+}
+
+impl Location {
+    fn is_floating(&self) -> bool {
+        self.addr == u16::MAX
+    }
+
+    fn is_floating_bank(&self) -> bool {
+        self.bank == u32::MAX
+    }
+
+    /// How far into its bank this location is, for a section of the given memory type; this is
+    /// where it's drawn. Addresses past the end of the bank wrap around to its beginning.
+    fn bank_offset(&self, mem_type: &MemType) -> u16 {
+        mem_type.offset_of(self.addr) % mem_type.bank_size()
+    }
+}
+
+/// How addresses are written when shown to the user.
+#[derive(FromStr, Debug, Clone, Copy, PartialEq, Eq)]
+#[display(style = "lowercase")]
+pub enum AddrStyle {
+    /// As seen by the CPU, e.g. $4000-$7FFF for ROMX.
+    Absolute,
+    /// Relative to the beginning of the bank, e.g. $0000-$3FFF for ROMX.
+    Relative,
+}
+
+impl Section {
+    /// Formats a location of this section, as `bank:addr`.
+    fn format_location(&self, location: &Location, style: AddrStyle) -> String {
+        let addr = match style {
+            AddrStyle::Absolute => location.addr,
+            AddrStyle::Relative => self.mem_type.offset_of(location.addr),
+        };
+        format!("{:02x}:{:04x}", location.bank, addr)
+    }
+
+    fn is_floating(&self) -> bool {
+        self.location.is_floating()
+    }
+
+    fn is_floating_bank(&self) -> bool {
+        self.location.is_floating_bank()
+    }
+}
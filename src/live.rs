@@ -0,0 +1,192 @@
+//! Previewing the log while it's still being written, e.g. to a named pipe by an ongoing build.
+
+use gb_packing_visualizer::render::{self, Options};
+use gb_packing_visualizer::{term, Sequence};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the preview is updated, at most.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reads the input from another thread, so that waiting for it can be interrupted: if nothing
+/// comes in for a while, reads fail with `WouldBlock`, and can simply be retried afterwards.
+pub struct LiveInput {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    /// How much of the current chunk has been consumed.
+    pos: usize,
+    done: bool,
+}
+
+impl LiveInput {
+    pub fn new(mut input: impl Read + Send + 'static) -> Self {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || loop {
+            let mut chunk = vec![0; 64 * 1024];
+            let result = match input.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(len) => {
+                    chunk.truncate(len);
+                    Ok(chunk)
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => Err(err),
+            };
+            let is_err = result.is_err();
+            // Stop if the other end isn't reading anymore, or after reporting an error
+            if tx.send(result).is_err() || is_err {
+                break;
+            }
+        });
+
+        Self {
+            chunks: rx,
+            chunk: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+}
+
+impl Read for LiveInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl BufRead for LiveInput {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.chunk.len() && !self.done {
+            match self.chunks.recv_timeout(INTERVAL) {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        "No new input for now",
+                    ))
+                }
+                // The input has ended
+                Err(RecvTimeoutError::Disconnected) => self.done = true,
+            }
+        }
+        Ok(&self.chunk[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+/// Where the preview of the latest frame is shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// A PNG file at this path, which image viewers can reload.
+    Png(PathBuf),
+    /// The terminal, redrawn in place, like `--format term` draws it.
+    Terminal,
+}
+
+/// A still image of the latest frame, kept up to date as the sequence grows.
+pub struct Preview<'a> {
+    target: &'a Target,
+    options: &'a Options,
+    /// The latest frame, drawn as the sequence grows instead of from scratch each time.
+    frame: render::LiveFrame<'a>,
+    /// The terminal's size when it was last drawn in, to redraw it from scratch once it changes.
+    term_size: Option<(u32, u32)>,
+    last_update: Instant,
+    /// How many frames the sequence had when the preview was last written.
+    nb_frames: usize,
+    /// Set once writing the preview failed, so as not to keep failing (and complaining).
+    failed: bool,
+}
+
+impl<'a> Preview<'a> {
+    pub fn new(target: &'a Target, options: &'a Options) -> Self {
+        Self {
+            target,
+            options,
+            frame: render::LiveFrame::new(options),
+            term_size: None,
+            last_update: Instant::now(),
+            nb_frames: 0,
+            failed: false,
+        }
+    }
+
+    /// Updates the preview if it's outdated, unless it was updated too recently.
+    pub fn update(&mut self, sequence: &Sequence) {
+        let resized = *self.target == Target::Terminal && self.term_size != Some(term::size());
+        if (sequence.frames.len() != self.nb_frames || resized)
+            && self.last_update.elapsed() >= INTERVAL
+        {
+            self.write(sequence, false);
+        }
+    }
+
+    /// Updates the preview one last time, now that the sequence is complete, with everything the
+    /// output's last frame shows.
+    pub fn finish(mut self, sequence: &Sequence) {
+        self.write(sequence, true);
+    }
+
+    fn write(&mut self, sequence: &Sequence, complete: bool) {
+        if self.failed {
+            return;
+        }
+        if !complete {
+            self.frame.update(sequence);
+        }
+        match self.target {
+            Target::Png(path) => {
+                let result = if complete {
+                    render::write_last_frame(sequence, self.options, path)
+                } else {
+                    self.frame.write_png(sequence, path)
+                };
+                if let Err(err) = result {
+                    eprintln!("Error writing live preview to {}: {}", path.display(), err);
+                    self.failed = true;
+                }
+            }
+            Target::Terminal => {
+                if let Err(err) = self.draw(sequence, complete) {
+                    eprintln!("Error drawing live preview: {}", err);
+                    self.failed = true;
+                }
+            }
+        }
+        self.last_update = Instant::now();
+        self.nb_frames = sequence.frames.len();
+    }
+
+    /// Draws over the previous drawing, moving the cursor back instead of scrolling.
+    fn draw(&mut self, sequence: &Sequence, complete: bool) -> Result<(), render::RenderError> {
+        let size = term::size();
+        let mut stdout = io::stdout().lock();
+        // Shrinking would leave bits of the bigger drawing around
+        if self.term_size != Some(size) {
+            write!(stdout, "{}", term::CLEAR)?;
+            self.term_size = Some(size);
+        }
+        write!(stdout, "{}", term::HOME)?;
+        if complete {
+            render::draw_in_terminal(sequence, self.options, size, &mut stdout)?;
+        } else {
+            self.frame.draw_in_terminal(sequence, size, &mut stdout)?;
+        }
+        write!(stdout, "{}", term::CLEAR_BELOW)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
@@ -1,96 +1,726 @@
-use parse_display::FromStr;
-use std::env;
-use std::ffi::OsStr;
-use std::path::Path;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches};
+use config::Config;
+use gb_packing_visualizer::warning::Warning;
+use gb_packing_visualizer::{
+    encode, export, palette, parse, render, stats, term, AddrStyle, MemType, Region, Sequence,
+};
+use std::cmp;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
-mod parse;
-mod render;
+mod cli;
+mod config;
+mod inline;
+mod live;
+mod metadata;
+mod mux;
+mod watch;
 
-#[derive(Debug)]
-struct Location {
-    bank: u32,
-    addr: u16,
-}
+fn main() {
+    // Usage errors exit with 1, like other errors; help and version requests exit normally
+    let matches = cli::Args::command()
+        .try_get_matches()
+        .and_then(|matches| Ok((cli::Args::from_arg_matches(&matches)?, matches)));
+    let (mut args, matches) = matches.unwrap_or_else(|err| {
+        if err.use_stderr() {
+            let _ = err.print();
+            exit(1);
+        }
+        err.exit();
+    });
+    if let Some(path) = args.config.clone() {
+        if let Err(err) = Config::read(&path).and_then(|config| config.apply(&mut args, &matches)) {
+            eprintln!("Error in config file {}: {}", path.display(), err);
+            exit(1);
+        }
+    }
 
-#[derive(FromStr, Debug, PartialEq, Eq)]
-#[display(style = "UPPERCASE")]
-enum MemType {
-    Rom0,
-    Romx,
-    Vram,
-    Sram,
-    Wram0,
-    Wramx,
-    Oam,
-    Hram,
-}
+    let mut options = render::Options {
+        grayscale: args.grayscale,
+        crt: args.crt,
+        profile: args.profile.unwrap_or(encode::Profile::Baseline),
+        level: args.level,
+        mp4_brand: args.mp4_brand.unwrap_or(encode::DEFAULT_BRAND),
+        mp4_compatible_brands: if args.mp4_compatible.is_empty() {
+            encode::DEFAULT_COMPATIBLE_BRANDS.to_vec()
+        } else {
+            args.mp4_compatible.clone()
+        },
+        name_color_seed: args.color_seed.or(args.color_by_name.then_some(0)),
+        checkpoint: args.checkpoint,
+        resume: args.resume,
+        fps: args.fps,
+        duration: args.duration,
+        overlay_alpha: args.overlay_alpha,
+        overlay_border: args.overlay_border,
+        min_section_px: args.min_section_px,
+        antialias: args.antialias,
+        show_free: args.show_free,
+        show_largest_gap: args.show_largest_gap,
+        mark_full: args.mark_full,
+        bank_labels: args.bank_labels,
+        highlight: args.highlight,
+        watch_ranges: args.watch_range,
+        palette: match &args.palette_file {
+            Some(path) => palette::Palette::read(path).unwrap_or_else(|err| {
+                eprintln!("Error in palette file {}: {}", path.display(), err);
+                exit(1);
+            }),
+            None => palette::Palette::default(),
+        },
+        grid: args.grid,
+        flash_frames: args
+            .flash_frames
+            .unwrap_or(if args.flash_on_settle { 6 } else { 0 }),
+        grow_frames: args.grow_on_settle,
+        snapshots: args.snapshots,
+        png_dir: args.png_dir,
+        png_zip: args.png_zip,
+        still: args.still,
+        annotate_settle_frame: args.annotate_settle_frame,
+        frame_sizes: args.frame_sizes,
+        max_output_bytes: args.max_output_bytes,
+        frame_cache: args.frame_cache,
+        scale: args.scale,
+        contact_sheet_frames: args.contact_sheet_frames,
+        frame_skip: args.frame_skip,
+        sample_every: args.sample_every,
+        diagnose_settles: args.invert_attempts_settled,
+        only_problems: args.only_problems,
+        coalesce_identical: args.coalesce_identical,
+        no_settle: args.no_settle,
+        verbose: args.verbose,
+        ..Default::default()
+    };
+    if let Some(rom0_half) = args.rom0_half {
+        options.rom0_half = rom0_half;
+    }
+    if let Some(bank_order) = args.bank_order {
+        options.bank_order = bank_order;
+    }
+    if let Some(orientation) = args.orientation {
+        options.orientation = orientation;
+    }
+    options.group_by = args.group_by;
+    if let Some(attempt_style) = args.attempt_style {
+        options.attempt_style = attempt_style;
+    }
+    if let Some(scale_mode) = args.scale_mode {
+        options.scale_mode = scale_mode;
+    }
+    options.follow = args.follow;
+    if args.duration.is_some() && matches.value_source("fps") == Some(ValueSource::CommandLine) {
+        eprintln!("Warning: --duration overrides --fps");
+    }
+    // Only showing some memory types implies showing their regions
+    let pick_region = args.regions.is_empty() && args.mem_types.is_empty();
+    if args.regions.is_empty() && !args.mem_types.is_empty() {
+        options.regions = args.mem_types.iter().map(MemType::region).collect();
+        options.regions.sort();
+        options.regions.dedup();
+    }
+    if !args.regions.is_empty() {
+        options.regions = Vec::new();
+        for region in args.regions {
+            match region {
+                cli::RegionArg::All => options.regions.extend_from_slice(&Region::ALL),
+                cli::RegionArg::One(region) => options.regions.push(region),
+            }
+        }
+    }
+    for mem_type in &args.mem_types {
+        if !options.regions.contains(&mem_type.region()) {
+            eprintln!(
+                "--filter-type {}: its region ({}) is not displayed, see --region",
+                mem_type,
+                mem_type.region()
+            );
+            exit(1);
+        }
+    }
+    options.mem_types = args.mem_types;
+    if args.pace_by == cli::PaceBy::Bytes {
+        options.pace = render::Pace::Bytes(args.bytes_per_frame);
+    }
 
-#[derive(Debug)]
-struct Section {
-    mem_type: MemType,
-    location: Location,
-    align_mask: u16,
-    align_ofs: u16,
-    size: u16,
-    name: String,
+    let (input, out_path, format) = (args.input, args.out_path, args.format);
+    let input_format =
+        args.input_format
+            .unwrap_or_else(|| match input.as_deref().and_then(Path::extension) {
+                Some(ext) if ext.eq_ignore_ascii_case("csv") => parse::InputFormat::Csv,
+                _ => parse::InputFormat::Trace,
+            });
+    options.format = format.unwrap_or_else(|| {
+        match out_path
+            .as_deref()
+            .and_then(Path::extension)
+            .and_then(|ext| ext.to_str())
+        {
+            Some(ext) if ext.eq_ignore_ascii_case("png") || ext.eq_ignore_ascii_case("apng") => {
+                render::Format::Apng
+            }
+            Some(ext) if ext.eq_ignore_ascii_case("svg") => render::Format::Svg,
+            Some(ext) if ext.eq_ignore_ascii_case("webp") => render::Format::Webp,
+            _ => render::Format::Mp4,
+        }
+    });
+    // Only terminal output can do without an output file, being written to standard output
+    if out_path.is_none()
+        && !(args.list_sections || args.benchmark_render)
+        && options.format != render::Format::Term
+    {
+        eprintln!("An output file is required, except with --format term");
+        exit(1);
+    }
+    let live = match args.live {
+        Some(Some(path)) => Some(live::Target::Png(path)),
+        Some(None) if options.format == render::Format::Term => Some(live::Target::Terminal),
+        Some(None) => {
+            eprintln!("--live requires a path, as in --live=PATH, except with --format term");
+            exit(1);
+        }
+        None => None,
+    };
+    if live.is_some() && input_format != parse::InputFormat::Trace {
+        eprintln!("--live is only supported for trace input");
+        exit(1);
+    }
+    if args.keep_going.is_some() && input_format != parse::InputFormat::Trace {
+        eprintln!("--keep-going is only supported for trace input");
+        exit(1);
+    }
+    if args.skip_orphan_attempts && input_format != parse::InputFormat::Trace {
+        eprintln!("--skip-orphan-attempts is only supported for trace input");
+        exit(1);
+    }
+    if args.two_pass && input_format != parse::InputFormat::Trace {
+        eprintln!("--two-pass is only supported for trace input");
+        exit(1);
+    }
+    if args.on_duplicate.is_some() && input_format != parse::InputFormat::Trace {
+        eprintln!("--on-duplicate is only supported for trace input");
+        exit(1);
+    }
+    if args.banks_from_rom && args.rom.is_none() {
+        eprintln!("--banks-from-rom requires --rom");
+        exit(1);
+    }
+    if args.on_duplicate.is_some() && args.two_pass {
+        eprintln!("--on-duplicate cannot be combined with --two-pass");
+        exit(1);
+    }
+    if options.format != render::Format::Mp4 && (options.checkpoint || options.resume) {
+        eprintln!("--checkpoint and --resume are only supported for MP4 output");
+        exit(1);
+    }
+    if options.format != render::Format::Mp4 && (args.profile.is_some() || args.level.is_some()) {
+        eprintln!("--profile and --level are only supported for MP4 output");
+        exit(1);
+    }
+    if options.format != render::Format::Mp4
+        && (args.mp4_brand.is_some() || !args.mp4_compatible.is_empty())
+    {
+        eprintln!("--mp4-brand and --mp4-compatible are only supported for MP4 output");
+        exit(1);
+    }
+    if options.format != render::Format::Mp4 && options.crt {
+        eprintln!("--crt is only supported for MP4 output");
+        exit(1);
+    }
+    if options.format != render::Format::Mp4 && args.click_track {
+        eprintln!("--click-track is only supported for MP4 output");
+        exit(1);
+    }
+    if options.format != render::Format::Mp4 && args.poster_frame.is_some() {
+        eprintln!("--poster-frame is only supported for MP4 output");
+        exit(1);
+    }
+    if options.format != render::Format::Mp4 && options.frame_sizes.is_some() {
+        eprintln!("--frame-sizes is only supported for MP4 output");
+        exit(1);
+    }
+    if options.format != render::Format::Mp4 && options.max_output_bytes.is_some() {
+        eprintln!("--max-output-bytes is only supported for MP4 output");
+        exit(1);
+    }
+    if options.format != render::Format::Mp4 && options.frame_cache != 0 {
+        eprintln!("--frame-cache is only supported for MP4 output");
+        exit(1);
+    }
+    if options.format == render::Format::Svg
+        && (!options.snapshots.is_empty()
+            || options.still.is_some()
+            || options.png_dir.is_some()
+            || options.png_zip.is_some())
+    {
+        eprintln!("--snapshot, --still, --png-dir, and --png-zip are not supported for SVG output");
+        exit(1);
+    }
+    if options.follow && options.scale.is_none() {
+        eprintln!("--follow requires --scale");
+        exit(1);
+    }
+    if options.follow && options.format == render::Format::Svg {
+        eprintln!("--follow is not supported for SVG output");
+        exit(1);
+    }
+    if options.format == render::Format::Term
+        && (!options.snapshots.is_empty()
+            || options.still.is_some()
+            || options.png_dir.is_some()
+            || options.png_zip.is_some()
+            || options.follow
+            || args.benchmark_render)
+    {
+        eprintln!("--snapshot, --still, --png-dir, --png-zip, --follow, and --benchmark-render are not supported for terminal output");
+        exit(1);
+    }
+    if options.no_settle && (options.diagnose_settles || options.format == render::Format::Svg) {
+        eprintln!("--no-settle cannot be combined with --invert-attempts-settled, nor SVG output");
+        exit(1);
+    }
+    if options.format == render::Format::Svg && options.show_largest_gap {
+        eprintln!("--show-largest-gap is not supported for SVG output");
+        exit(1);
+    }
+    if options.format == render::Format::Svg && args.benchmark_render {
+        eprintln!("--benchmark-render is not supported for SVG output");
+        exit(1);
+    }
+    if let (render::Format::Mp4, Some((width, height))) = (options.format, options.scale) {
+        if width % 2 != 0 || height % 2 != 0 {
+            eprintln!("--scale must use even dimensions for MP4 output");
+            exit(1);
+        }
+    }
+    // Read the inline input's file right away, like the command line it stands in for
+    let input_base64 = args.input_base64.map(|arg| match arg.strip_prefix('@') {
+        Some(path) => fs::read_to_string(path).unwrap_or_else(|err| {
+            eprintln!("Error reading {}: {}", path, err);
+            exit(1);
+        }),
+        None => arg,
+    });
+    let job = Job {
+        input,
+        input_base64,
+        input_format,
+        parse_options: parse::ParseOptions {
+            max_line_len: args.max_line_len,
+            max_skipped: args.keep_going.map(|max| max.unwrap_or(u64::MAX)),
+            skip_orphans: args.skip_orphan_attempts,
+            two_pass: args.two_pass,
+            on_duplicate: args.on_duplicate,
+        },
+        strict: args.strict,
+        max_attempts: args.max_attempts_per_section.map(|max| max as usize),
+        out_path,
+        export_json: args.export_json,
+        vtt: args.vtt,
+        trace_json: args.trace_json,
+        live,
+        click_track: args.click_track,
+        index: args.index,
+        dot: args.dot,
+        contact_sheet: args.contact_sheet,
+        net_diff: args.net_diff,
+        dump_rgb: args.dump_rgb,
+        megastill: args.megastill,
+        legend_png: args.legend_png,
+        poster_frame: args.poster_frame,
+        rom: args.rom,
+        banks_from_rom: args.banks_from_rom,
+        title_card: args.title_card,
+        deterministic: args.deterministic,
+        print_stats: args.stats,
+        list_sections: args.list_sections,
+        benchmark: args.benchmark_render,
+        addr_style: args.addr_style,
+        pick_region,
+    };
+    let render_once = || run(&job, &options);
+    if args.watch {
+        let input = job.input.as_deref().unwrap_or_else(|| {
+            eprintln!("--watch requires an --input file");
+            exit(1);
+        });
+        // Errors are reported, but we keep watching in case the next version of the file is fine
+        if let Err(err) = watch::watch(input, || {
+            let _ = render_once();
+        }) {
+            eprintln!("Error watching {}: {}", input.display(), err);
+            exit(1);
+        }
+    } else if render_once().is_err() {
+        exit(1);
+    }
 }
 
-#[derive(Debug)]
-struct Frame {
-    location: Location,
-    section_id: usize,
+/// What to do with the input, besides rendering it.
+struct Job {
+    input: Option<PathBuf>,
+    /// The log itself, as given by `--input-base64`.
+    input_base64: Option<String>,
+    input_format: parse::InputFormat,
+    /// How to parse trace input.
+    parse_options: parse::ParseOptions,
+    /// Whether suspicious input is rejected, instead of only warned about.
+    strict: bool,
+    max_attempts: Option<usize>,
+    /// Only absent when listing sections or benchmarking.
+    out_path: Option<PathBuf>,
+    export_json: Option<PathBuf>,
+    vtt: Option<PathBuf>,
+    trace_json: Option<PathBuf>,
+    /// Where to keep a preview of the latest frame while reading the input.
+    live: Option<live::Target>,
+    click_track: bool,
+    index: Option<PathBuf>,
+    dot: Option<PathBuf>,
+    contact_sheet: Option<PathBuf>,
+    net_diff: Option<PathBuf>,
+    dump_rgb: Option<PathBuf>,
+    megastill: Option<PathBuf>,
+    legend_png: Option<PathBuf>,
+    /// Which frame to embed as the MP4's cover art, if any; the last one if not given.
+    poster_frame: Option<Option<usize>>,
+    rom: Option<PathBuf>,
+    /// Whether to display as many ROM banks as the ROM has.
+    banks_from_rom: bool,
+    title_card: bool,
+    /// Whether the output must only depend on the input's contents, not on where it's from.
+    deterministic: bool,
+    print_stats: bool,
+    list_sections: bool,
+    /// Whether to only time rendering, instead of writing the output.
+    benchmark: bool,
+    addr_style: AddrStyle,
+    /// Whether to pick the region to display depending on the input.
+    pick_region: bool,
 }
 
-#[derive(Debug)]
-pub struct Sequence {
-    nb_banks: u32,
-    frames: Vec<Frame>,
-    sections: Vec<Section>,
-}
+/// Parses the input and renders it, reporting any errors.
+fn run(job: &Job, options: &render::Options) -> Result<(), ()> {
+    let input = job.input.as_deref();
+    let mut warnings = Vec::new();
+    let mut parse = |input: &mut dyn BufRead| match (job.input_format, &job.live) {
+        (parse::InputFormat::Trace, Some(target)) => {
+            let mut preview = live::Preview::new(target, options);
+            let sequence =
+                parse::parse_input_live(input, &job.parse_options, &mut warnings, |sequence| {
+                    preview.update(sequence)
+                })?;
+            preview.finish(&sequence);
+            Ok(sequence)
+        }
+        (parse::InputFormat::Trace, None) => {
+            parse::parse_input(input, &job.parse_options, &mut warnings)
+        }
+        (parse::InputFormat::Csv, _) => parse::parse_csv(input, &mut warnings),
+    };
+    let sequence = match (input, &job.input_base64) {
+        (_, Some(text)) => match inline::decode(text) {
+            Ok(log) => parse(&mut &log[..]),
+            Err(err) => {
+                eprintln!("Error decoding --input-base64: {}", err);
+                return Err(());
+            }
+        },
+        (Some(path), None) => match File::open(path) {
+            Ok(file) if job.live.is_some() => parse(&mut live::LiveInput::new(file)),
+            Ok(file) => parse(&mut BufReader::new(file)),
+            Err(err) => {
+                eprintln!("Error opening {}: {}", path.display(), err);
+                return Err(());
+            }
+        },
+        (None, None) if job.live.is_some() => parse(&mut live::LiveInput::new(io::stdin())),
+        (None, None) => parse(&mut io::stdin().lock()),
+    };
+    // Report the warnings before rendering, which may take a while, or before the error if any
+    let warned = report_warnings(&warnings, job.strict);
+    let mut sequence = match sequence {
+        Ok(seq) => seq,
+        Err(err) => {
+            eprintln!("Input parse error: {}", err);
+            return Err(());
+        }
+    };
+    warned?;
+    if let Some(max) = job.max_attempts {
+        let trimmed = sequence.trim_attempts(max);
+        if !trimmed.is_empty() {
+            eprintln!(
+                "Trimmed {} attempt(s) from {} section(s) attempted more than {} times:",
+                trimmed.iter().map(|&(_, nb)| nb).sum::<usize>(),
+                trimmed.len(),
+                max
+            );
+            for (section_id, nb) in trimmed {
+                eprintln!("    {}: {} trimmed", sequence.sections[section_id].name, nb);
+            }
+        }
+    }
 
-impl Location {
-    fn is_floating(&self) -> bool {
-        self.addr == u16::MAX
+    if job.list_sections {
+        stats::print_sections(&sequence, job.addr_style);
+        return Ok(());
+    }
+    if options.only_problems {
+        let nb_problems = sequence.problems().len();
+        if nb_problems == 0 {
+            eprintln!("No sections are settled over others or out of bounds, so there is nothing to render");
+            return Ok(());
+        }
+        eprintln!(
+            "Found {} section(s) settled over others or out of bounds",
+            nb_problems
+        );
     }
 
-    fn is_floating_bank(&self) -> bool {
-        self.bank == u32::MAX
+    // Read the ROM before rendering, so as not to fail only after a long render
+    let metadata = match &job.rom {
+        Some(path) => match metadata::Metadata::from_rom(path) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                eprintln!("Error reading ROM {}: {}", path.display(), err);
+                return Err(());
+            }
+        },
+        None => metadata::Metadata::from_input(input.filter(|_| !job.deterministic), &sequence),
+    };
+    if job.rom.is_some() {
+        let max_bank = sequence.max_bank(Region::Rom);
+        match (metadata.nb_banks(), max_bank) {
+            (Some(nb_banks), Some(bank)) if bank >= nb_banks => {
+                report_warnings(&[Warning::BeyondRom(bank, nb_banks)], job.strict)?;
+            }
+            (None, _) if job.banks_from_rom => {
+                eprintln!(
+                    "Error reading ROM {}: unknown ROM size in its header",
+                    job.rom.as_deref().unwrap().display()
+                );
+                return Err(());
+            }
+            _ => {}
+        }
+        if let (Some(nb_banks), true) = (metadata.nb_banks(), job.banks_from_rom) {
+            // Banks that the log uses are still displayed, even if the ROM is too small for them
+            sequence.nb_banks[Region::Rom as usize] =
+                cmp::max(nb_banks, max_bank.map_or(0, |bank| bank + 1));
+        }
     }
-}
 
-impl Section {
-    fn is_floating(&self) -> bool {
-        self.location.is_floating()
+    let mut options = options.clone();
+    if job.pick_region {
+        options.regions = vec![sequence.pick_region()];
+    }
+    if job.title_card {
+        let date = job
+            .rom
+            .as_deref()
+            .or(input)
+            .filter(|_| !job.deterministic)
+            .and_then(metadata::modification_date);
+        options.title_card = title_card(&metadata, date, &sequence, &options);
     }
+    let options = &options;
 
-    fn is_floating_bank(&self) -> bool {
-        self.location.is_floating_bank()
+    if job.print_stats {
+        stats::print_stats(&sequence, &options.regions, options.rom0_half);
+    }
+    if !options.watch_ranges.is_empty() {
+        export::report_watched(&sequence, options, job.addr_style);
+    }
+    if let Some(path) = &job.export_json {
+        if let Err(err) = export::export_json(&sequence, options, path) {
+            eprintln!("Error exporting JSON to {}: {}", path.display(), err);
+            return Err(());
+        }
+    }
+    if let Some(path) = &job.vtt {
+        if let Err(err) = export::export_vtt(&sequence, options, path) {
+            eprintln!("Error exporting subtitles to {}: {}", path.display(), err);
+            return Err(());
+        }
+    }
+    if let Some(path) = &job.trace_json {
+        if let Err(err) = export::export_trace_json(&sequence, options, job.addr_style, path) {
+            eprintln!("Error exporting trace to {}: {}", path.display(), err);
+            return Err(());
+        }
+    }
+    if let Some(path) = &job.index {
+        if let Err(err) = export::export_index(&sequence, options, job.addr_style, path) {
+            eprintln!("Error exporting index to {}: {}", path.display(), err);
+            return Err(());
+        }
+    }
+    if let Some(path) = &job.dot {
+        if let Err(err) = export::export_dot(&sequence, path) {
+            eprintln!("Error exporting graph to {}: {}", path.display(), err);
+            return Err(());
+        }
+    }
+    if let Some(path) = &job.contact_sheet {
+        if let Err(err) = render::contact_sheet(&sequence, options, path) {
+            eprintln!("Error writing contact sheet to {}: {}", path.display(), err);
+            return Err(());
+        }
+    }
+    if let Some(path) = &job.net_diff {
+        if let Err(err) = render::net_diff(&sequence, options, path) {
+            eprintln!("Error writing net diff to {}: {}", path.display(), err);
+            return Err(());
+        }
+    }
+    if let Some(path) = &job.dump_rgb {
+        if let Err(err) = render::dump_rgb(&sequence, options, path) {
+            eprintln!("Error writing raw pixels to {}: {}", path.display(), err);
+            return Err(());
+        }
+    }
+    if let Some(path) = &job.megastill {
+        if let Err(err) = render::megastill(&sequence, options, path) {
+            eprintln!("Error writing megastill to {}: {}", path.display(), err);
+            return Err(());
+        }
+    }
+    if let Some(path) = &job.legend_png {
+        if let Err(err) = render::legend(&sequence, options, path) {
+            eprintln!("Error writing legend to {}: {}", path.display(), err);
+            return Err(());
+        }
     }
-}
 
-fn usage(progname: &OsStr) {
-    eprintln!("Usage: {} <output file>", progname.to_string_lossy());
+    if job.benchmark {
+        if let Err(err) = render::benchmark(&sequence, options) {
+            eprintln!("Rendering error: {}", err);
+            return Err(());
+        }
+        return Ok(());
+    }
+    let out_path = match job.out_path.as_deref() {
+        Some(out_path) => out_path,
+        // The live preview already drew how everything ends up
+        None if job.live == Some(live::Target::Terminal) => return Ok(()),
+        None => {
+            return render::draw_in_terminal(&sequence, options, term::size(), io::stdout())
+                .map_err(|err| eprintln!("Rendering error: {}", err));
+        }
+    };
+    let mut warnings = Vec::new();
+    if let Err(err) = render::render(&sequence, out_path, options, &mut warnings) {
+        eprintln!("Rendering error: {}", err);
+        return Err(());
+    }
+    if options.format == render::Format::Mp4 {
+        let poster = match job.poster_frame {
+            Some(index) => match render::poster(&sequence, options, index) {
+                Ok(Some(png)) => Some(png),
+                Ok(None) => {
+                    warnings.push(Warning::MissingPoster(index.unwrap()));
+                    None
+                }
+                Err(err) => {
+                    eprintln!("Error rendering poster frame: {}", err);
+                    return Err(());
+                }
+            },
+            None => None,
+        };
+        if job.click_track {
+            let samples = export::click_track(&sequence, options);
+            if let Err(err) = mux::add_audio_track(out_path, export::CLICK_TRACK_RATE, &samples) {
+                eprintln!(
+                    "Error adding click track to {}: {}",
+                    out_path.display(),
+                    err
+                );
+                return Err(());
+            }
+        }
+        if let Err(err) = metadata.write_to(out_path, poster.as_deref()) {
+            eprintln!("Error writing metadata to {}: {}", out_path.display(), err);
+            return Err(());
+        }
+    }
+    report_warnings(&warnings, job.strict)
 }
 
-fn main() {
-    let mut args = env::args_os();
-    let progname = args.next().unwrap_or_else(|| env!("CARGO_PKG_NAME").into());
-    let out_path = args.next().unwrap_or_else(|| {
-        usage(&progname);
-        exit(1);
-    });
+/// How many location warnings are printed at most, unless being strict, since a broken log may
+/// well trigger one per line.
+const MAX_LOCATIONS: usize = 10;
 
-    let sequence = match parse::parse_input() {
-        Ok(seq) => seq,
-        Err(err) => {
-            eprintln!("Input parse error: {}", err);
-            exit(1);
+/// Prints the warnings; if being strict, they are all printed, and any of them is an error.
+fn report_warnings(warnings: &[Warning], strict: bool) -> Result<(), ()> {
+    let (mut nb_locations, mut nb_skipped) = (0, 0);
+    for warning in warnings {
+        match warning {
+            Warning::Location(_) => {
+                nb_locations += 1;
+                if !strict && nb_locations > MAX_LOCATIONS {
+                    continue;
+                }
+            }
+            Warning::SkippedLine(_) => nb_skipped += 1,
+            Warning::SkippedAttempts(..)
+            | Warning::OrphanAttempts(..)
+            | Warning::DuplicateSection(..)
+            | Warning::BeyondRom(..)
+            | Warning::MissingSnapshot(..)
+            | Warning::MissingPoster(_) => {}
         }
-    };
+        eprintln!("Warning: {}", warning);
+    }
+    if !strict && nb_locations > MAX_LOCATIONS {
+        eprintln!(
+            "Warning: {} more location(s) out of range were not reported",
+            nb_locations - MAX_LOCATIONS
+        );
+    }
+    if nb_skipped != 0 {
+        eprintln!("Warning: skipped {} malformed line(s)", nb_skipped);
+    }
 
-    if let Err(err) = render::render(&sequence, Path::new(&out_path)) {
-        eprintln!("Rendering error: {}", err);
-        exit(1);
+    if strict && !warnings.is_empty() {
+        eprintln!(
+            "Error: {} warning(s) were reported, and --strict turns them into errors",
+            warnings.len()
+        );
+        return Err(());
+    }
+    Ok(())
+}
+
+/// The lines of the title card: what the video shows, from when, and how full memory ends up.
+fn title_card(
+    metadata: &metadata::Metadata,
+    date: Option<String>,
+    sequence: &Sequence,
+    options: &render::Options,
+) -> Vec<String> {
+    let mut lines = vec![match metadata.title() {
+        "" => "(untitled)".to_string(),
+        title => title.to_string(),
+    }];
+    lines.extend(date);
+    lines.push(format!("{} sections", sequence.sections.len()));
+    let stats = stats::bank_stats(sequence, &options.regions, options.rom0_half);
+    for &region in &options.regions {
+        let banks: Vec<_> = stats.iter().filter(|bank| bank.region == region).collect();
+        let used: u64 = banks.iter().map(|bank| u64::from(bank.used)).sum();
+        let size: u64 = banks.iter().map(|bank| u64::from(bank.size)).sum();
+        lines.push(format!(
+            "{}: {} bank{}, {}% used",
+            region,
+            banks.len(),
+            if banks.len() == 1 { "" } else { "s" },
+            used * 100 / cmp::max(size, 1)
+        ));
     }
+    lines
 }
@@ -0,0 +1,190 @@
+//! Tagging the video with what it visualizes, so that it can be traced back to a build.
+//!
+//! The MP4 writer has no support for metadata, so it is appended to the finished file instead:
+//! the writer puts the `moov` box last, so a `udta` box can be added at its end, with the usual
+//! iTunes-style `meta` > `ilst` layout that players understand.
+
+use crate::mux::{self, mp4_box};
+use gb_packing_visualizer::{FrameKind, Sequence};
+use std::convert::{TryFrom, TryInto};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// How the `data` boxes' payloads are to be interpreted.
+const DATA_TYPE_UTF8: u8 = 1;
+const DATA_TYPE_PNG: u8 = 14;
+
+#[derive(Debug)]
+pub struct Metadata {
+    /// Stored as the video's title (`©nam`).
+    title: String,
+    /// Stored as the video's comment (`©cmt`).
+    comment: String,
+    /// How many ROM banks the header says there are, if known.
+    nb_banks: Option<u32>,
+}
+
+impl Metadata {
+    /// Identifies the ROM by its header's title and global checksum.
+    pub fn from_rom(path: &Path) -> io::Result<Self> {
+        let rom = fs::read(path)?;
+        if rom.len() < 0x150 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Too small to contain a ROM header",
+            ));
+        }
+
+        // The title may be shorter than its field, and newer ROMs reuse its end for other purposes
+        let title: String = rom[0x134..0x144]
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| if c.is_ascii_graphic() { c as char } else { ' ' })
+            .collect();
+        let checksum = u16::from_be_bytes([rom[0x14e], rom[0x14f]]);
+        // 32 KiB shifted left by the code, except for a few sizes that only Pan Docs mention
+        let nb_banks = match rom[0x148] {
+            code @ 0x00..=0x08 => Some(2 << code),
+            0x52 => Some(72),
+            0x53 => Some(80),
+            0x54 => Some(96),
+            _ => None,
+        };
+        Ok(Self {
+            title: title.trim_end().to_string(),
+            comment: format!("Global checksum: ${:04x}", checksum),
+            nb_banks,
+        })
+    }
+
+    /// Without a ROM, identifies the log instead, by its file name and a hash of its contents.
+    pub fn from_input(input: Option<&Path>, sequence: &Sequence) -> Self {
+        let title = match input.and_then(Path::file_name) {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => "<stdin>".to_string(),
+        };
+        Self {
+            title,
+            comment: format!("Log hash: {:016x}", fingerprint(sequence)),
+            nb_banks: None,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn nb_banks(&self) -> Option<u32> {
+        self.nb_banks
+    }
+
+    /// Adds the metadata to an MP4 file that was just written, along with a PNG as its cover art.
+    pub fn write_to(&self, path: &Path, cover: Option<&[u8]>) -> io::Result<()> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let file_len = file.metadata()?.len();
+
+        let moov_ofs = mux::find_moov(&mut file)?;
+
+        // The type is followed by an unspecified locale
+        let data = |data_type: u8, bytes: &[u8]| {
+            let mut payload = vec![0, 0, 0, data_type, 0, 0, 0, 0];
+            payload.extend_from_slice(bytes);
+            mp4_box(b"data", &payload)
+        };
+        let mut ilst = [
+            mp4_box(b"\xa9nam", &data(DATA_TYPE_UTF8, self.title.as_bytes())),
+            mp4_box(b"\xa9cmt", &data(DATA_TYPE_UTF8, self.comment.as_bytes())),
+        ]
+        .concat();
+        if let Some(png) = cover {
+            ilst.extend_from_slice(&mp4_box(b"covr", &data(DATA_TYPE_PNG, png)));
+        }
+        let hdlr = mp4_box(
+            b"hdlr",
+            // Version & flags, pre-defined, handler type, reserved, and an empty name
+            &[&[0; 8][..], b"mdir", b"appl", &[0; 9]].concat(),
+        );
+        // `meta` is a full box, so it starts with a version & flags
+        let meta = mp4_box(
+            b"meta",
+            &[&[0; 4][..], &hdlr, &mp4_box(b"ilst", &ilst)].concat(),
+        );
+        let udta = mp4_box(b"udta", &meta);
+
+        let moov_size: u32 = (file_len - moov_ofs + udta.len() as u64)
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "`moov` box too large"))?;
+        file.seek(SeekFrom::Start(moov_ofs))?;
+        file.write_all(&moov_size.to_be_bytes())?;
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(&udta)?;
+        file.flush()
+    }
+}
+
+/// When a file was last modified, as `YYYY-MM-DD` (in UTC).
+pub fn modification_date(path: &Path) -> Option<String> {
+    let secs = fs::metadata(path)
+        .ok()?
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+
+    // From days since the epoch to a calendar date, using Howard Hinnant's `civil_from_days`;
+    // years begin in March there, so that leap days come last
+    let days = secs / 86400 + 719468;
+    let (era, day_of_era) = (days / 146097, days % 146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_ofs = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_ofs + 2) / 5 + 1;
+    let month = if month_ofs < 10 {
+        month_ofs + 3
+    } else {
+        month_ofs - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    Some(format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+/// A hash of the sequence's contents; FNV-1a, so that it's stable across Rust versions.
+fn fingerprint(sequence: &Sequence) -> u64 {
+    let mut hash = 0xcbf29ce484222325_u64;
+    let mut write = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3);
+        }
+    };
+
+    for section in &sequence.sections {
+        write(section.mem_type.to_string().as_bytes());
+        write(&section.location.bank.to_le_bytes());
+        write(&section.location.addr.to_le_bytes());
+        write(&section.align_mask.to_le_bytes());
+        write(&section.align_ofs.to_le_bytes());
+        // Sizes used to be 16-bit, so keep hashing those the same way
+        match u16::try_from(section.size) {
+            Ok(size) => write(&size.to_le_bytes()),
+            Err(_) => write(&section.size.to_le_bytes()),
+        }
+        write(section.name.as_bytes());
+        // Terminate the name, so that it can't run into the next section
+        write(&[0]);
+    }
+    for frame in &sequence.frames {
+        write(&[match frame.kind {
+            FrameKind::Attempt => 0,
+            FrameKind::Placement => 1,
+            FrameKind::Removal => 2,
+        }]);
+        write(&(frame.section_id as u64).to_le_bytes());
+        write(&frame.location.bank.to_le_bytes());
+        write(&frame.location.addr.to_le_bytes());
+    }
+    hash
+}
@@ -0,0 +1,268 @@
+//! Adding to the finished MP4 what its writer doesn't support.
+//!
+//! The writer puts the `moov` box last, so it can be read back, added to, and written again at
+//! the end of the file; anything else, such as more samples, can then go right before it.
+
+use std::cmp;
+use std::convert::{TryFrom, TryInto};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Wraps a payload into a box of the given type.
+pub fn mp4_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let size: u32 = (payload.len() + 8).try_into().unwrap();
+    [&size.to_be_bytes()[..], box_type, payload].concat()
+}
+
+/// Where the MP4's `moov` box is, which must be its last one.
+pub fn find_moov(file: &mut File) -> io::Result<u64> {
+    let file_len = file.metadata()?.len();
+    let mut ofs = 0;
+    loop {
+        if ofs >= file_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "No `moov` box found",
+            ));
+        }
+        let mut header = [0; 8];
+        file.seek(SeekFrom::Start(ofs))?;
+        file.read_exact(&mut header)?;
+        let size = u64::from(u32::from_be_bytes(header[..4].try_into().unwrap()));
+        if &header[4..] == b"moov" {
+            if ofs + size != file_len {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "The `moov` box is not at the end of the file",
+                ));
+            }
+            return Ok(ofs);
+        }
+        if size < 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Bad box size {} at offset {}", size, ofs),
+            ));
+        }
+        ofs += size;
+    }
+}
+
+/// Where the payload of the first child box of this type starts and ends, within a box's payload.
+fn find_child(payload: &[u8], box_type: &[u8; 4]) -> Option<(usize, usize)> {
+    let mut ofs = 0;
+    while ofs + 8 <= payload.len() {
+        let size = u32::from_be_bytes(payload[ofs..ofs + 4].try_into().unwrap()) as usize;
+        if size < 8 || ofs + size > payload.len() {
+            return None;
+        }
+        if &payload[ofs + 4..ofs + 8] == box_type {
+            return Some((ofs + 8, ofs + size));
+        }
+        ofs += size;
+    }
+    None
+}
+
+/// Adds a track of 16-bit mono PCM audio to an MP4 file that was just written, starting along
+/// with the video.
+pub fn add_audio_track(path: &Path, sample_rate: u32, samples: &[i16]) -> io::Result<()> {
+    /// How many samples are stored together, which is how much players read at once.
+    const CHUNK_LEN: usize = 48000;
+
+    let bad = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let moov_ofs = find_moov(&mut file)?;
+    let mut moov = Vec::new();
+    file.seek(SeekFrom::Start(moov_ofs + 8))?;
+    file.read_to_end(&mut moov)?;
+
+    // The track takes the movie's timescale for its duration, and the ID after the others'; the
+    // writer leaves the movie's next track ID at 0, so the tracks' own IDs are looked at instead
+    let (mvhd_start, mvhd_end) = find_child(&moov, b"mvhd").ok_or_else(|| bad("No `mvhd` box"))?;
+    let mvhd = &moov[mvhd_start..mvhd_end];
+    // Both fields are right after the times, which version 1 makes 64-bit
+    let times_end = |header: &[u8]| if header.first() == Some(&1) { 20 } else { 12 };
+    let timescale_ofs = times_end(mvhd);
+    let timescale = mvhd
+        .get(timescale_ofs..timescale_ofs + 4)
+        .ok_or_else(|| bad("`mvhd` box too short"))?;
+    let timescale = u32::from_be_bytes(timescale.try_into().unwrap());
+    let mut track_id = 1;
+    let mut ofs = 0;
+    while let Some((start, end)) = find_child(&moov[ofs..], b"trak") {
+        let trak = &moov[ofs + start..ofs + end];
+        let (tkhd_start, tkhd_end) =
+            find_child(trak, b"tkhd").ok_or_else(|| bad("No `tkhd` box"))?;
+        let tkhd = &trak[tkhd_start..tkhd_end];
+        let id_ofs = times_end(tkhd);
+        let id = tkhd
+            .get(id_ofs..id_ofs + 4)
+            .ok_or_else(|| bad("`tkhd` box too short"))?;
+        track_id = cmp::max(track_id, u32::from_be_bytes(id.try_into().unwrap()) + 1);
+        ofs += end;
+    }
+    moov[mvhd_end - 4..mvhd_end].copy_from_slice(&(track_id + 1).to_be_bytes());
+
+    // The samples go in a `mdat` box of their own, where the `moov` box was
+    let data: Vec<u8> = samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect();
+    let mdat_header = match u32::try_from(data.len() + 8) {
+        Ok(size) => [&size.to_be_bytes()[..], b"mdat"].concat(),
+        Err(_) => [
+            &1_u32.to_be_bytes()[..],
+            b"mdat",
+            &(data.len() as u64 + 16).to_be_bytes(),
+        ]
+        .concat(),
+    };
+    let data_ofs = moov_ofs + mdat_header.len() as u64;
+    let chunk_ofs: Vec<u64> = (0..samples.len())
+        .step_by(CHUNK_LEN)
+        .map(|i| data_ofs + i as u64 * 2)
+        .collect();
+
+    let nb_samples = samples.len() as u64;
+    let duration = u128::from(nb_samples) * u128::from(timescale) / u128::from(sample_rate);
+    let duration: u64 = duration
+        .try_into()
+        .map_err(|_| bad("Click track too long"))?;
+    let nb_samples_u32: u32 = nb_samples
+        .try_into()
+        .map_err(|_| bad("Click track too long"))?;
+    // Times are 64-bit (version 1), so that they fit however long the video is
+    let matrix: Vec<u8> = [0x0001_0000_u32, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000]
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect();
+    let tkhd = mp4_box(
+        b"tkhd",
+        &[
+            // Version & flags (enabled, in the movie), creation and modification times
+            &[1, 0, 0, 3][..],
+            &[0; 16],
+            &track_id.to_be_bytes(),
+            &[0; 4],
+            &duration.to_be_bytes(),
+            // Reserved, layer, alternate group, volume (full), reserved
+            &[0; 8],
+            &[0, 0, 0, 0, 1, 0, 0, 0],
+            &matrix,
+            // No width nor height
+            &[0; 8],
+        ]
+        .concat(),
+    );
+    let mdhd = mp4_box(
+        b"mdhd",
+        &[
+            &[1, 0, 0, 0][..],
+            &[0; 16],
+            &sample_rate.to_be_bytes(),
+            &nb_samples.to_be_bytes(),
+            // "und", as 5-bit letters
+            &0x55c4_u16.to_be_bytes(),
+            &[0; 2],
+        ]
+        .concat(),
+    );
+    let hdlr = mp4_box(
+        b"hdlr",
+        &[&[0; 8][..], b"soun", &[0; 12], b"Click track\0"].concat(),
+    );
+    let smhd = mp4_box(b"smhd", &[0; 8]);
+    // The samples are in this file, which a `url ` box without a URL means
+    let dref = mp4_box(
+        b"dref",
+        &[
+            &[0, 0, 0, 0, 0, 0, 0, 1][..],
+            &mp4_box(b"url ", &[0, 0, 0, 1]),
+        ]
+        .concat(),
+    );
+    let dinf = mp4_box(b"dinf", &dref);
+    // Little-endian 16-bit PCM, as QuickTime names it
+    let sowt = mp4_box(
+        b"sowt",
+        &[
+            // Reserved, data reference index, then reserved for QuickTime's version and vendor
+            &[0, 0, 0, 0, 0, 0, 0, 1][..],
+            &[0; 8],
+            // Channels, bits per sample, and the 16.16 sample rate
+            &1_u16.to_be_bytes(),
+            &16_u16.to_be_bytes(),
+            &[0; 4],
+            &(sample_rate << 16).to_be_bytes(),
+        ]
+        .concat(),
+    );
+    let stsd = mp4_box(b"stsd", &[&[0, 0, 0, 0, 0, 0, 0, 1][..], &sowt].concat());
+    let stts = mp4_box(
+        b"stts",
+        &[
+            &[0, 0, 0, 0, 0, 0, 0, 1][..],
+            &nb_samples_u32.to_be_bytes(),
+            &1_u32.to_be_bytes(),
+        ]
+        .concat(),
+    );
+    // Every chunk is full, except maybe the last one
+    let (nb_full_chunks, remainder) = (samples.len() / CHUNK_LEN, samples.len() % CHUNK_LEN);
+    let mut runs = Vec::new();
+    if nb_full_chunks != 0 {
+        runs.push((1, CHUNK_LEN));
+    }
+    if remainder != 0 {
+        runs.push((nb_full_chunks + 1, remainder));
+    }
+    let mut stsc = vec![0, 0, 0, 0];
+    stsc.extend_from_slice(&(runs.len() as u32).to_be_bytes());
+    for (first_chunk, nb_samples) in runs {
+        stsc.extend_from_slice(&(first_chunk as u32).to_be_bytes());
+        stsc.extend_from_slice(&(nb_samples as u32).to_be_bytes());
+        stsc.extend_from_slice(&1_u32.to_be_bytes());
+    }
+    let stsc = mp4_box(b"stsc", &stsc);
+    let stsz = mp4_box(
+        b"stsz",
+        &[
+            &[0; 4][..],
+            &2_u32.to_be_bytes(),
+            &nb_samples_u32.to_be_bytes(),
+        ]
+        .concat(),
+    );
+    // Offsets past 4 GiB need 64 bits
+    let mut offsets = vec![0, 0, 0, 0];
+    offsets.extend_from_slice(&(chunk_ofs.len() as u32).to_be_bytes());
+    let wide = chunk_ofs
+        .last()
+        .is_some_and(|&ofs| ofs > u64::from(u32::MAX));
+    for &ofs in &chunk_ofs {
+        if wide {
+            offsets.extend_from_slice(&ofs.to_be_bytes());
+        } else {
+            offsets.extend_from_slice(&(ofs as u32).to_be_bytes());
+        }
+    }
+    let offsets = mp4_box(if wide { b"co64" } else { b"stco" }, &offsets);
+    let stbl = mp4_box(b"stbl", &[stsd, stts, stsc, stsz, offsets].concat());
+    let minf = mp4_box(b"minf", &[smhd, dinf, stbl].concat());
+    let mdia = mp4_box(b"mdia", &[mdhd, hdlr, minf].concat());
+    moov.extend_from_slice(&mp4_box(b"trak", &[tkhd, mdia].concat()));
+
+    file.set_len(moov_ofs)?;
+    file.seek(SeekFrom::Start(moov_ofs))?;
+    file.write_all(&mdat_header)?;
+    file.write_all(&data)?;
+    let moov_size: u32 = (moov.len() + 8)
+        .try_into()
+        .map_err(|_| bad("`moov` box too large"))?;
+    file.write_all(&moov_size.to_be_bytes())?;
+    file.write_all(b"moov")?;
+    file.write_all(&moov)?;
+    file.flush()
+}
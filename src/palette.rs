@@ -0,0 +1,81 @@
+//! Colors picked for sections by name, from a file.
+//!
+//! Each line of the file is a pattern, an `=`, and a color as `#rrggbb`, e.g. `Audio* = #3366cc`.
+//! Patterns are globs (`*` matching any characters, `?` any single one), or regexes if wrapped in
+//! slashes, e.g. `/^(Audio|SFX)/`; either way, they must match the whole name. Blank lines and
+//! lines starting with `;` are ignored.
+
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default)]
+pub struct Palette {
+    /// Tried in order, the first match winning.
+    entries: Vec<(Regex, (u8, u8, u8))>,
+}
+
+impl Palette {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let entries = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                let line = line.trim();
+                !line.is_empty() && !line.starts_with(';')
+            })
+            .map(|(i, line)| parse_entry(line).map_err(|err| format!("line {}: {}", i + 1, err)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { entries })
+    }
+
+    /// The color of the first pattern matching the name, if any.
+    pub fn color(&self, name: &str) -> Option<(u8, u8, u8)> {
+        self.entries
+            .iter()
+            .find(|(regex, _)| regex.is_match(name))
+            .map(|&(_, color)| color)
+    }
+}
+
+fn parse_entry(line: &str) -> Result<(Regex, (u8, u8, u8)), String> {
+    // The pattern may contain `=`, but the color may not
+    let (pattern, color) = line
+        .rsplit_once('=')
+        .ok_or_else(|| "expected `<pattern> = #rrggbb`".to_string())?;
+    let (pattern, color) = (pattern.trim(), color.trim());
+    if pattern.is_empty() {
+        return Err("empty pattern".to_string());
+    }
+
+    let regex = match pattern
+        .strip_prefix('/')
+        .and_then(|pattern| pattern.strip_suffix('/'))
+    {
+        Some(regex) => format!("^(?:{})$", regex),
+        None => {
+            let glob: String = pattern
+                .split('*')
+                .map(|part| {
+                    part.split('?')
+                        .map(regex::escape)
+                        .collect::<Vec<_>>()
+                        .join(".")
+                })
+                .collect::<Vec<_>>()
+                .join(".*");
+            format!("^{}$", glob)
+        }
+    };
+    let regex = Regex::new(&regex).map_err(|err| format!("bad pattern: {}", err))?;
+
+    let color = match color.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+            (channel(0), channel(2), channel(4))
+        }
+        _ => return Err(format!("bad color {:?}, expected `#rrggbb`", color)),
+    };
+    Ok((regex, color))
+}
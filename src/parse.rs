@@ -1,10 +1,13 @@
-use crate::{Frame, Location, MemType, Section, Sequence};
+use crate::warning::Warning;
+use crate::{Frame, FrameKind, Location, MemType, Region, Section, Sequence};
 use lazy_static::lazy_static;
 use parse_display::Display;
 use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
-use std::io;
+use std::io::{self, BufRead, Read};
 use std::num::ParseIntError;
 use std::str::FromStr;
 
@@ -34,52 +37,308 @@ pub enum SectionParseError {
     BadSize(ParseIntError),
 }
 
-type AttemptParseError = LocationParseError;
+pub type AttemptParseError = LocationParseError;
+
+#[derive(Debug, Display)]
+#[display(style = "Title case")]
+pub enum CsvRowParseError {
+    #[display("Expected 5 fields, found {0}")]
+    WrongFieldCount(usize),
+    #[display("{}: {0}")]
+    BadType(parse_display::ParseError),
+    #[display("{}: {0}")]
+    BadBank(ParseIntError),
+    #[display("{}: {0}")]
+    BadAddr(ParseIntError),
+    #[display("{}: {0}")]
+    BadSize(ParseIntError),
+}
+
+/// What to do when a section is defined again, with the same name as an earlier one, e.g. in logs
+/// of several runs that got concatenated.
+#[derive(parse_display::FromStr, Debug, Clone, Copy, PartialEq, Eq)]
+#[display(style = "lowercase")]
+pub enum OnDuplicate {
+    /// Keep the first definition, and consider what follows the new one to be about it.
+    Merge,
+    /// Forget the earlier section, along with its attempts, and keep the new one.
+    Last,
+    /// Reject the log.
+    Error,
+}
+
+/// Which kind of input is being read.
+#[derive(parse_display::FromStr, Debug, Clone, Copy, PartialEq, Eq)]
+#[display(style = "lowercase")]
+pub enum InputFormat {
+    /// The patched RGBLINK's packing log.
+    Trace,
+    /// A list of already-placed sections, one per row.
+    Csv,
+}
 
 #[derive(Debug)]
-pub enum ParseError {
+pub struct ParseError {
+    kind: ParseErrorKind,
+    format_guess: Option<LogFormat>,
+}
+
+/// What went wrong while parsing; line numbers count from 1, and come with the offending line.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
     Io(io::Error),
     AttemptBeforeSection(u64, String),
+    /// No sections were defined, but `--skip-orphan-attempts` counted this many attempts, on the
+    /// lines given (only the first few).
+    NoSections(u64, Vec<u64>),
     BadSection(SectionParseError, u64, String),
     BadAttempt(AttemptParseError, u64, String),
+    BadPlacement(AttemptParseError, u64, String),
+    BadRemoval(AttemptParseError, u64, String),
+    /// Nothing was placed at the location being removed from.
+    RemovalOfNothing(u64, String),
+    /// No section has the name that an attempt gives, with two passes.
+    UnknownSection(u64, String),
+    /// Several sections have the name that an attempt gives, with two passes.
+    AmbiguousSection(u64, String),
+    /// A section has the same name as the one defined earlier on the given line.
+    DuplicateSection(u64, u64, String),
+    /// The address is not one that the section's memory type spans.
+    AddrOutOfRange(MemType, u64, String),
+    /// The bank is non-zero, but the section's memory type is not banked.
+    BankNotZero(MemType, u64, String),
+    /// The line is longer than the maximum, which is given.
+    LineTooLong(u64, usize),
+    Csv(csv::Error),
+    BadCsvHeader(String),
+    BadCsvRow(CsvRowParseError, u64, String),
+}
+
+/// What the input looks like, based on its first few lines; used to hint at format mismatches.
+#[derive(Debug, Display, PartialEq, Eq)]
+pub enum LogFormat {
+    #[display("this looks like packing output from a patched RGBLINK 0.5.x, but it may be truncated or mixed with other output")]
+    Trace,
+    #[display("this looks like an RGBLINK map file (`-m`), not a packing log; see `link-logs.patch` for how to generate one")]
+    MapFile,
+    #[display("this looks like an RGBLINK symbol file (`-n`), not a packing log; see `link-logs.patch` for how to generate one")]
+    SymFile,
 }
 
-impl From<io::Error> for ParseError {
+impl LogFormat {
+    /// How many non-empty lines are inspected to guess the format.
+    const NB_LINES: usize = 8;
+
+    fn guess<S: AsRef<str>>(lines: &[S]) -> Option<Self> {
+        lazy_static! {
+            static ref MAP_RE: Regex = Regex::new(
+                r"^(?:[A-Z0-9]+ bank #[0-9]+:|SECTION: \$|EMPTY: \$|(?:TOTAL )?EMPTY:|SUMMARY:)"
+            )
+            .unwrap();
+            static ref SYM_RE: Regex = Regex::new(
+                r"^(?:; File generated by rgblink|[[:xdigit:]]{2}:[[:xdigit:]]{4} [^[:blank:]]+$)"
+            )
+            .unwrap();
+            static ref SECTION_RE: Regex =
+                Regex::new(r"^\[[A-Z0-9]+ @ [[:xdigit:]]+:[[:xdigit:]]+ & ").unwrap();
+        }
+
+        let count = |re: &Regex| {
+            lines
+                .iter()
+                .filter(|line| re.is_match(line.as_ref().trim_start()))
+                .count()
+        };
+        let (map, sym, section) = (count(&MAP_RE), count(&SYM_RE), count(&SECTION_RE));
+
+        if map != 0 && map >= sym {
+            Some(Self::MapFile)
+        } else if sym != 0 && section == 0 {
+            Some(Self::SymFile)
+        } else if section != 0 {
+            Some(Self::Trace)
+        } else {
+            None
+        }
+    }
+}
+
+impl ParseError {
+    /// What went wrong, to react to it without going through its message.
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+
+    /// What the input looks like instead, if its contents suggest it's not a packing log.
+    pub fn format_guess(&self) -> Option<&LogFormat> {
+        self.format_guess.as_ref()
+    }
+}
+
+impl ParseErrorKind {
+    /// Whether the error only concerns a single line, which can be skipped over.
+    fn is_line_error(&self) -> bool {
+        match self {
+            ParseErrorKind::AttemptBeforeSection(..)
+            | ParseErrorKind::BadSection(..)
+            | ParseErrorKind::BadAttempt(..)
+            | ParseErrorKind::BadPlacement(..)
+            | ParseErrorKind::BadRemoval(..)
+            | ParseErrorKind::RemovalOfNothing(..)
+            | ParseErrorKind::UnknownSection(..)
+            | ParseErrorKind::AmbiguousSection(..)
+            | ParseErrorKind::AddrOutOfRange(..)
+            | ParseErrorKind::BankNotZero(..) => true,
+            ParseErrorKind::Io(_)
+            | ParseErrorKind::NoSections(..)
+            | ParseErrorKind::DuplicateSection(..)
+            | ParseErrorKind::LineTooLong(..)
+            | ParseErrorKind::Csv(_)
+            | ParseErrorKind::BadCsvHeader(..)
+            | ParseErrorKind::BadCsvRow(..) => false,
+        }
+    }
+}
+
+impl From<io::Error> for ParseErrorKind {
     fn from(err: io::Error) -> Self {
-        ParseError::Io(err)
+        ParseErrorKind::Io(err)
+    }
+}
+
+impl From<csv::Error> for ParseErrorKind {
+    fn from(err: csv::Error) -> Self {
+        ParseErrorKind::Csv(err)
     }
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Self::Io(err) => write!(fmt, "I/O error: {}", err),
-            Self::AttemptBeforeSection(line_no, line) => write!(
+        match self.kind() {
+            ParseErrorKind::Io(err) => write!(fmt, "I/O error: {}", err),
+            ParseErrorKind::AttemptBeforeSection(line_no, line) => write!(
                 fmt,
                 "Location attempt before any sections on line {} ({})",
                 line_no, line
             ),
-            Self::BadSection(err, line_no, line) => {
+            ParseErrorKind::NoSections(nb, line_nos) => write!(
+                fmt,
+                "No sections defined, but {} location attempt(s) were found, on line(s) {}",
+                nb,
+                list_line_nos(*nb, line_nos)
+            ),
+            ParseErrorKind::BadSection(err, line_no, line) => {
                 write!(fmt, "Bad section on line {}: {} ({})", line_no, err, line)
             }
-            Self::BadAttempt(err, line_no, line) => write!(
+            ParseErrorKind::BadAttempt(err, line_no, line) => write!(
                 fmt,
                 "Bad location attempt on line {}: {} ({})",
                 line_no, err, line
             ),
+            ParseErrorKind::BadPlacement(err, line_no, line) => {
+                write!(fmt, "Bad placement on line {}: {} ({})", line_no, err, line)
+            }
+            ParseErrorKind::BadRemoval(err, line_no, line) => {
+                write!(fmt, "Bad removal on line {}: {} ({})", line_no, err, line)
+            }
+            ParseErrorKind::RemovalOfNothing(line_no, line) => write!(
+                fmt,
+                "Removal on line {} from where no section was placed ({})",
+                line_no, line
+            ),
+            ParseErrorKind::UnknownSection(line_no, line) => write!(
+                fmt,
+                "Attempt on line {} names no known section ({})",
+                line_no, line
+            ),
+            ParseErrorKind::AmbiguousSection(line_no, line) => write!(
+                fmt,
+                "Attempt on line {} names a section, but several have that name ({})",
+                line_no, line
+            ),
+            ParseErrorKind::DuplicateSection(line_no, first_line_no, line) => write!(
+                fmt,
+                "Section on line {} has the same name as the one on line {} ({})",
+                line_no, first_line_no, line
+            ),
+            ParseErrorKind::AddrOutOfRange(mem_type, line_no, line) => {
+                let range = mem_type.addr_range();
+                write!(
+                    fmt,
+                    "Address on line {} is outside of {} (${:04x}-${:04x}) ({})",
+                    line_no,
+                    mem_type,
+                    range.start(),
+                    range.end(),
+                    line
+                )
+            }
+            ParseErrorKind::BankNotZero(mem_type, line_no, line) => write!(
+                fmt,
+                "Bank on line {} is not 0, but {} is not banked ({})",
+                line_no, mem_type, line
+            ),
+            ParseErrorKind::LineTooLong(line_no, max_len) => write!(
+                fmt,
+                "Line {} is longer than the maximum of {} bytes",
+                line_no, max_len
+            ),
+            ParseErrorKind::Csv(err) => write!(fmt, "CSV error: {}", err),
+            ParseErrorKind::BadCsvHeader(header) => write!(
+                fmt,
+                "Bad CSV header ({}), expected \"{}\"",
+                header,
+                CSV_HEADER.join(",")
+            ),
+            ParseErrorKind::BadCsvRow(err, line_no, row) => {
+                write!(fmt, "Bad CSV row on line {}: {} ({})", line_no, err, row)
+            }
+        }?;
+        match self.format_guess() {
+            Some(format) => write!(fmt, "\nHint: {}", format),
+            None => Ok(()),
         }
     }
 }
 
 impl Error for ParseError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        match self {
-            Self::Io(err) => Some(err),
-            Self::AttemptBeforeSection(..) | Self::BadSection(..) | Self::BadAttempt(..) => None,
+        match self.kind() {
+            ParseErrorKind::Io(err) => Some(err),
+            ParseErrorKind::Csv(err) => Some(err),
+            ParseErrorKind::AttemptBeforeSection(..)
+            | ParseErrorKind::NoSections(..)
+            | ParseErrorKind::BadSection(..)
+            | ParseErrorKind::BadAttempt(..)
+            | ParseErrorKind::BadPlacement(..)
+            | ParseErrorKind::BadRemoval(..)
+            | ParseErrorKind::RemovalOfNothing(..)
+            | ParseErrorKind::UnknownSection(..)
+            | ParseErrorKind::AmbiguousSection(..)
+            | ParseErrorKind::DuplicateSection(..)
+            | ParseErrorKind::AddrOutOfRange(..)
+            | ParseErrorKind::BankNotZero(..)
+            | ParseErrorKind::LineTooLong(..)
+            | ParseErrorKind::BadCsvHeader(..)
+            | ParseErrorKind::BadCsvRow(..) => None,
         }
     }
 }
 
+/// Lists the line numbers that were kept, out of how many there were in total.
+pub fn list_line_nos(nb: u64, line_nos: &[u64]) -> String {
+    let mut list = line_nos
+        .iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    if nb > line_nos.len() as u64 {
+        list.push_str(", ...");
+    }
+    list
+}
+
 impl FromStr for Location {
     type Err = LocationParseError;
 
@@ -99,50 +358,221 @@ impl FromStr for Section {
 
     fn from_str(rest: &str) -> Result<Self, <Self as FromStr>::Err> {
         // Format: "type @ bank:addr & algn_mask + ofs ] size name..." (only one space before name)
-        lazy_static! {
-            static ref RE: Regex = Regex::new(
-                r"(?x)
-                    ^([^[:blank:]@]+)
-                    [[:blank:]]*@[[:blank:]]*([^[:blank:]&]+)
-                    [[:blank:]]*&[[:blank:]]*([^[:blank:]+]+)
-                    [[:blank:]]*\+[[:blank:]]*([^[:blank:]\]]+)
-                    [[:blank:]]*\][[:blank:]]*([^[:blank:]]+)
-                    [[:blank:]](.*)$"
-            )
-            .unwrap();
-        }
+        // Each field is consumed in turn, up to its delimiter, so that whatever remains is the
+        // name, verbatim; that way, it may contain anything, including the delimiters.
+        let is_blank = |c: char| c == ' ' || c == '\t';
+        let mut rest = rest;
+        let mut field = |delim: char| {
+            let (field, after) = rest
+                .split_once(delim)
+                .ok_or(SectionParseError::SyntaxError)?;
+            rest = after;
+            match field.trim_matches(is_blank) {
+                field if field.is_empty() || field.contains(is_blank) => {
+                    Err(SectionParseError::SyntaxError)
+                }
+                field => Ok(field),
+            }
+        };
+        let mem_type = field('@')?;
+        let location = field('&')?;
+        let align_mask = field('+')?;
+        let align_ofs = field(']')?;
+        let (size, name) = rest
+            .trim_start_matches(is_blank)
+            .split_once(is_blank)
+            .filter(|(size, _)| !size.is_empty())
+            .ok_or(SectionParseError::SyntaxError)?;
 
-        let captures = RE.captures(rest).ok_or(SectionParseError::SyntaxError)?;
         Ok(Self {
-            mem_type: captures[1].parse().map_err(SectionParseError::BadType)?,
-            location: captures[2]
-                .parse()
-                .map_err(SectionParseError::BadLocation)?,
-            align_mask: u16::from_str_radix(&captures[3], 16)
+            mem_type: mem_type.parse().map_err(SectionParseError::BadType)?,
+            location: location.parse().map_err(SectionParseError::BadLocation)?,
+            align_mask: u16::from_str_radix(align_mask, 16)
                 .map_err(SectionParseError::BadAlignMask)?,
-            align_ofs: u16::from_str_radix(&captures[4], 16)
+            align_ofs: u16::from_str_radix(align_ofs, 16)
                 .map_err(SectionParseError::BadAlignOfs)?,
-            size: (&captures[5]).parse().map_err(SectionParseError::BadSize)?,
-            name: captures[6].to_string(),
+            size: size.parse().map_err(SectionParseError::BadSize)?,
+            name: name.to_string(),
         })
     }
 }
 
-pub fn parse_input() -> Result<Sequence, ParseError> {
+/// Checks that a section is placed within its memory type, and in bank 0 if it's not banked, as
+/// anything else means that the log is malformed; such locations are warned about.
+fn check_location(
+    section: &Section,
+    location: &Location,
+    line_no: u64,
+    line: &str,
+    warnings: &mut Vec<Warning>,
+) {
+    check_bank(section, location, line_no, line, warnings);
+    if !section.mem_type.addr_range().contains(&location.addr) {
+        warn(
+            ParseErrorKind::AddrOutOfRange(section.mem_type, line_no, line.to_string()),
+            warnings,
+        );
+    }
+}
+
+/// Only checks the bank, e.g. for sections' fixed locations, whose address may be floating.
+fn check_bank(
+    section: &Section,
+    location: &Location,
+    line_no: u64,
+    line: &str,
+    warnings: &mut Vec<Warning>,
+) {
+    if !(section.mem_type.is_banked() || location.bank == 0 || location.is_floating_bank()) {
+        warn(
+            ParseErrorKind::BankNotZero(section.mem_type, line_no, line.to_string()),
+            warnings,
+        );
+    }
+}
+
+fn warn(kind: ParseErrorKind, warnings: &mut Vec<Warning>) {
+    warnings.push(Warning::Location(ParseError {
+        kind,
+        format_guess: None,
+    }));
+}
+
+/// The default for how long lines can be, in bytes, which keeps garbage input from eating memory.
+pub const DEFAULT_MAX_LINE_LEN: usize = 64 * 1024;
+
+/// How to parse a log.
+#[derive(Debug, Clone, Copy)]
+pub struct ParseOptions {
+    /// How long lines can be, in bytes.
+    pub max_line_len: usize,
+    /// If set, up to that many malformed lines are skipped (with a warning) instead of being
+    /// rejected.
+    pub max_skipped: Option<u64>,
+    /// Whether attempts before any section are counted instead of being rejected, and only
+    /// reported together at the end; if no section was defined at all, the log is rejected then.
+    pub skip_orphans: bool,
+    /// Whether sections are all read first, wherever they are in the log, so that attempts may
+    /// then name the section they are for (see `parse_two_pass`).
+    pub two_pass: bool,
+    /// Without `two_pass`, what to do with sections defined again; by default, they're just kept
+    /// alongside the earlier ones.
+    pub on_duplicate: Option<OnDuplicate>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            max_line_len: DEFAULT_MAX_LINE_LEN,
+            max_skipped: None,
+            skip_orphans: false,
+            two_pass: false,
+            on_duplicate: None,
+        }
+    }
+}
+
+/// Parses a log, adding anything suspicious about it to `warnings`.
+pub fn parse_input<R: BufRead>(
+    input: R,
+    options: &ParseOptions,
+    warnings: &mut Vec<Warning>,
+) -> Result<Sequence, ParseError> {
+    parse_input_live(input, options, warnings, |_| {})
+}
+
+/// Like `parse_input`, but also lets `progress` look at the sequence parsed so far after each line,
+/// and whenever the input reports that it would block.
+pub fn parse_input_live<R: BufRead>(
+    input: R,
+    options: &ParseOptions,
+    warnings: &mut Vec<Warning>,
+    mut progress: impl FnMut(&Sequence),
+) -> Result<Sequence, ParseError> {
     eprint!("Parsing input...\r");
 
-    let mut nb_banks = 2;
-    let mut frames = Vec::new();
-    let mut sections = Vec::new();
+    let mut head = Vec::with_capacity(LogFormat::NB_LINES);
+    let sequence =
+        parse_lines(input, options, warnings, &mut head, &mut progress).map_err(|kind| {
+            // I/O errors and duplicates have nothing to do with the input's format
+            let format_guess = match kind {
+                ParseErrorKind::Io(_)
+                | ParseErrorKind::LineTooLong(..)
+                | ParseErrorKind::DuplicateSection(..) => None,
+                _ => LogFormat::guess(&head),
+            };
+            ParseError { kind, format_guess }
+        })?;
+
+    eprintln!("Parsing input - Done.");
+    Ok(sequence)
+}
+
+/// Parses the input, keeping a copy of the first few non-empty lines in `head`.
+fn parse_lines<R: BufRead>(
+    mut input: R,
+    options: &ParseOptions,
+    warnings: &mut Vec<Warning>,
+    head: &mut Vec<String>,
+    progress: &mut dyn FnMut(&Sequence),
+) -> Result<Sequence, ParseErrorKind> {
+    let ParseOptions {
+        max_line_len,
+        max_skipped,
+        skip_orphans,
+        two_pass,
+        on_duplicate,
+    } = *options;
+    let mut nb_skipped = 0;
+    let mut orphans = Orphans {
+        enabled: skip_orphans,
+        nb: 0,
+        line_nos: Vec::new(),
+    };
+    let mut definitions = Definitions {
+        on_duplicate,
+        by_name: HashMap::new(),
+        current: None,
+        skipped: None,
+        forgotten: HashSet::new(),
+    };
+    let mut placed = Placed::default();
+    // With two passes, lines are only kept for now
+    let mut lines = Vec::new();
+    let mut sequence = Sequence {
+        nb_banks: Region::ALL.map(Region::min_nb_banks),
+        frames: Vec::new(),
+        sections: Vec::new(),
+        has_placements: false,
+    };
 
-    let stdin = io::stdin();
-    let mut line = String::new();
+    let mut line = Vec::new();
     let mut line_no = 0;
     while {
         line.clear();
-        stdin.read_line(&mut line)? != 0
+        // Read one byte past the limit, so that we can tell whether it was reached
+        let limit = u64::try_from(max_line_len)
+            .unwrap_or(u64::MAX)
+            .saturating_add(1);
+        loop {
+            let limit = limit.saturating_sub(line.len() as u64);
+            match input.by_ref().take(limit).read_until(b'\n', &mut line) {
+                // Live input that has nothing new for now; what was read so far is kept
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => progress(&sequence),
+                result => {
+                    result?;
+                    break;
+                }
+            }
+        }
+        !line.is_empty()
     } {
         line_no += 1;
+        if line.len() > max_line_len && line.last() != Some(&b'\n') {
+            return Err(ParseErrorKind::LineTooLong(line_no, max_line_len));
+        }
+        let line = std::str::from_utf8(&line)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
 
         // Ignore leading whitespace (but not trailing, as it might be significant)
         let line = line.trim_start();
@@ -152,51 +582,922 @@ pub fn parse_input() -> Result<Sequence, ParseError> {
         if line.is_empty() {
             continue;
         }
+        if head.len() < LogFormat::NB_LINES {
+            head.push(line.to_string());
+        }
+
+        if two_pass {
+            lines.push((line_no, line.to_string()));
+            continue;
+        }
+        if let Err(kind) = parse_line(
+            line,
+            line_no,
+            &mut sequence,
+            &mut definitions,
+            &mut placed,
+            warnings,
+        )
+        .or_else(|kind| orphans.count(kind))
+        {
+            let is_section = matches!(kind, ParseErrorKind::BadSection(..));
+            skip_line(kind, max_skipped, &mut nb_skipped, warnings)?;
+            if is_section {
+                definitions.skip_section(line_no, warnings);
+            }
+        }
+        progress(&sequence);
+    }
+    definitions.end_skip(warnings);
+    definitions.remove_forgotten(&mut sequence);
+
+    if two_pass {
+        parse_two_pass(
+            &lines,
+            &mut sequence,
+            max_skipped,
+            &mut nb_skipped,
+            &mut orphans,
+            warnings,
+        )?;
+        progress(&sequence);
+    }
+    orphans.report(&sequence, warnings)?;
+    Ok(sequence)
+}
+
+/// How many attempts before any section should be kept in their report, at most.
+const MAX_ORPHAN_LINES: usize = 5;
 
-        match line.strip_prefix('[') {
-            // New section
-            Some(rest) => {
-                let section: Section = rest.parse().map_err(|err_type| {
-                    ParseError::BadSection(err_type, line_no, line.to_string())
-                })?;
+/// The attempts found before any section, when they are counted instead of being rejected.
+struct Orphans {
+    enabled: bool,
+    nb: u64,
+    /// The first few attempts' line numbers.
+    line_nos: Vec<u64>,
+}
 
-                sections.push(section);
+impl Orphans {
+    /// Counts the attempt if that's what the error is about, or gives the error back otherwise.
+    fn count(&mut self, kind: ParseErrorKind) -> Result<(), ParseErrorKind> {
+        match kind {
+            ParseErrorKind::AttemptBeforeSection(line_no, _) if self.enabled => {
+                self.nb += 1;
+                if self.line_nos.len() < MAX_ORPHAN_LINES {
+                    self.line_nos.push(line_no);
+                }
+                Ok(())
             }
+            kind => Err(kind),
+        }
+    }
 
-            // New attempt within a section
-            None => {
-                let location: Location = line.parse().map_err(|err_type| {
-                    ParseError::BadAttempt(err_type, line_no, line.to_string())
-                })?;
-                let section_id = sections
-                    .len()
-                    .checked_sub(1)
-                    .ok_or_else(|| ParseError::AttemptBeforeSection(line_no, line.to_string()))?;
+    /// Warns about the attempts that were counted, or rejects the log if it has nothing else.
+    fn report(
+        self,
+        sequence: &Sequence,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<(), ParseErrorKind> {
+        if self.nb == 0 {
+            Ok(())
+        } else if sequence.sections.is_empty() {
+            Err(ParseErrorKind::NoSections(self.nb, self.line_nos))
+        } else {
+            warnings.push(Warning::OrphanAttempts(self.nb, self.line_nos));
+            Ok(())
+        }
+    }
+}
 
-                let section = &sections[section_id];
-                match section.mem_type {
-                    MemType::Romx => {
-                        if location.bank >= nb_banks {
-                            nb_banks = location.bank.next_power_of_two();
-                        }
+/// Skips a line that failed to parse, if `max_skipped` allows it, and fails otherwise.
+fn skip_line(
+    kind: ParseErrorKind,
+    max_skipped: Option<u64>,
+    nb_skipped: &mut u64,
+    warnings: &mut Vec<Warning>,
+) -> Result<(), ParseErrorKind> {
+    match max_skipped {
+        Some(max) if kind.is_line_error() && *nb_skipped < max => {
+            warnings.push(Warning::SkippedLine(ParseError {
+                kind,
+                format_guess: None,
+            }));
+            *nb_skipped += 1;
+            Ok(())
+        }
+        Some(_) if kind.is_line_error() => {
+            eprintln!("Giving up after skipping {} malformed line(s)", nb_skipped);
+            Err(kind)
+        }
+        _ => Err(kind),
+    }
+}
+
+/// Parses the lines in two passes: first all of the sections, then everything else.
+///
+/// Attempts and placements may then name the section they are for after their location, separated
+/// by a single blank (e.g. `01:4000 Tileset`), in which case they are for the section with that
+/// name, wherever it's defined. The others are for the section defined last before them, as usual,
+/// since the log doesn't tell otherwise; they're dropped if that section's line was skipped.
+fn parse_two_pass(
+    lines: &[(u64, String)],
+    sequence: &mut Sequence,
+    max_skipped: Option<u64>,
+    nb_skipped: &mut u64,
+    orphans: &mut Orphans,
+    warnings: &mut Vec<Warning>,
+) -> Result<(), ParseErrorKind> {
+    // Which line each section is defined on, in order, along with its ID, or `None` if the line
+    // was skipped
+    let mut section_lines = Vec::new();
+    for (line_no, line) in lines {
+        if let Some(rest) = line.strip_prefix('[') {
+            match rest.parse::<Section>() {
+                Ok(section) => {
+                    check_bank(&section, &section.location, *line_no, line, warnings);
+                    section_lines.push((*line_no, Some(sequence.sections.len())));
+                    sequence.sections.push(section);
+                }
+                Err(err_type) => {
+                    skip_line(
+                        ParseErrorKind::BadSection(err_type, *line_no, line.to_string()),
+                        max_skipped,
+                        nb_skipped,
+                        warnings,
+                    )?;
+                    section_lines.push((*line_no, None));
+                }
+            }
+        }
+    }
+    // How many attempts were dropped after each skipped section line, as they're for that
+    // unknown section
+    let mut nb_dropped = BTreeMap::new();
+    let mut placed = Placed::default();
+
+    // Names that several sections share can't tell which one is meant
+    let mut by_name: HashMap<String, Option<usize>> = HashMap::new();
+    for (section_id, section) in sequence.sections.iter().enumerate() {
+        by_name
+            .entry(section.name.clone())
+            .and_modify(|id| *id = None)
+            .or_insert(Some(section_id));
+    }
+
+    for (line_no, line) in lines {
+        if line.starts_with('[') {
+            continue;
+        }
+        let result = if line.starts_with('-') {
+            parse_removal(line, *line_no, sequence, &mut placed)
+        } else {
+            let (location, name) = match line.split_once([' ', '\t']) {
+                Some((location, name)) => (location, Some(name)),
+                None => (line.as_str(), None),
+            };
+            let section_id = match name {
+                Some(name) => match by_name.get(name) {
+                    Some(&Some(section_id)) => Ok(section_id),
+                    Some(None) => Err(ParseErrorKind::AmbiguousSection(*line_no, line.clone())),
+                    None => Err(ParseErrorKind::UnknownSection(*line_no, line.clone())),
+                },
+                None => match section_lines.iter().rfind(|(at, _)| at < line_no) {
+                    Some(&(_, Some(section_id))) => Ok(section_id),
+                    Some(&(at, None)) => {
+                        *nb_dropped.entry(at).or_insert(0) += 1;
+                        continue;
                     }
-                    MemType::Rom0 => (),
-                    _ => continue,
+                    None => Err(ParseErrorKind::AttemptBeforeSection(*line_no, line.clone())),
+                },
+            };
+            section_id.and_then(|section_id| {
+                parse_attempt(location, line, *line_no, section_id, sequence, warnings)
+            })
+        };
+        if let Err(kind) = result.or_else(|kind| orphans.count(kind)) {
+            skip_line(kind, max_skipped, nb_skipped, warnings)?;
+        }
+    }
+    for (line_no, nb) in nb_dropped {
+        warnings.push(Warning::SkippedAttempts(nb, line_no));
+    }
+    Ok(())
+}
+
+/// Where the sections read so far were defined, to tell when one is defined again.
+struct Definitions {
+    on_duplicate: Option<OnDuplicate>,
+    /// Each name's section, and the line it was defined on.
+    by_name: HashMap<String, (usize, u64)>,
+    /// The section that attempts are for, which is usually the latest one.
+    current: Option<usize>,
+    /// If the latest section line was skipped, which line it was, and how many attempts after it
+    /// were dropped, as they're for that unknown section.
+    skipped: Option<(u64, u64)>,
+    /// The sections replaced by a later definition; they are only removed once the whole log is
+    /// parsed, as removing each right away would renumber all the sections after it every time.
+    forgotten: HashSet<usize>,
+}
+
+impl Definitions {
+    /// Adds a section to the sequence, unless it's a duplicate that must be handled otherwise.
+    fn define(
+        &mut self,
+        section: Section,
+        line_no: u64,
+        line: &str,
+        sequence: &mut Sequence,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<(), ParseErrorKind> {
+        self.end_skip(warnings);
+        if let Some(&(earlier_id, earlier_line_no)) = self.by_name.get(&section.name) {
+            let kind = ParseErrorKind::DuplicateSection(line_no, earlier_line_no, line.to_string());
+            match self.on_duplicate {
+                Some(OnDuplicate::Error) => return Err(kind),
+                Some(OnDuplicate::Merge) => {
+                    warnings.push(Warning::DuplicateSection(
+                        ParseError {
+                            kind,
+                            format_guess: None,
+                        },
+                        self.on_duplicate,
+                    ));
+                    self.current = Some(earlier_id);
+                    return Ok(());
+                }
+                Some(OnDuplicate::Last) => {
+                    self.forgotten.insert(earlier_id);
+                }
+                None => {}
+            }
+            warnings.push(Warning::DuplicateSection(
+                ParseError {
+                    kind,
+                    format_guess: None,
+                },
+                self.on_duplicate,
+            ));
+        }
+
+        self.by_name
+            .insert(section.name.clone(), (sequence.sections.len(), line_no));
+        self.current = Some(sequence.sections.len());
+        sequence.sections.push(section);
+        Ok(())
+    }
+
+    /// Drops the attempts after a section line that was skipped, instead of crediting them to the
+    /// section before it.
+    fn skip_section(&mut self, line_no: u64, warnings: &mut Vec<Warning>) {
+        self.end_skip(warnings);
+        self.current = None;
+        self.skipped = Some((line_no, 0));
+    }
+
+    /// Reports how many attempts were dropped after the latest skipped section line, if any.
+    fn end_skip(&mut self, warnings: &mut Vec<Warning>) {
+        if let Some((line_no, nb)) = self.skipped.take() {
+            if nb != 0 {
+                warnings.push(Warning::SkippedAttempts(nb, line_no));
+            }
+        }
+    }
+
+    /// Removes the forgotten sections from the sequence, along with all of their frames.
+    fn remove_forgotten(&mut self, sequence: &mut Sequence) {
+        if self.forgotten.is_empty() {
+            return;
+        }
+        // The sections after them move down
+        let mut nb_kept = 0;
+        let new_ids: Vec<_> = (0..sequence.sections.len())
+            .map(|id| {
+                (!self.forgotten.contains(&id)).then(|| {
+                    nb_kept += 1;
+                    nb_kept - 1
+                })
+            })
+            .collect();
+        let mut ids = new_ids.iter();
+        sequence.sections.retain(|_| ids.next().unwrap().is_some());
+        sequence
+            .frames
+            .retain_mut(|frame| match new_ids[frame.section_id] {
+                Some(id) => {
+                    frame.section_id = id;
+                    true
                 }
+                None => false,
+            });
+        self.forgotten.clear();
+        sequence.has_placements = sequence
+            .frames
+            .iter()
+            .any(|frame| frame.kind == FrameKind::Placement);
+    }
+}
+
+/// Parses a single non-empty line, adding whatever it describes to the sequence.
+fn parse_line(
+    line: &str,
+    line_no: u64,
+    sequence: &mut Sequence,
+    definitions: &mut Definitions,
+    placed: &mut Placed,
+    warnings: &mut Vec<Warning>,
+) -> Result<(), ParseErrorKind> {
+    match line.strip_prefix('[') {
+        // New section
+        Some(rest) => {
+            let section: Section = rest.parse().map_err(|err_type| {
+                ParseErrorKind::BadSection(err_type, line_no, line.to_string())
+            })?;
+
+            check_bank(&section, &section.location, line_no, line, warnings);
+            definitions.define(section, line_no, line, sequence, warnings)?;
+        }
+
+        // A section being taken back out, which isn't necessarily the latest one
+        None if line.starts_with('-') => parse_removal(line, line_no, sequence, placed)?,
+
+        // Attempts for a section that was skipped can't be drawn
+        None if definitions.skipped.is_some() => definitions.skipped.as_mut().unwrap().1 += 1,
 
-                frames.push(Frame {
-                    location,
-                    section_id,
-                });
+        // New attempt within the latest section, or the section's final placement
+        None => {
+            let section_id = definitions
+                .current
+                .ok_or_else(|| ParseErrorKind::AttemptBeforeSection(line_no, line.to_string()))?;
+            parse_attempt(line, line, line_no, section_id, sequence, warnings)?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_removal(
+    line: &str,
+    line_no: u64,
+    sequence: &mut Sequence,
+    placed: &mut Placed,
+) -> Result<(), ParseErrorKind> {
+    let location: Location = line[1..]
+        .parse()
+        .map_err(|err_type| ParseErrorKind::BadRemoval(err_type, line_no, line.to_string()))?;
+    let section_id = placed
+        .last_at(&location, sequence)
+        .ok_or_else(|| ParseErrorKind::RemovalOfNothing(line_no, line.to_string()))?;
+
+    sequence.frames.push(Frame {
+        kind: FrameKind::Removal,
+        location,
+        section_id,
+    });
+    Ok(())
+}
+
+/// Which sections are placed at each location, for removals to find out what they take out
+/// without going through all of the frames every time.
+#[derive(Default)]
+struct Placed {
+    /// How many of the sequence's frames have been accounted for.
+    nb_frames: usize,
+    /// The sections placed at each location, and not removed from there since, in order; both
+    /// with attempts counting as placements and without, as which is right depends on whether the
+    /// log has placement lines, which any later line may change.
+    by_location: HashMap<Location, (Vec<usize>, Vec<usize>)>,
+}
+
+impl Placed {
+    /// The section that was last placed at this location, and hasn't been removed from there since.
+    fn last_at(&mut self, location: &Location, sequence: &Sequence) -> Option<usize> {
+        for frame in &sequence.frames[self.nb_frames..] {
+            let (attempted, placed) = self.by_location.entry(frame.location).or_default();
+            match frame.kind {
+                FrameKind::Placement => {
+                    attempted.push(frame.section_id);
+                    placed.push(frame.section_id);
+                }
+                FrameKind::Attempt => attempted.push(frame.section_id),
+                FrameKind::Removal => {
+                    for ids in [attempted, placed] {
+                        if let Some(i) = ids.iter().rposition(|&id| id == frame.section_id) {
+                            ids.remove(i);
+                        }
+                    }
+                }
             }
         }
+        self.nb_frames = sequence.frames.len();
+
+        let (attempted, placed) = self.by_location.get(location)?;
+        let ids = if sequence.has_placements {
+            placed
+        } else {
+            attempted
+        };
+        ids.last().copied()
+    }
+}
+
+/// Parses an attempt or placement of the given section, which `text` is the part of the line
+/// giving the location of.
+fn parse_attempt(
+    text: &str,
+    line: &str,
+    line_no: u64,
+    section_id: usize,
+    sequence: &mut Sequence,
+    warnings: &mut Vec<Warning>,
+) -> Result<(), ParseErrorKind> {
+    let (kind, location): (_, Location) = match text.strip_prefix('=') {
+        Some(rest) => (
+            FrameKind::Placement,
+            rest.parse().map_err(|err_type| {
+                ParseErrorKind::BadPlacement(err_type, line_no, line.to_string())
+            })?,
+        ),
+        None => (
+            FrameKind::Attempt,
+            text.parse().map_err(|err_type| {
+                ParseErrorKind::BadAttempt(err_type, line_no, line.to_string())
+            })?,
+        ),
+    };
+
+    let section = &sequence.sections[section_id];
+    check_location(section, &location, line_no, line, warnings);
+    grow_nb_banks(&mut sequence.nb_banks, section, &location);
+
+    sequence.has_placements |= kind == FrameKind::Placement;
+    sequence.frames.push(Frame {
+        kind,
+        location,
+        section_id,
+    });
+    Ok(())
+}
+
+/// Makes room for the bank a section is being placed in, if it's a banked one.
+fn grow_nb_banks(nb_banks: &mut [u32], section: &Section, location: &Location) {
+    if section.mem_type.is_banked() {
+        let nb_banks = &mut nb_banks[section.mem_type.region() as usize];
+        if location.bank >= *nb_banks {
+            *nb_banks = (location.bank + 1).next_power_of_two();
+        }
     }
+}
+
+/// The columns a CSV input must have, in order.
+const CSV_HEADER: [&str; 5] = ["type", "bank", "addr", "size", "name"];
+
+/// Parses a CSV list of sections, each of which is shown being placed at the given location.
+pub fn parse_csv<R: Read>(input: R, warnings: &mut Vec<Warning>) -> Result<Sequence, ParseError> {
+    eprint!("Parsing input...\r");
+
+    let sequence = parse_csv_rows(input, warnings).map_err(|kind| ParseError {
+        kind,
+        format_guess: None,
+    })?;
 
     eprintln!("Parsing input - Done.");
+    Ok(sequence)
+}
+
+fn parse_csv_rows<R: Read>(
+    input: R,
+    warnings: &mut Vec<Warning>,
+) -> Result<Sequence, ParseErrorKind> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(input);
+
+    let header = reader.headers()?;
+    if !header
+        .iter()
+        .map(str::to_ascii_lowercase)
+        .eq(CSV_HEADER.iter().copied())
+    {
+        return Err(ParseErrorKind::BadCsvHeader(
+            header.iter().collect::<Vec<_>>().join(","),
+        ));
+    }
+
+    let mut nb_banks = Region::ALL.map(Region::min_nb_banks);
+    let mut frames = Vec::new();
+    let mut sections = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line_no = record.position().map_or(0, csv::Position::line);
+        let bad_row = |err| {
+            ParseErrorKind::BadCsvRow(err, line_no, record.iter().collect::<Vec<_>>().join(","))
+        };
+
+        if record.len() != CSV_HEADER.len() {
+            return Err(bad_row(CsvRowParseError::WrongFieldCount(record.len())));
+        }
+        // Numbers are written the same way as in the trace: hex, except for the size
+        let location = Location {
+            bank: u32::from_str_radix(&record[1], 16)
+                .map_err(|err| bad_row(CsvRowParseError::BadBank(err)))?,
+            addr: u16::from_str_radix(&record[2], 16)
+                .map_err(|err| bad_row(CsvRowParseError::BadAddr(err)))?,
+        };
+        let section = Section {
+            mem_type: record[0]
+                .to_ascii_uppercase()
+                .parse()
+                .map_err(|err| bad_row(CsvRowParseError::BadType(err)))?,
+            location,
+            align_mask: 0,
+            align_ofs: 0,
+            size: record[3]
+                .parse()
+                .map_err(|err| bad_row(CsvRowParseError::BadSize(err)))?,
+            name: record[4].to_string(),
+        };
+
+        check_location(
+            &section,
+            &location,
+            line_no,
+            &record.iter().collect::<Vec<_>>().join(","),
+            warnings,
+        );
+        grow_nb_banks(&mut nb_banks, &section, &location);
+        // Show each section where it goes before placing it there
+        for kind in [FrameKind::Attempt, FrameKind::Placement] {
+            frames.push(Frame {
+                kind,
+                location,
+                section_id: sections.len(),
+            });
+        }
+        sections.push(section);
+    }
 
     Ok(Sequence {
         nb_banks,
         frames,
         sections,
+        has_placements: true,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(log: &str) -> Result<Sequence, ParseErrorKind> {
+        parse_input(log.as_bytes(), &Default::default(), &mut Vec::new()).map_err(|err| err.kind)
+    }
+
+    /// Parses the log with `--keep-going=<max_skipped>`, and `--two-pass` if asked to, also
+    /// returning the warnings.
+    fn parse_keep_going(
+        log: &str,
+        max_skipped: u64,
+        two_pass: bool,
+    ) -> Result<(Sequence, Vec<Warning>), ParseErrorKind> {
+        let mut warnings = Vec::new();
+        let sequence = parse_input(
+            log.as_bytes(),
+            &ParseOptions {
+                max_skipped: Some(max_skipped),
+                two_pass,
+                ..Default::default()
+            },
+            &mut warnings,
+        )
+        .map_err(|err| err.kind)?;
+        Ok((sequence, warnings))
+    }
+
+    /// Each frame's section's name, and its bank and address.
+    fn frames(sequence: &Sequence) -> Vec<(&str, u32, u16)> {
+        sequence
+            .frames
+            .iter()
+            .map(|frame| {
+                let section = &sequence.sections[frame.section_id];
+                let location = &frame.location;
+                (section.name.as_str(), location.bank, location.addr)
+            })
+            .collect()
+    }
+
+    /// The warnings reported while parsing the log, which must succeed.
+    fn warnings(log: &str) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        parse_input(log.as_bytes(), &Default::default(), &mut warnings).unwrap();
+        warnings
+    }
+
+    /// Parses the log, with lines of at most this many bytes.
+    fn parse_max_line_len(log: &str, max_line_len: usize) -> Result<Sequence, ParseErrorKind> {
+        let options = ParseOptions {
+            max_line_len,
+            ..Default::default()
+        };
+        parse_lines(
+            log.as_bytes(),
+            &options,
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &mut |_| {},
+        )
+    }
+
+    #[test]
+    fn lines_past_the_limit_are_too_long() {
+        // The section line is 32 bytes long, not counting its newline
+        let log = "[ROMX @ 01:ffff & 0 + 0] 16 ABCD\n01:4000\n";
+        assert!(parse_max_line_len(log, 32).is_ok());
+        assert!(matches!(
+            parse_max_line_len(log, 31),
+            Err(ParseErrorKind::LineTooLong(1, 31))
+        ));
+        // Even without a newline to end it
+        let log = format!(
+            "[ROMX @ 01:ffff & 0 + 0] 16 ABCD\n01:4000\n{}",
+            "~".repeat(33)
+        );
+        assert!(matches!(
+            parse_max_line_len(&log, 32),
+            Err(ParseErrorKind::LineTooLong(3, 32))
+        ));
+    }
+
+    #[test]
+    fn on_duplicate_last_keeps_the_last_definition() {
+        let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n\
+                   [ROMX @ 01:ffff & 0 + 0] 16 B\n01:4010\n\
+                   [ROMX @ 01:ffff & 0 + 0] 32 A\n01:4020\n\
+                   [ROMX @ 01:ffff & 0 + 0] 16 A\n01:4040\n\
+                   [ROMX @ 01:ffff & 0 + 0] 16 C\n=01:4050\n";
+        let mut warnings = Vec::new();
+        let sequence = parse_input(
+            log.as_bytes(),
+            &ParseOptions {
+                on_duplicate: Some(OnDuplicate::Last),
+                ..Default::default()
+            },
+            &mut warnings,
+        )
+        .unwrap();
+        let names: Vec<_> = sequence
+            .sections
+            .iter()
+            .map(|section| &section.name)
+            .collect();
+        assert_eq!(names, ["B", "A", "C"]);
+        assert_eq!(sequence.sections[1].size, 16);
+        assert_eq!(
+            frames(&sequence),
+            [("B", 1, 0x4010), ("A", 1, 0x4040), ("C", 1, 0x4050)]
+        );
+        assert!(sequence.has_placements);
+        assert_eq!(warnings.len(), 2);
+    }
+
+    /// Parses the log with `--skip-orphan-attempts`, also returning the warnings.
+    fn parse_skipping_orphans(log: &str) -> Result<(Sequence, Vec<Warning>), ParseErrorKind> {
+        let mut warnings = Vec::new();
+        let sequence = parse_input(
+            log.as_bytes(),
+            &ParseOptions {
+                skip_orphans: true,
+                ..Default::default()
+            },
+            &mut warnings,
+        )
+        .map_err(|err| err.kind)?;
+        Ok((sequence, warnings))
+    }
+
+    #[test]
+    fn only_orphan_attempts_are_rejected() {
+        let log = "01:4000\n01:4010\n\n01:4020\n01:4030\n01:4040\n01:4050\n01:4060\n";
+        match parse_skipping_orphans(log) {
+            // Only the first few lines are listed
+            Err(ParseErrorKind::NoSections(7, line_nos)) => assert_eq!(line_nos, [1, 2, 4, 5, 6]),
+            result => panic!("Unexpected result: {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn orphan_attempts_are_reported_together() {
+        let log = "01:4000\n01:4010\n[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4020\n";
+        let (sequence, warnings) = parse_skipping_orphans(log).unwrap();
+        assert_eq!(frames(&sequence), [("A", 1, 0x4020)]);
+        match &warnings[..] {
+            [Warning::OrphanAttempts(2, line_nos)] => assert_eq!(line_nos, &[1, 2]),
+            warnings => panic!("Unexpected warnings: {:?}", warnings),
+        }
+        // Without skipping them, the first one is an error
+        assert!(matches!(
+            parse(log),
+            Err(ParseErrorKind::AttemptBeforeSection(1, _))
+        ));
+    }
+
+    #[test]
+    fn sizes_may_take_32_bits() {
+        for size in [0x1_0000_u32, 0x2_0000, u32::MAX] {
+            let log = format!("[ROMX @ 01:ffff & 0 + 0] {} A\n01:4000\n", size);
+            assert_eq!(parse(&log).unwrap().sections[0].size, size);
+        }
+        assert!(matches!(
+            parse("[ROMX @ 01:ffff & 0 + 0] 4294967296 A\n01:4000\n"),
+            Err(ParseErrorKind::BadSection(
+                SectionParseError::BadSize(_),
+                1,
+                _
+            ))
+        ));
+    }
+
+    #[test]
+    fn section_names_may_contain_delimiters() {
+        let section: Section = "ROMX @ 01:4000 & 3fff + 0] 16 Tiles+Map [1]"
+            .parse()
+            .unwrap();
+        assert_eq!(section.name, "Tiles+Map [1]");
+        assert_eq!(section.size, 16);
+        assert_eq!(section.align_mask, 0x3fff);
+        let section: Section = "ROM0 @ 00:ffff & 0 + 0] 1 a ] b + c & d @ e"
+            .parse()
+            .unwrap();
+        assert_eq!(section.name, "a ] b + c & d @ e");
+    }
+
+    #[test]
+    fn section_names_are_kept_verbatim() {
+        // Only one space separates the size from the name
+        let section: Section = "WRAM0 @ 00:ffff & 0 + 0] 4  Spaced ".parse().unwrap();
+        assert_eq!(section.name, " Spaced ");
+    }
+
+    #[test]
+    fn section_fields_must_be_present() {
+        for line in [
+            "ROMX @ 01:4000 & 0 0] 16 NoPlus",
+            "ROMX @ 01:4000 & 0 + 0 16 NoBracket",
+            "ROMX @ 01:4000 & 0 + ] 16 EmptyOfs",
+            "ROMX @ 01:4000 & 0 + 0] 16",
+        ] {
+            assert!(
+                matches!(line.parse::<Section>(), Err(SectionParseError::SyntaxError)),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn removal_takes_back_the_latest_placement() {
+        let sequence = parse(
+            "[ROMX @ 01:ffff & 0 + 0] 16 A\n=01:4000\n\
+             [ROMX @ 01:ffff & 0 + 0] 16 B\n=01:4000\n\
+             -01:4000\n-01:4000\n",
+        )
+        .unwrap();
+        let removed: Vec<_> = sequence
+            .frames
+            .iter()
+            .filter(|frame| frame.kind == FrameKind::Removal)
+            .map(|frame| frame.section_id)
+            .collect();
+        assert_eq!(removed, [1, 0]);
+    }
+
+    #[test]
+    fn removal_takes_back_attempts_only_without_placements() {
+        let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n-01:4000\n\
+                   [ROMX @ 01:ffff & 0 + 0] 16 B\n01:4000\n=01:4010\n-01:4000\n";
+        let err = parse(log).unwrap_err();
+        assert!(matches!(err, ParseErrorKind::RemovalOfNothing(7, _)));
+        let log = log.replace("=01:4010", "=01:4000");
+        let sequence = parse(&log).unwrap();
+        let removed: Vec<_> = sequence
+            .frames
+            .iter()
+            .filter(|frame| frame.kind == FrameKind::Removal)
+            .map(|frame| frame.section_id)
+            .collect();
+        assert_eq!(removed, [0, 1]);
+    }
+
+    #[test]
+    fn removal_of_a_removed_section() {
+        let err =
+            parse("[ROMX @ 01:ffff & 0 + 0] 16 A\n=01:4000\n-01:4000\n-01:4000\n").unwrap_err();
+        assert!(matches!(err, ParseErrorKind::RemovalOfNothing(4, _)));
+    }
+
+    #[test]
+    fn unbanked_types_must_be_in_bank_0() {
+        for (mem_type, addr, banked) in [
+            (MemType::Rom0, 0x0000, false),
+            (MemType::Romx, 0x4000, true),
+            (MemType::Vram, 0x8000, true),
+            (MemType::Sram, 0xa000, true),
+            (MemType::Wram0, 0xc000, false),
+            (MemType::Wramx, 0xd000, true),
+            (MemType::Oam, 0xfe00, false),
+            (MemType::Hram, 0xff80, false),
+        ] {
+            assert_eq!(mem_type.is_banked(), banked, "{}", mem_type);
+            let log = |bank| {
+                format!(
+                    "[{} @ 00:ffff & 0 + 0] 1 A\n{:02x}:{:04x}\n",
+                    mem_type, bank, addr
+                )
+            };
+            assert!(warnings(&log(0)).is_empty(), "{}", mem_type);
+            match &warnings(&log(2))[..] {
+                [] => assert!(banked, "{}", mem_type),
+                [Warning::Location(err)] => {
+                    assert!(!banked, "{}", mem_type);
+                    assert!(
+                        matches!(err.kind, ParseErrorKind::BankNotZero(ty, 2, _) if ty == mem_type)
+                    );
+                }
+                warnings => panic!("{}: {:?}", mem_type, warnings),
+            }
+        }
+    }
+
+    #[test]
+    fn keep_going_skips_bad_lines() {
+        let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n01:zzzz\n01:4010\n\
+                   [ROMX @ 01:ffff & 0 + 0] 16 B\n-01:\n01:4020\n";
+        let (sequence, warnings) = parse_keep_going(log, 2, false).unwrap();
+        assert_eq!(
+            frames(&sequence),
+            [("A", 1, 0x4000), ("A", 1, 0x4010), ("B", 1, 0x4020)]
+        );
+        match &warnings[..] {
+            [Warning::SkippedLine(first), Warning::SkippedLine(second)] => {
+                assert!(matches!(first.kind, ParseErrorKind::BadAttempt(_, 3, _)));
+                assert!(matches!(second.kind, ParseErrorKind::BadRemoval(_, 6, _)));
+            }
+            warnings => panic!("{:?}", warnings),
+        }
+        assert!(matches!(
+            parse_keep_going(log, 1, false),
+            Err(ParseErrorKind::BadRemoval(_, 6, _))
+        ));
+    }
+
+    #[test]
+    fn keep_going_drops_the_attempts_of_a_skipped_section() {
+        let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n\
+                   [ROMX @ 01:ffff & 0 + 0] sixteen B\n01:4010\n=01:4010\n\
+                   [ROMX @ 01:ffff & 0 + 0] 16 C\n01:4020\n";
+        // The dropped attempts don't count as skipped lines
+        let (sequence, warnings) = parse_keep_going(log, 1, false).unwrap();
+        assert_eq!(frames(&sequence), [("A", 1, 0x4000), ("C", 1, 0x4020)]);
+        match &warnings[..] {
+            [Warning::SkippedLine(err), Warning::SkippedAttempts(2, 3)] => {
+                assert!(matches!(err.kind, ParseErrorKind::BadSection(_, 3, _)));
+            }
+            warnings => panic!("{:?}", warnings),
+        }
+    }
+
+    #[test]
+    fn keep_going_drops_attempts_until_the_end() {
+        let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n[ROMX @ 01:ffff & 0 0] 16 B\n01:4010\n";
+        let (sequence, warnings) = parse_keep_going(log, 1, false).unwrap();
+        assert_eq!(sequence.frames.len(), 1);
+        assert!(matches!(
+            &warnings[..],
+            [Warning::SkippedLine(_), Warning::SkippedAttempts(1, 3)]
+        ));
+    }
+
+    #[test]
+    fn two_pass_drops_the_attempts_of_a_skipped_section() {
+        let log = "01:4000 C\n[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n\
+                   [ROMX @ 01:ffff & 0 + 0] sixteen B\n01:4010\n01:4020 A\n01:4030\n\
+                   [ROMX @ 01:ffff & 0 + 0] 16 C\n01:4040\n";
+        let (sequence, warnings) = parse_keep_going(log, 1, true).unwrap();
+        // Attempts naming their section are still kept
+        assert_eq!(
+            frames(&sequence),
+            [
+                ("C", 1, 0x4000),
+                ("A", 1, 0x4000),
+                ("A", 1, 0x4020),
+                ("C", 1, 0x4040)
+            ]
+        );
+        match &warnings[..] {
+            [Warning::SkippedLine(err), Warning::SkippedAttempts(2, 4)] => {
+                assert!(matches!(err.kind, ParseErrorKind::BadSection(_, 4, _)));
+            }
+            warnings => panic!("{:?}", warnings),
+        }
+    }
+}
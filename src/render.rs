@@ -1,16 +1,27 @@
-use crate::{Location, MemType, Section, Sequence};
-use mp4::{
-    AvcConfig, FourCC, MediaConfig, Mp4Config, Mp4Sample, Mp4Writer, TrackConfig, TrackType,
-};
-use openh264::encoder::{EncodedBitStream, Encoder, EncoderConfig};
-use openh264::formats::{RBGYUVConverter, YUVSource};
+use crate::apng::{self, ApngSink};
+use crate::checkpoint::Checkpoint;
+use crate::encode::{self, Mp4Sink, NullSink};
+use crate::export;
+use crate::font;
+use crate::palette::Palette;
+use crate::stats;
+use crate::term;
+use crate::warning::Warning;
+use crate::webp::WebpSink;
+use crate::zip::ZipWriter;
+use crate::{Frame, FrameKind, Location, MemType, Region, Section, Sequence};
+use parse_display::FromStr;
+use regex::Regex;
+use std::borrow::Cow;
 use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
 use std::error::Error;
 use std::fmt;
-use std::fs::File;
-use std::io::{self, BufWriter};
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 #[derive(Debug)]
 pub struct RenderError {
@@ -18,11 +29,61 @@ pub struct RenderError {
     frame: Option<u32>,
 }
 
+/// What went wrong while rendering.
 #[derive(Debug)]
-enum RenderErrorKind {
+#[non_exhaustive]
+pub enum RenderErrorKind {
     Io(io::Error),
     H264(openh264::Error),
     Mp4(mp4::Error),
+    Png(png::EncodingError),
+    /// The output cannot be seeked into, which the MP4 writer requires.
+    NotSeekable(io::Error),
+    /// The video's duration overflows what the container can represent.
+    TooLong,
+    /// The video's samples take more bytes than `--max-output-bytes` allows.
+    TooLarge(u64),
+    /// The H.264 level requested is lower than the one the stream requires, as `level_idc`s.
+    LevelTooLow {
+        requested: u8,
+        required: u8,
+    },
+}
+
+impl RenderError {
+    pub fn too_long() -> Self {
+        Self {
+            kind: RenderErrorKind::TooLong,
+            frame: None,
+        }
+    }
+
+    pub fn too_large(max_bytes: u64) -> Self {
+        Self {
+            kind: RenderErrorKind::TooLarge(max_bytes),
+            frame: None,
+        }
+    }
+
+    pub fn level_too_low(requested: u8, required: u8) -> Self {
+        Self {
+            kind: RenderErrorKind::LevelTooLow {
+                requested,
+                required,
+            },
+            frame: None,
+        }
+    }
+
+    /// What went wrong, to react to it without going through its message.
+    pub fn kind(&self) -> &RenderErrorKind {
+        &self.kind
+    }
+
+    /// Which frame was being rendered, if the error concerns a single one.
+    pub fn frame(&self) -> Option<u32> {
+        self.frame
+    }
 }
 
 impl From<io::Error> for RenderError {
@@ -52,26 +113,175 @@ impl From<mp4::Error> for RenderError {
     }
 }
 
+impl From<png::EncodingError> for RenderError {
+    fn from(err: png::EncodingError) -> Self {
+        Self {
+            kind: RenderErrorKind::Png(err),
+            frame: None,
+        }
+    }
+}
+
 impl fmt::Display for RenderError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        let loc_string = match self.frame {
+        let loc_string = match self.frame() {
             Some(frame) => format!(" (on frame {})", frame),
             None => "".to_string(), // TODO: meh
         };
-        match &self.kind {
+        match self.kind() {
             RenderErrorKind::Io(err) => write!(fmt, "I/O error{}: {}", loc_string, err),
             RenderErrorKind::H264(err) => write!(fmt, "H264 error{}: {}", loc_string, err),
             RenderErrorKind::Mp4(err) => write!(fmt, "MP4 error{}: {}", loc_string, err),
+            RenderErrorKind::Png(err) => write!(fmt, "PNG error{}: {}", loc_string, err),
+            RenderErrorKind::NotSeekable(err) => write!(
+                fmt,
+                "Cannot write MP4 to a pipe or terminal, as it requires a seekable file ({}); write to a regular file instead, then copy it",
+                err
+            ),
+            RenderErrorKind::TooLong => write!(
+                fmt,
+                "The video is too long to be timestamped{}; drop some frames with --frame-skip",
+                loc_string
+            ),
+            RenderErrorKind::TooLarge(max_bytes) => write!(
+                fmt,
+                "The video would be larger than {} bytes, so it was removed; drop some frames with --frame-skip or --sample-every, or shrink them with --scale",
+                max_bytes
+            ),
+            RenderErrorKind::LevelTooLow {
+                requested,
+                required,
+            } => write!(
+                fmt,
+                "H.264 level {} is too low for this video, which requires at least {}",
+                encode::level_name(*requested),
+                encode::level_name(*required)
+            ),
         }
     }
 }
 
 impl Error for RenderError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        match &self.kind {
+        match self.kind() {
             RenderErrorKind::Io(ref err) => Some(err),
             RenderErrorKind::H264(ref err) => Some(err),
             RenderErrorKind::Mp4(ref err) => Some(err),
+            RenderErrorKind::Png(ref err) => Some(err),
+            RenderErrorKind::NotSeekable(ref err) => Some(err),
+            RenderErrorKind::TooLong
+            | RenderErrorKind::TooLarge(_)
+            | RenderErrorKind::LevelTooLow { .. } => None,
+        }
+    }
+}
+
+/// Where things are drawn on the canvas, in pixels.
+#[derive(Debug)]
+pub struct Layout {
+    /// The output's size, after transposing if banks are rows; everything else is laid out as if
+    /// they were columns.
+    pub width: u32,
+    pub height: u32,
+    pub orientation: Orientation,
+    pub bank_width: u32,
+    /// Width of the lines between banks of a same region, both between columns and rows.
+    pub spacer_width: u32,
+    /// Width of the bands between regions.
+    pub region_separator_width: u32,
+    /// The regions displayed, left to right.
+    pub regions: Vec<RegionGroup>,
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{}x{} pixels, banks {} pixels wide",
+            self.width, self.height, self.bank_width
+        )?;
+        if self.orientation == Orientation::Horizontal {
+            write!(fmt, " (transposed, as rows)")?;
+        }
+        for group in &self.regions {
+            write!(
+                fmt,
+                "; {}: {} bank(s) in {} row(s) of {}, {} pixels tall",
+                group.label(),
+                group.nb_banks,
+                group.nb_rows,
+                group.nb_columns,
+                group.bank_height
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A set of banks displayed as a grid, left to right then top to bottom, sharing the same geometry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionGroup {
+    pub region: Region,
+    /// The group of the region's sections shown, with `--group-by`.
+    pub panel: Option<String>,
+    /// Which bank is displayed in each slot of the grid, in order.
+    pub banks: Vec<u32>,
+    /// The slot each bank is displayed in, if it is displayed at all.
+    slots: Vec<Option<u32>>,
+    pub nb_banks: u32,
+    pub nb_rows: u32,
+    pub nb_columns: u32,
+    /// Where the group's first bank's column begins.
+    pub x: u32,
+    pub bank_height: u32,
+    /// How tall the strip above each row of banks is, where their numbers are written; 0 if they
+    /// aren't.
+    pub label_height: u32,
+}
+
+impl RegionGroup {
+    /// Lays out these banks, in this order, in at most this many rows.
+    fn new(
+        region: Region,
+        panel: Option<String>,
+        banks: Vec<u32>,
+        max_rows: u32,
+        label_height: u32,
+    ) -> Self {
+        let nb_banks = banks.len() as u32;
+        let nb_rows = cmp::min(nb_banks, max_rows);
+        let nb_columns = nb_banks.div_ceil(nb_rows);
+        let mut slots = vec![None; banks.iter().map(|&bank| bank as usize + 1).max().unwrap()];
+        for (slot, &bank) in banks.iter().enumerate() {
+            slots[bank as usize] = Some(slot as u32);
+        }
+        Self {
+            region,
+            panel,
+            banks,
+            slots,
+            nb_banks,
+            nb_rows,
+            nb_columns,
+            x: 0,
+            bank_height: (Canvas::HEIGHT
+                - (nb_rows - 1) * Canvas::SPACER_WIDTH
+                - nb_rows * label_height)
+                / nb_rows,
+            label_height,
+        }
+    }
+
+    /// How far apart the tops of consecutive rows are.
+    fn row_pitch(&self) -> u32 {
+        self.label_height + self.bank_height + Canvas::SPACER_WIDTH
+    }
+
+    /// What the band before the group says.
+    fn label(&self) -> String {
+        match &self.panel {
+            Some(panel) => panel.clone(),
+            None => self.region.to_string(),
         }
     }
 }
@@ -79,241 +289,3845 @@ impl Error for RenderError {
 #[derive(Debug)]
 struct Canvas {
     bank_width: u32,
-    nb_banks: u32,
+    width: u32,
+    groups: Vec<RegionGroup>,
     pixels: Vec<u8>,
+    /// The canvas as it is before any section is settled, to restore removed ones from.
+    background: Vec<u8>,
+    name_color_seed: Option<u64>,
+    rom0_half: Rom0Half,
+    overlay_alpha: f32,
+    overlay_border: u32,
+    min_section_px: u32,
+    antialias: bool,
+    highlight: Vec<Regex>,
+    watch_ranges: Vec<WatchRange>,
+    palette: Palette,
+    attempt_style: AttemptStyle,
+    /// How many bytes are settled in each bank, indexed like `groups` then by bank.
+    used: Vec<Vec<u32>>,
+    mark_full: bool,
+    /// Whether free space is colored instead, with settled sections carving it out.
+    show_free: bool,
+    /// Whether banks are shown as rows, i.e. whether the canvas is transposed.
+    horizontal: bool,
+    /// If set, each region is split into panels, one per group of sections, each of which is
+    /// labelled, even the first.
+    group_by: Option<GroupBy>,
 }
 
 type Color = (u8, u8, u8);
 
+/// Where the canvas' pixels are in memory. The canvas is always laid out with banks as columns,
+/// and is transposed when they are shown as rows instead, which simply swaps coordinates here.
+#[derive(Debug, Clone, Copy)]
+struct Raster {
+    /// How wide the canvas is, before transposing.
+    width: u32,
+    transposed: bool,
+}
+
+impl Raster {
+    fn index(self, x: u32, y: u32) -> usize {
+        let idx = if self.transposed {
+            y + x * Canvas::HEIGHT
+        } else {
+            x + y * self.width
+        };
+        usize::try_from(idx).unwrap() * 3
+    }
+
+    /// The same pixels, without transposing; text is drawn this way, so that it remains upright.
+    fn upright(self) -> Self {
+        Self {
+            width: if self.transposed {
+                Canvas::HEIGHT
+            } else {
+                self.width
+            },
+            transposed: false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// How much of a rectangle's top and bottom rows its section actually covers, from 0 to 1; rows in
+/// between are always fully covered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Coverage {
+    top: f32,
+    bottom: f32,
+}
+
+impl Coverage {
+    const FULL: Self = Self {
+        top: 1.0,
+        bottom: 1.0,
+    };
+
+    fn of_row(&self, rect: &Rect, y: u32) -> f32 {
+        let mut coverage = 1.0;
+        if y == rect.y {
+            coverage *= self.top;
+        }
+        if y == rect.y + rect.height - 1 {
+            coverage *= self.bottom;
+        }
+        coverage
+    }
+}
+
+/// Picks a color for a section from its name, such that the same seed and name always yield the
+/// same color.
+fn name_color(name: &str, seed: u64) -> Color {
+    // FNV-1a, which unlike `DefaultHasher` is guaranteed to be stable across Rust versions
+    let hash = seed
+        .to_le_bytes()
+        .iter()
+        .chain(name.as_bytes())
+        .fold(0xcbf29ce484222325_u64, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        });
+
+    // Pick a fairly saturated and bright hue, so that sections contrast with the background
+    let hue = (hash % 360) as u32;
+    let (max, min) = (230, 60);
+    let ramp = |ofs: u32| min + (max - min) * ofs / 60;
+    let (r, g, b) = match hue / 60 {
+        0 => (max, ramp(hue % 60), min),
+        1 => (ramp(60 - hue % 60), max, min),
+        2 => (min, max, ramp(hue % 60)),
+        3 => (min, ramp(60 - hue % 60), max),
+        4 => (ramp(hue % 60), min, max),
+        _ => (max, min, ramp(60 - hue % 60)),
+    };
+    (r as u8, g as u8, b as u8)
+}
+
 impl Canvas {
     // The layout is: N pixels, 2 spacers, N pixels, and so on
     const HEIGHT: u32 = 512;
     const MAX_WIDTH: u32 = Canvas::HEIGHT * 2; // 2:1 should be an *acceptable* ratio
     const SPACER_WIDTH: u32 = 2;
     const MAX_BANK_WIDTH: u32 = 32 - Canvas::SPACER_WIDTH;
-    const BYTES_PER_ROW: u32 = 0x4000 / Canvas::HEIGHT; // How many bytes each row of pixels represents
+    // Region groups are separated by a wider band instead, with the name of the following region
+    const LABEL_SCALE: u32 = 2;
+    const REGION_SEPARATOR_WIDTH: u32 = (font::GLYPH_WIDTH + 2) * Canvas::LABEL_SCALE;
+    /// How tall the strips of bank numbers are, with `--bank-labels`.
+    const BANK_LABEL_HEIGHT: u32 = font::GLYPH_HEIGHT + 2;
+    /// The same, when banks are rows, so that the strips are as wide as 3 digits instead.
+    const BANK_LABEL_WIDTH: u32 = 3 * (font::GLYPH_WIDTH + 1) + 1;
 
     const FILLED_COLOR: Color = (0, 255, 0);
+    const EMPTY_COLOR: Color = (255, 255, 255);
+    /// Free space, when showing it instead of settled sections.
+    const FREE_COLOR: Color = (64, 160, 255);
     const OVERLAY_COLOR: Color = (255, 0, 0);
+    const HIGHLIGHT_COLOR: Color = (255, 0, 255);
+    const FLASH_COLOR: Color = (255, 255, 0);
+    const BORDER_DARK_COLOR: Color = (0, 0, 0);
+    const BORDER_LIGHT_COLOR: Color = (255, 255, 255);
+    const SPACER_COLOR: Color = (0, 0, 0);
+    const REGION_SEPARATOR_COLOR: Color = (64, 64, 64);
+    const LABEL_COLOR: Color = (255, 255, 255);
+    /// Frame numbers written over sections, with `--annotate-settle-frame`.
+    const ANNOTATION_COLOR: Color = (0, 0, 0);
+    const GRID_COLOR: Color = (224, 224, 224);
+    const FULL_MARK_COLOR: Color = (160, 0, 0);
+    const LARGEST_GAP_COLOR: Color = (255, 144, 0);
+    const FULL_MARK_HEIGHT: u32 = 2;
+    /// Where sections were settled, when diagnosing settles.
+    const DIAGNOSIS_SETTLED_COLOR: Color = (0, 128, 255);
+    /// How opaque each of a section's attempts is, when diagnosing settles; overlapping ones
+    /// stack up, so that the most-attempted spots stand out.
+    const DIAGNOSIS_ATTEMPT_ALPHA: f32 = 0.5;
+    /// How big the markers drawn for attempts are, with `AttemptStyle::Dots`.
+    const DOT_SIZE: u32 = 4;
 
-    pub fn new(nb_banks: u32) -> Self {
-        // Pick a width depending on the amount of banks
-        // Note that the width has to be even! Thus, we round the width down if necessary.
-        let bank_width = cmp::min(
-            ((Self::MAX_WIDTH / nb_banks) & !1) - Self::SPACER_WIDTH,
-            Self::MAX_BANK_WIDTH,
-        );
-        let width = Self::n_banks_width(bank_width, nb_banks);
+    pub fn new(sequence: &Sequence, options: &Options) -> Self {
+        let (bank_width, groups, width) = Self::arrange(sequence, options);
+        let horizontal = options.orientation == Orientation::Horizontal;
 
         let mut canvas = Self {
             bank_width,
-            nb_banks,
-            // Canvas is white by default
-            pixels: vec![255; (width * Self::HEIGHT * 3).try_into().unwrap()],
+            width,
+            groups,
+            // Banks are empty by default, and everything else gets drawn over
+            pixels: {
+                let (r, g, b) = if options.show_free {
+                    Self::FREE_COLOR
+                } else {
+                    Self::EMPTY_COLOR
+                };
+                [r, g, b].repeat((width * Self::HEIGHT).try_into().unwrap())
+            },
+            background: Vec::new(),
+            name_color_seed: options.name_color_seed,
+            rom0_half: options.rom0_half,
+            overlay_alpha: options.overlay_alpha,
+            overlay_border: options.overlay_border,
+            min_section_px: options.min_section_px,
+            antialias: options.antialias,
+            highlight: options.highlight.clone(),
+            watch_ranges: options.watch_ranges.clone(),
+            palette: options.palette.clone(),
+            attempt_style: options.attempt_style,
+            used: Vec::new(),
+            mark_full: options.mark_full,
+            show_free: options.show_free,
+            horizontal,
+            group_by: options.group_by,
         };
+        let raster = canvas.raster();
+
+        // Draw lines between banks, and bands between regions
+        for (i, group) in canvas.groups.iter().enumerate() {
+            let group_width =
+                (bank_width + Self::SPACER_WIDTH) * group.nb_columns - Self::SPACER_WIDTH;
+            for column in 1..group.nb_columns {
+                Self::fill_rect(
+                    &mut canvas.pixels,
+                    raster,
+                    &Rect {
+                        x: group.x + column * (bank_width + Self::SPACER_WIDTH)
+                            - Self::SPACER_WIDTH,
+                        y: 0,
+                        width: Self::SPACER_WIDTH,
+                        height: Self::HEIGHT,
+                    },
+                    Self::SPACER_COLOR,
+                );
+            }
+            for row in 1..group.nb_rows {
+                Self::fill_rect(
+                    &mut canvas.pixels,
+                    raster,
+                    &Rect {
+                        x: group.x,
+                        y: row * group.row_pitch() - Self::SPACER_WIDTH,
+                        width: group_width,
+                        height: Self::SPACER_WIDTH,
+                    },
+                    Self::SPACER_COLOR,
+                );
+            }
+            // Blank out what isn't part of any bank: the remainder of the height not evenly
+            // divided between rows, and the end of the last row if it's not full
+            let grid_height = group.row_pitch() * group.nb_rows;
+            if grid_height < Self::HEIGHT + Self::SPACER_WIDTH {
+                Self::fill_rect(
+                    &mut canvas.pixels,
+                    raster,
+                    &Rect {
+                        x: group.x,
+                        y: grid_height - Self::SPACER_WIDTH,
+                        width: group_width,
+                        height: Self::HEIGHT + Self::SPACER_WIDTH - grid_height,
+                    },
+                    Self::SPACER_COLOR,
+                );
+            }
+            // Faint lines at regular addresses, to help estimate where things are
+            if let Some(step) = options.grid {
+                for (slot, &bank) in group.banks.iter().enumerate() {
+                    let bank_size = canvas.column_size(group.region, bank);
+                    let (x, y) = canvas.bank_origin(group, slot as u32);
+                    for ofs in (step..bank_size).step_by(step.try_into().unwrap()) {
+                        Self::fill_rect(
+                            &mut canvas.pixels,
+                            raster,
+                            &Rect {
+                                x,
+                                y: y + ofs * group.bank_height / bank_size,
+                                width: bank_width,
+                                height: 1,
+                            },
+                            Self::GRID_COLOR,
+                        );
+                    }
+                }
+            }
+            for bank in group.nb_banks..group.nb_rows * group.nb_columns {
+                let (x, y) = canvas.bank_origin(group, bank);
+                Self::fill_rect(
+                    &mut canvas.pixels,
+                    raster,
+                    &Rect {
+                        x,
+                        y,
+                        width: bank_width,
+                        height: group.bank_height,
+                    },
+                    Self::SPACER_COLOR,
+                );
+            }
 
-        // Draw columns between sections
-        let width = canvas.width();
-        for y in 0..canvas.height() {
-            for bank in 1..canvas.nb_banks {
-                for xofs in 1..=Self::SPACER_WIDTH {
-                    Self::write_color(
+            // Each row of banks gets a strip above it, with each bank's number centered over it,
+            // if it fits
+            if group.label_height != 0 {
+                for row in 0..group.nb_rows {
+                    Self::fill_rect(
+                        &mut canvas.pixels,
+                        raster,
+                        &Rect {
+                            x: group.x,
+                            y: row * group.row_pitch(),
+                            width: group_width,
+                            height: group.label_height,
+                        },
+                        Self::SPACER_COLOR,
+                    );
+                }
+                for (slot, &bank) in group.banks.iter().enumerate() {
+                    let label = format!("{:x}", bank);
+                    let label_width = label.len() as u32 * (font::GLYPH_WIDTH + 1) - 1;
+                    let (x, y) = canvas.bank_origin(group, slot as u32);
+                    // Text isn't transposed, so it's centered the other way around then
+                    let (x, y) = if !horizontal && label_width <= bank_width {
+                        (
+                            x + (bank_width - label_width) / 2,
+                            y - group.label_height + 1,
+                        )
+                    } else if horizontal
+                        && label_width <= group.label_height
+                        && font::GLYPH_HEIGHT <= bank_width
+                    {
+                        (
+                            y - group.label_height + (group.label_height - label_width) / 2,
+                            x + (bank_width - font::GLYPH_HEIGHT) / 2,
+                        )
+                    } else {
+                        continue;
+                    };
+                    Self::draw_text(
                         &mut canvas.pixels,
-                        bank * (canvas.bank_width + Self::SPACER_WIDTH) - xofs,
+                        raster,
+                        x,
                         y,
-                        width,
-                        (0, 0, 0),
+                        &label,
+                        1,
+                        Self::LABEL_COLOR,
+                    );
+                }
+            }
+
+            if canvas.has_band(i) {
+                let x = group.x - Self::REGION_SEPARATOR_WIDTH;
+                Self::fill_rect(
+                    &mut canvas.pixels,
+                    raster,
+                    &Rect {
+                        x,
+                        y: 0,
+                        width: Self::REGION_SEPARATOR_WIDTH,
+                        height: Self::HEIGHT,
+                    },
+                    Self::REGION_SEPARATOR_COLOR,
+                );
+                // The label is written along the band, one character below the other if it's
+                // vertical, and cut off if it's too long for it
+                let max_len = if horizontal {
+                    (Self::HEIGHT - Self::LABEL_SCALE)
+                        / ((font::GLYPH_WIDTH + 1) * Self::LABEL_SCALE)
+                } else {
+                    (Self::HEIGHT - Self::LABEL_SCALE)
+                        / ((font::GLYPH_HEIGHT + 1) * Self::LABEL_SCALE)
+                };
+                let label: String = group.label().chars().take(max_len as usize).collect();
+                if horizontal {
+                    Self::draw_text(
+                        &mut canvas.pixels,
+                        raster,
+                        Self::LABEL_SCALE,
+                        x + (Self::REGION_SEPARATOR_WIDTH - font::GLYPH_HEIGHT * Self::LABEL_SCALE)
+                            / 2,
+                        &label,
+                        Self::LABEL_SCALE,
+                        Self::LABEL_COLOR,
                     );
+                } else {
+                    for (j, c) in label.chars().enumerate() {
+                        Self::draw_text(
+                            &mut canvas.pixels,
+                            raster,
+                            x + Self::LABEL_SCALE,
+                            Self::LABEL_SCALE
+                                + j as u32 * (font::GLYPH_HEIGHT + 1) * Self::LABEL_SCALE,
+                            &c.to_string(),
+                            Self::LABEL_SCALE,
+                            Self::LABEL_COLOR,
+                        );
+                    }
                 }
             }
         }
 
+        canvas.background = canvas.pixels.clone();
+        canvas.used = canvas
+            .groups
+            .iter()
+            .map(|group| vec![0; group.slots.len()])
+            .collect();
         canvas
     }
 
-    fn n_banks_width(bank_width: u32, nb_banks: u32) -> u32 {
-        (bank_width + Self::SPACER_WIDTH) * nb_banks - Self::SPACER_WIDTH
+    /// Where each group of banks goes, and how wide the banks and the canvas are.
+    fn arrange(sequence: &Sequence, options: &Options) -> (u32, Vec<RegionGroup>, u32) {
+        let regions = || {
+            Region::ALL
+                .iter()
+                .copied()
+                .filter(|region| options.regions.contains(region))
+        };
+        // Each region is a group of banks, unless it's split into several; the panels are shared
+        // between the regions, so that their bands always fit
+        let max_panels = cmp::max(GroupBy::MAX_PANELS / regions().count(), 2);
+        let panels: Vec<_> = regions()
+            .flat_map(|region| {
+                let banks = options
+                    .bank_order
+                    .banks(sequence, region, options.rom0_half);
+                match options.group_by {
+                    Some(group_by) => {
+                        group_by.panels(sequence, region, &options.mem_types, banks, max_panels)
+                    }
+                    None => vec![(region, None, banks)],
+                }
+            })
+            .collect();
+        let nb_separators = if options.group_by.is_some() {
+            panels.len() as u32
+        } else {
+            panels.len() as u32 - 1
+        };
+        // Without a cap, banks all get as wide as they can be
+        let max_width = if options.uncapped {
+            u32::MAX
+        } else {
+            Self::MAX_WIDTH
+        };
+        assert!(
+            nb_separators * Self::REGION_SEPARATOR_WIDTH < max_width,
+            "Too many groups to fit on the canvas"
+        );
+        let available_width = max_width - nb_separators * Self::REGION_SEPARATOR_WIDTH;
+        // Pick a width depending on the amount of columns
+        // Note that the width has to be even! Thus, we round the width down if necessary.
+        let bank_width = |groups: &[RegionGroup]| {
+            let nb_columns: u32 = groups.iter().map(|group| group.nb_columns).sum();
+            cmp::min(
+                ((available_width / nb_columns) & !1).saturating_sub(Self::SPACER_WIDTH),
+                Self::MAX_BANK_WIDTH,
+            )
+        };
+
+        // Try wrapping the banks into more and more rows, and keep the layout with the widest
+        // banks; in case of a tie, fewer rows are better, as they keep the banks taller
+        let max_banks = panels.iter().map(|(_, _, banks)| banks.len() as u32).max();
+        let horizontal = options.orientation == Orientation::Horizontal;
+        let label_height = match (options.bank_labels, horizontal) {
+            (false, _) => 0,
+            (true, false) => Self::BANK_LABEL_HEIGHT,
+            (true, true) => Self::BANK_LABEL_WIDTH,
+        };
+        // Banks must remain at least a pixel tall
+        let max_rows =
+            (Self::HEIGHT + Self::SPACER_WIDTH) / (label_height + 1 + Self::SPACER_WIDTH);
+        let (bank_width, mut groups) = (1..=cmp::min(max_banks.unwrap(), max_rows))
+            .map(|max_rows| {
+                let groups: Vec<_> = panels
+                    .iter()
+                    .map(|(region, panel, banks)| {
+                        RegionGroup::new(
+                            *region,
+                            panel.clone(),
+                            banks.clone(),
+                            max_rows,
+                            label_height,
+                        )
+                    })
+                    .collect();
+                (bank_width(&groups), groups)
+            })
+            .reduce(|best, candidate| {
+                if candidate.0 > best.0 {
+                    candidate
+                } else {
+                    best
+                }
+            })
+            .unwrap();
+        assert_ne!(bank_width, 0, "Too many banks to fit on the canvas");
+
+        let mut x = 0;
+        for (i, group) in groups.iter_mut().enumerate() {
+            if i != 0 {
+                x += Self::REGION_SEPARATOR_WIDTH - Self::SPACER_WIDTH;
+            } else if options.group_by.is_some() {
+                x += Self::REGION_SEPARATOR_WIDTH;
+            }
+            group.x = x;
+            x += (bank_width + Self::SPACER_WIDTH) * group.nb_columns;
+        }
+        (bank_width, groups, x - Self::SPACER_WIDTH)
     }
 
+    /// How wide the output is, which isn't the canvas' width if it's transposed.
     pub fn width(&self) -> u32 {
-        Self::n_banks_width(self.bank_width, self.nb_banks)
+        self.raster().upright().width
+    }
+
+    pub fn layout(&self) -> Layout {
+        Layout {
+            width: self.width(),
+            height: self.height(),
+            orientation: if self.horizontal {
+                Orientation::Horizontal
+            } else {
+                Orientation::Vertical
+            },
+            bank_width: self.bank_width,
+            spacer_width: Self::SPACER_WIDTH,
+            region_separator_width: Self::REGION_SEPARATOR_WIDTH,
+            regions: self.groups.clone(),
+        }
     }
 
     pub fn height(&self) -> u32 {
-        Canvas::HEIGHT
+        if self.horizontal {
+            self.width
+        } else {
+            Canvas::HEIGHT
+        }
+    }
+
+    fn raster(&self) -> Raster {
+        Raster {
+            width: self.width,
+            transposed: self.horizontal,
+        }
     }
 
-    fn write_color(pixels: &mut [u8], x: u32, y: u32, width: u32, color: Color) {
-        let idx = usize::try_from(x + y * width).unwrap() * 3;
+    fn write_color(pixels: &mut [u8], x: u32, y: u32, raster: Raster, color: Color) {
+        let idx = raster.index(x, y);
         pixels[idx] = color.0;
         pixels[idx + 1] = color.1;
         pixels[idx + 2] = color.2;
     }
 
-    fn draw_rect(
+    /// Mixes the color into the pixel's current one; an alpha of 1 simply overwrites it.
+    fn blend_color(pixels: &mut [u8], x: u32, y: u32, raster: Raster, color: Color, alpha: f32) {
+        let idx = raster.index(x, y);
+        Self::blend_pixel(&mut pixels[idx..idx + 3], color, alpha);
+    }
+
+    fn blend_pixel(pixel: &mut [u8], color: Color, alpha: f32) {
+        let blend =
+            |new: u8, old: u8| (f32::from(new) * alpha + f32::from(old) * (1.0 - alpha)).round();
+        pixel[0] = blend(color.0, pixel[0]) as u8;
+        pixel[1] = blend(color.1, pixel[1]) as u8;
+        pixel[2] = blend(color.2, pixel[2]) as u8;
+    }
+
+    fn fill_rect(pixels: &mut [u8], raster: Raster, rect: &Rect, color: Color) {
+        for y in rect.y..rect.y + rect.height {
+            for x in rect.x..rect.x + rect.width {
+                Self::write_color(pixels, x, y, raster, color);
+            }
+        }
+    }
+
+    /// Blends a color over a rectangle, its edge rows less so the less they are covered.
+    fn paint_rect(
         pixels: &mut [u8],
-        location: &Location,
-        nb_bytes: u32,
-        width: u32,
-        bank_width: u32,
+        raster: Raster,
+        rect: &Rect,
+        coverage: Coverage,
+        color: Color,
+        alpha: f32,
+    ) {
+        for y in rect.y..rect.y + rect.height {
+            let alpha = alpha * coverage.of_row(rect, y);
+            for x in rect.x..rect.x + rect.width {
+                Self::blend_color(pixels, x, y, raster, color, alpha);
+            }
+        }
+    }
+
+    /// Draws a line of text, whose top-left corner is at the given coordinates; unlike everything
+    /// else, those are the output's, so that text is never transposed.
+    fn draw_text(
+        pixels: &mut [u8],
+        raster: Raster,
+        x: u32,
+        y: u32,
+        text: &str,
+        scale: u32,
         color: Color,
     ) {
-        let addr = u32::from(location.addr) % 0x4000; // Only take the address within the bank
+        for (i, c) in text.chars().enumerate() {
+            let glyph_x = x + i as u32 * (font::GLYPH_WIDTH + 1) * scale;
+            for (row, bits) in font::glyph(c).iter().enumerate() {
+                for col in 0..font::GLYPH_WIDTH {
+                    if bits & (1 << (font::GLYPH_WIDTH - 1 - col)) != 0 {
+                        Self::fill_rect(
+                            pixels,
+                            raster.upright(),
+                            &Rect {
+                                x: glyph_x + col * scale,
+                                y: y + row as u32 * scale,
+                                width: scale,
+                                height: scale,
+                            },
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether the group at this index is preceded by a band, with its label.
+    fn has_band(&self, index: usize) -> bool {
+        index != 0 || self.group_by.is_some()
+    }
+
+    /// Which group a section is drawn in.
+    fn group_index(&self, section: &Section) -> usize {
+        let region = section.mem_type.region();
+        let panel = self.group_by.map(|group_by| group_by.key(section));
+        let position = |panel: &Option<String>| {
+            self.groups.iter().position(|group| {
+                group.region == region && (group.panel.is_none() || group.panel == *panel)
+            })
+        };
+        // Groups that didn't get their own panel share the last one
+        position(&panel)
+            .or_else(|| position(&Some(GroupBy::OTHER_PANEL.to_string())))
+            .expect("Section's region is not being rendered")
+    }
+
+    /// Where the largest free block of each bank displayed ends up, once all sections are placed;
+    /// banks shown in several panels have it drawn in each.
+    fn largest_gap_rects(&self, sequence: &Sequence) -> Vec<Rect> {
+        let regions: Vec<_> = self.groups.iter().map(|group| group.region).collect();
+        let mut rects = Vec::new();
+        for bank in stats::bank_stats(sequence, &regions, self.rom0_half) {
+            let (ofs, size) = match bank.largest_gap_block() {
+                Some(gap) => gap,
+                None => continue,
+            };
+            for group in self
+                .groups
+                .iter()
+                .filter(|group| group.region == bank.region)
+            {
+                let slot = match group.slots.get(bank.bank as usize) {
+                    Some(&Some(slot)) => slot,
+                    _ => continue,
+                };
+                let column_size = self.column_size(group.region, bank.bank);
+                let top = ofs * group.bank_height / column_size;
+                // Keep even tiny gaps visible
+                let bottom = cmp::max((ofs + size) * group.bank_height / column_size, top + 1);
+                let (x, y) = self.bank_origin(group, slot);
+                rects.push(Rect {
+                    x,
+                    y: y + top,
+                    width: self.bank_width,
+                    height: bottom - top,
+                });
+            }
+        }
+        rects
+    }
+
+    /// The top-left corner of a slot of the group's grid.
+    fn bank_origin(&self, group: &RegionGroup, slot: u32) -> (u32, u32) {
+        let (row, column) = (slot / group.nb_columns, slot % group.nb_columns);
+        (
+            group.x + column * (self.bank_width + Self::SPACER_WIDTH),
+            row * group.row_pitch() + group.label_height,
+        )
+    }
+
+    /// Computes the area covered by a section at a given location.
+    /// This is usually a single rectangle, but may be split across banks.
+    fn section_rects(&self, section: &Section, location: &Location) -> Vec<Rect> {
+        self.section_areas(section, location)
+            .into_iter()
+            .map(|(rect, _)| rect)
+            .collect()
+    }
+
+    /// Like `section_rects`, but also reports how much of their edge rows sections cover, if
+    /// anti-aliasing.
+    fn section_areas(&self, section: &Section, location: &Location) -> Vec<(Rect, Coverage)> {
+        let group = &self.groups[self.group_index(section)];
+
+        // Banks that are not displayed simply yield no rectangle
+        let bank_rect = |bank: u32, ofs: u32, size: u32| {
+            let slot = (*group.slots.get(bank as usize)?)?;
+            let bank_size = self.column_size(group.region, bank);
+            let first_byte_row = ofs * group.bank_height / bank_size;
+            // Cap at the end of the bank, of course
+            let last_byte = cmp::min(ofs.saturating_add(size).saturating_sub(1), bank_size - 1);
+            let last_byte_row = cmp::max(last_byte * group.bank_height / bank_size, first_byte_row);
+            let mut height = last_byte_row - first_byte_row + 1;
+            let mut top = first_byte_row;
+            let mut coverage = Coverage::FULL;
+            if self.antialias {
+                // Which fraction of a row's bytes lie within the section
+                let (start, end) = (
+                    f64::from(ofs),
+                    f64::from(cmp::min(ofs.saturating_add(size), bank_size)),
+                );
+                let row_coverage = |row: u32| {
+                    let row_bytes = f64::from(bank_size) / f64::from(group.bank_height);
+                    let row_start = f64::from(row) * row_bytes;
+                    let overlap = end.min(row_start + row_bytes) - start.max(row_start);
+                    (overlap / row_bytes).clamp(0.0, 1.0) as f32
+                };
+                coverage.top = row_coverage(first_byte_row);
+                // A single row is only accounted for once
+                if height > 1 {
+                    coverage.bottom = row_coverage(last_byte_row);
+                }
+            }
+            // Enlarge tiny sections around their middle, but without spilling out of the bank
+            let min_height = cmp::min(self.min_section_px, group.bank_height);
+            if height < min_height {
+                let grown = min_height - height;
+                top = cmp::min(
+                    top.saturating_sub(grown / 2),
+                    group.bank_height - min_height,
+                );
+                height = min_height;
+                // Enlarged sections are drawn solid, as they are not to scale anymore anyway
+                coverage = Coverage::FULL;
+            }
+
+            let (x, y) = self.bank_origin(group, slot);
+            Some((
+                Rect {
+                    x,
+                    y: y + top,
+                    width: self.bank_width,
+                    height,
+                },
+                coverage,
+            ))
+        };
+
+        self.section_spans(section, location)
+            .into_iter()
+            .filter_map(|(bank, ofs, size)| bank_rect(bank, ofs, size))
+            .collect()
+    }
 
-        let x = location.bank * (bank_width + Self::SPACER_WIDTH);
-        let first_byte_row = addr / Self::BYTES_PER_ROW;
-        // Cap at the end of the bank, of course
-        let last_byte_row = cmp::min(addr + nb_bytes - 1, 0x3fff) / Self::BYTES_PER_ROW;
+    fn section_spans(&self, section: &Section, location: &Location) -> Vec<(u32, u32, u32)> {
+        self.rom0_half.section_spans(section, location)
+    }
+
+    fn column_size(&self, region: Region, bank: u32) -> u32 {
+        self.rom0_half.column_size(region, bank)
+    }
+
+    /// Accounts for a section's bytes being added to (or removed from) the banks it's drawn in.
+    fn count_used(&mut self, section: &Section, location: &Location, added: bool) {
+        let region = section.mem_type.region();
+        let group_idx = self.group_index(section);
+        for (bank, ofs, size) in self.section_spans(section, location) {
+            // Bytes past the end of the bank are not drawn, so they don't count either
+            let size = cmp::min(size, self.column_size(region, bank) - ofs);
+            if let Some(used) = self.used[group_idx].get_mut(bank as usize) {
+                *used = if added {
+                    *used + size
+                } else {
+                    used.saturating_sub(size)
+                };
+            }
+        }
+    }
 
-        for y in first_byte_row..=last_byte_row {
-            for x_ofs in 0..bank_width {
-                Self::write_color(pixels, x + x_ofs, y, width, color);
+    /// Clones the canvas, to draw a frame's transient elements onto.
+    fn frame_pixels(&self) -> Vec<u8> {
+        let mut pixels = self.pixels.clone();
+        if self.mark_full {
+            for (group, used) in self.groups.iter().zip(&self.used) {
+                for (bank, &used) in used.iter().enumerate() {
+                    let slot = match group.slots[bank] {
+                        Some(slot) if used >= self.column_size(group.region, bank as u32) => slot,
+                        _ => continue,
+                    };
+                    let (x, y) = self.bank_origin(group, slot);
+                    let rect = Rect {
+                        x,
+                        y,
+                        width: self.bank_width,
+                        height: cmp::min(Self::FULL_MARK_HEIGHT, group.bank_height),
+                    };
+                    Self::fill_rect(&mut pixels, self.raster(), &rect, Self::FULL_MARK_COLOR);
+                }
             }
         }
+        pixels
+    }
+
+    fn is_highlighted(&self, section: &Section, location: &Location) -> bool {
+        self.highlight
+            .iter()
+            .any(|regex| regex.is_match(&section.name))
+            || self
+                .watch_ranges
+                .iter()
+                .any(|range| range.overlaps(section, location))
     }
 
     pub fn settle(&mut self, section: &Section, location: &Location) {
-        let width = self.width();
-        let bank_width = self.bank_width;
+        let color = self.settled_color(section, location);
+        for (rect, coverage) in self.section_areas(section, location) {
+            let raster = self.raster();
+            Self::paint_rect(&mut self.pixels, raster, &rect, coverage, color, 1.0);
+        }
+        self.count_used(section, location, true);
+    }
 
-        Self::draw_rect(
-            &mut self.pixels,
-            location,
-            section.size.into(),
-            width,
-            bank_width,
-            Self::FILLED_COLOR,
-        );
+    fn settled_color(&self, section: &Section, location: &Location) -> Color {
+        if self.is_highlighted(section, location) {
+            Self::HIGHLIGHT_COLOR
+        } else if self.show_free {
+            // The space is no longer free, so it looks like what normally is
+            Self::EMPTY_COLOR
+        } else {
+            self.section_color(section)
+        }
+    }
+
+    /// The color a settled section is drawn in, based on its name if colors are picked that way.
+    fn section_color(&self, section: &Section) -> Color {
+        if let Some(color) = self.palette.color(&section.name) {
+            color
+        } else {
+            match self.name_color_seed {
+                Some(seed) => name_color(&section.name, seed),
+                None => Self::FILLED_COLOR,
+            }
+        }
+    }
+
+    /// Repaints the area a section covered as if it had never been settled, leaving a hole instead.
+    pub fn unsettle(&mut self, section: &Section, location: &Location) {
+        let raster = self.raster();
+        for rect in self.section_rects(section, location) {
+            for y in rect.y..rect.y + rect.height {
+                for x in rect.x..rect.x + rect.width {
+                    let idx = raster.index(x, y);
+                    self.pixels[idx..idx + 3].copy_from_slice(&self.background[idx..idx + 3]);
+                }
+            }
+        }
+        self.count_used(section, location, false);
     }
 
     pub fn overlay(&self, section: &Section, location: &Location) -> Vec<u8> {
-        let mut pixels = self.pixels.clone();
-        let width = self.width();
-        let bank_width = self.bank_width;
+        let color = self.overlay_color(section, location);
+        let mut pixels = self.frame_pixels();
+        let mut areas = self.section_areas(section, location);
+        if self.attempt_style == AttemptStyle::Dots {
+            // Only mark where the section begins
+            areas.truncate(1);
+            for (rect, coverage) in &mut areas {
+                let size = cmp::min(Self::DOT_SIZE, self.bank_width);
+                *rect = Rect {
+                    x: rect.x + (rect.width - size) / 2,
+                    y: cmp::min(rect.y.saturating_sub(size / 2), Self::HEIGHT - size),
+                    width: size,
+                    height: size,
+                };
+                *coverage = Coverage::FULL;
+            }
+        }
+        for (rect, coverage) in areas {
+            Self::paint_rect(
+                &mut pixels,
+                self.raster(),
+                &rect,
+                coverage,
+                color,
+                self.overlay_alpha,
+            );
+            self.draw_border(&mut pixels, &rect);
+        }
+        pixels
+    }
 
-        Self::draw_rect(
-            &mut pixels,
-            location,
-            section.size.into(),
-            width,
-            bank_width,
-            Self::OVERLAY_COLOR,
-        );
+    fn overlay_color(&self, section: &Section, location: &Location) -> Color {
+        if self.is_highlighted(section, location) {
+            Self::HIGHLIGHT_COLOR
+        } else {
+            self.palette
+                .color(&section.name)
+                .unwrap_or(Self::OVERLAY_COLOR)
+        }
+    }
+
+    /// Draws an attempt for good, in its overlay's color, without counting it as used space.
+    pub fn stamp(&mut self, section: &Section, location: &Location) {
+        let color = self.overlay_color(section, location);
+        for (rect, coverage) in self.section_areas(section, location) {
+            let raster = self.raster();
+            Self::paint_rect(&mut self.pixels, raster, &rect, coverage, color, 1.0);
+        }
+    }
+
+    /// Only draws the given fraction of the rows of sections that were just settled, from the top,
+    /// as if they were growing into place.
+    pub fn grow<'a>(
+        &self,
+        sections: impl Iterator<Item = (&'a Section, &'a Location)>,
+        fraction: f32,
+    ) -> Vec<u8> {
+        let mut pixels = self.frame_pixels();
+        let raster = self.raster();
+        for (section, location) in sections {
+            for rect in self.section_rects(section, location) {
+                let nb_grown = (rect.height as f32 * fraction).ceil() as u32;
+                for y in rect.y + nb_grown..rect.y + rect.height {
+                    for x in rect.x..rect.x + rect.width {
+                        let idx = raster.index(x, y);
+                        pixels[idx..idx + 3].copy_from_slice(&self.background[idx..idx + 3]);
+                    }
+                }
+            }
+        }
         pixels
     }
-}
 
-struct OneSecEncoder {
-    config: EncoderConfig,
-    encoder: Encoder,
-    encodes: u8,
-}
+    /// Highlights sections that were just settled, blending the flash color over them.
+    pub fn flash<'a>(
+        &self,
+        sections: impl Iterator<Item = (&'a Section, &'a Location)>,
+        alpha: f32,
+    ) -> Vec<u8> {
+        let mut pixels = self.frame_pixels();
+        for (section, location) in sections {
+            for (rect, coverage) in self.section_areas(section, location) {
+                Self::paint_rect(
+                    &mut pixels,
+                    self.raster(),
+                    &rect,
+                    coverage,
+                    Self::FLASH_COLOR,
+                    alpha,
+                );
+            }
+        }
+        pixels
+    }
 
-impl OneSecEncoder {
-    fn new(config: EncoderConfig) -> Result<Self, openh264::Error> {
-        Ok(Self {
-            config,
-            encoder: Encoder::with_config(config)?,
-            encodes: 0,
-        })
+    /// Shows everywhere a section was attempted, and where it was settled on top of that.
+    pub fn diagnosis(
+        &self,
+        section: &Section,
+        attempts: &[&Location],
+        settled: &Location,
+    ) -> Vec<u8> {
+        let mut pixels = self.frame_pixels();
+        for location in attempts {
+            for (rect, coverage) in self.section_areas(section, location) {
+                Self::paint_rect(
+                    &mut pixels,
+                    self.raster(),
+                    &rect,
+                    coverage,
+                    Self::OVERLAY_COLOR,
+                    Self::DIAGNOSIS_ATTEMPT_ALPHA,
+                );
+            }
+        }
+        for (rect, coverage) in self.section_areas(section, settled) {
+            Self::paint_rect(
+                &mut pixels,
+                self.raster(),
+                &rect,
+                coverage,
+                Self::DIAGNOSIS_SETTLED_COLOR,
+                1.0,
+            );
+            self.draw_border(&mut pixels, &rect);
+        }
+        pixels
+    }
+
+    /// Draws the title card, with each line centered on a black background, the first one bigger.
+    pub fn title_card(&self, lines: &[String]) -> Vec<u8> {
+        const MARGIN: u32 = 8;
+        const TITLE_SCALE: u32 = 4;
+        const DETAILS_SCALE: u32 = 2;
+
+        let mut pixels = vec![0; self.pixels.len()];
+        let advance = font::GLYPH_WIDTH + 1;
+        let max_width = self.width().saturating_sub(2 * MARGIN);
+        // Shrink lines that wouldn't fit, and cut them off if that's still not enough
+        let lines: Vec<(String, u32)> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let max_scale = if i == 0 { TITLE_SCALE } else { DETAILS_SCALE };
+                let nb_chars = cmp::max(line.chars().count() as u32, 1);
+                let scale = (max_width / (nb_chars * advance)).clamp(1, max_scale);
+                let max_chars = usize::try_from(max_width / (advance * scale)).unwrap();
+                (line.chars().take(max_chars).collect(), scale)
+            })
+            .collect();
+
+        let line_height = |scale: u32| (font::GLYPH_HEIGHT + 3) * scale;
+        let total_height: u32 = lines.iter().map(|&(_, scale)| line_height(scale)).sum();
+        let mut y = self.height().saturating_sub(total_height) / 2;
+        for (text, scale) in &lines {
+            let text_width = (text.chars().count() as u32 * advance).saturating_sub(1) * scale;
+            let x = self.width().saturating_sub(text_width) / 2;
+            Self::draw_text(
+                &mut pixels,
+                self.raster(),
+                x,
+                y,
+                text,
+                *scale,
+                Self::LABEL_COLOR,
+            );
+            y += line_height(*scale);
+        }
+        pixels
     }
 
-    fn encode<T: YUVSource>(
-        &mut self,
-        source: &T,
-    ) -> Result<EncodedBitStream<'_>, openh264::Error> {
-        if self.encodes == 60 {
-            self.encodes = 0;
-            self.encoder = Encoder::with_config(self.config)?;
+    /// Draws the overlay's border around a rectangle, in whichever of black or white contrasts
+    /// the most with each pixel beneath it.
+    fn draw_border(&self, pixels: &mut [u8], rect: &Rect) {
+        let border = self.overlay_border;
+        if border == 0 {
+            return;
         }
 
-        let encoded = self.encoder.encode(source);
+        // Clip to the canvas
+        let left = rect.x.saturating_sub(border);
+        let top = rect.y.saturating_sub(border);
+        let right = cmp::min(rect.x + rect.width + border, self.width);
+        let bottom = cmp::min(rect.y + rect.height + border, Self::HEIGHT);
+        for y in top..bottom {
+            for x in left..right {
+                let inside = (rect.x..rect.x + rect.width).contains(&x)
+                    && (rect.y..rect.y + rect.height).contains(&y);
+                if inside {
+                    continue;
+                }
 
-        self.encodes += 1;
-        encoded
+                let idx = self.raster().index(x, y);
+                let beneath = &self.pixels[idx..idx + 3];
+                let luma = 299 * u32::from(beneath[0])
+                    + 587 * u32::from(beneath[1])
+                    + 114 * u32::from(beneath[2]);
+                let color = if luma > 128 * 1000 {
+                    Self::BORDER_DARK_COLOR
+                } else {
+                    Self::BORDER_LIGHT_COLOR
+                };
+                Self::write_color(pixels, x, y, self.raster(), color);
+            }
+        }
     }
 }
 
-pub fn render(sequence: &Sequence, out_path: &Path) -> Result<(), RenderError> {
-    eprint!("Rendering...\r");
+/// Produces the pixels of each rendered frame, in order.
+///
+/// Sections are settled onto the canvas as the iteration progresses, so the frames must be
+/// consumed in order; the canvas is left in its final state once the iterator is exhausted.
+pub struct Frames<'a> {
+    sequence: &'a Sequence,
+    canvas: Canvas,
+    regions: Vec<Region>,
+    /// The only memory types rendered, if not empty.
+    mem_types: Vec<MemType>,
+    next_index: usize,
+    /// The last frame yielded, which gets settled if the next one belongs to another section.
+    prev_frame: Option<&'a Frame>,
+    pace: Pace,
+    /// How many bytes have been settled so far.
+    settled_bytes: u64,
+    /// How many frames are left to yield before advancing, when pacing by bytes.
+    nb_repeats: u64,
+    /// How many frames are dropped after each one yielded.
+    frame_skip: u32,
+    /// Only every this many frames are yielded, after everything else.
+    sample_every: u32,
+    /// When sampling, how many frames there are before that, and how many were gone through.
+    nb_unsampled: Option<(usize, usize)>,
+    /// How many frames are inserted to grow sections as they are settled, before flashing them.
+    nb_grow_frames: u32,
+    /// How many frames are inserted to flash sections as they are settled.
+    nb_flash_frames: u32,
+    /// The frames whose sections were settled since the last flash.
+    just_settled: Vec<&'a Frame>,
+    flash: Option<Flash<'a>>,
+    /// When diagnosing settles, everywhere each section was attempted, by ID.
+    attempts: Option<Vec<Vec<&'a Location>>>,
+    /// How many frames each section is held for, when diagnosing settles.
+    nb_hold_frames: u32,
+    /// The section being diagnosed, with the index it's shown at and how many frames it's still
+    /// held for.
+    held: Option<(usize, &'a Frame, u32)>,
+    title_card: Vec<String>,
+    /// How many frames the title card is still shown for.
+    nb_title_frames: u32,
+    /// If only showing problems, which of the sequence's frames are close enough to one.
+    kept: Option<Vec<bool>>,
+    /// Whether attempts drawn exactly like the frame before them are dropped.
+    coalesce: bool,
+    /// Whether every attempt is kept drawn, instead of settling sections.
+    accumulate: bool,
+    /// How many times sections were settled or removed so far, to tell when the canvas changed.
+    nb_changes: u64,
+    /// The last frame yielded, with how many changes there had been by then, and its overlay's
+    /// section and areas, if coalescing.
+    #[allow(clippy::type_complexity)]
+    last_drawn: Option<(usize, u64, usize, Vec<(Rect, Coverage)>)>,
+    /// How many frames have been shown (or skipped) so far.
+    nb_shown: usize,
+    /// If showing the largest gaps, how many frames there are in total, as they are drawn over
+    /// the last one, and where they are.
+    largest_gaps: Option<(usize, Vec<Rect>)>,
+}
 
-    let out = BufWriter::new(File::create(out_path)?);
-    let mut canvas = Canvas::new(sequence.nb_banks);
-    let mut encoder = OneSecEncoder::new(EncoderConfig::new(canvas.width(), canvas.height()))?;
-    let section = |section_id| &sequence.sections[section_id];
+/// Sections that are being grown and flashed, before yielding the next frame.
+struct Flash<'a> {
+    settled: Vec<&'a Frame>,
+    /// How many grow and flash frames have been yielded so far.
+    step: u32,
+    next: Option<(usize, &'a Frame)>,
+}
 
-    let fcc = |code: &[u8; 4]| FourCC { value: *code };
-    let mut writer = Mp4Writer::write_start(
-        out,
-        &Mp4Config {
-            major_brand: fcc(b"isom"),
-            minor_version: 512,
-            compatible_brands: vec![fcc(b"isom"), fcc(b"iso2"), fcc(b"avc1"), fcc(b"mp41")],
-            timescale: 60,
-        },
-    )?;
+/// A frame to be yielded.
+pub enum Shown<'a> {
+    /// One of the sequence's frames, with its index.
+    Frame(usize, &'a Frame),
+    /// A step of the sections just settled growing, along with the index of the frame that will
+    /// follow it.
+    Grow(usize, u32),
+    /// A step of the current flash, along with the index of the frame that will follow it.
+    Flash(usize, u32),
+    /// A section that was just settled, shown along with all of its attempts, and the index of
+    /// the frame that will follow it.
+    Settled(usize, &'a Frame),
+    /// The title card, before any of the sequence's frames.
+    Title,
+}
 
-    writer.add_track(&TrackConfig {
-        track_type: TrackType::Video,
-        timescale: 60,
-        language: "eng".to_string(), // No real language so to speak...
-        media_conf: MediaConfig::AvcConfig(AvcConfig {
-            width: canvas.width().try_into().unwrap(),
-            height: Canvas::HEIGHT.try_into().unwrap(),
-            seq_param_set: vec![
-                0, // ???
-                0, // avc_profile_indication
-                0, // profile_compatibility
-                0, // avc_level_indication
-            ],
-            pic_param_set: vec![],
-        }),
-    })?;
-
-    let mut iter = sequence
-        .frames
-        .iter()
-        .enumerate()
-        .map(|(i, frame)| (i, frame, section(frame.section_id)))
-        .filter(|(_, _, section)| matches!(section.mem_type, MemType::Rom0 | MemType::Romx))
-        .peekable();
-
-    while let Some((i, frame, section)) = iter.next() {
-        eprint!("Rendering... {} / {}\r", i, sequence.frames.len());
-
-        let pixels = canvas.overlay(section, &frame.location);
-        let mut yuv = RBGYUVConverter::new(
-            canvas.width().try_into().unwrap(),
-            canvas.height().try_into().unwrap(),
-        );
-        yuv.convert(&pixels);
+impl<'a> Frames<'a> {
+    /// How many of the sequence's frames are shown before and after each problem, if only
+    /// showing those.
+    const PROBLEM_CONTEXT: usize = 10;
 
-        let mut bytes = vec![];
-        encoder.encode(&yuv)?.write_vec(&mut bytes);
+    /// The frames of the output; when sampling or showing the largest gaps, this first counts
+    /// them, which a caller that has counted them already can spare with [`Frames::counted`].
+    pub fn new(sequence: &'a Sequence, options: &Options) -> Self {
+        let nb_unsampled = (options.sample_every != 1 || options.show_largest_gap)
+            .then(|| Self::nb_unsampled(sequence, options));
+        Self::build(sequence, options, nb_unsampled)
+    }
 
-        writer.write_sample(
-            1,
-            &Mp4Sample {
-                start_time: i.try_into().unwrap(),
-                duration: 1,
-                rendering_offset: 0,
-                is_sync: true,
-                bytes: bytes.into(),
-            },
-        )?;
+    /// Like [`Frames::new`], given how many frames there are before sampling.
+    pub fn counted(sequence: &'a Sequence, options: &Options, nb_unsampled: usize) -> Self {
+        Self::build(sequence, options, Some(nb_unsampled))
+    }
 
-        // If the next frame uses a different section, "settle" the current one's
-        if iter
-            .peek()
-            .map(|(_, next_frame, _)| next_frame.section_id != frame.section_id)
-            == Some(true)
-        {
-            canvas.settle(section, &frame.location);
-        }
+    /// How many frames there are before sampling; this goes through all of them, without
+    /// rendering them.
+    pub fn nb_unsampled(sequence: &'a Sequence, options: &Options) -> usize {
+        let options = Options {
+            sample_every: 1,
+            show_largest_gap: false,
+            ..options.clone()
+        };
+        Self::build(sequence, &options, None).nb_remaining()
     }
 
-    writer.write_end()?;
+    /// How many frames are yielded, out of this many before sampling.
+    pub fn nb_sampled(nb_unsampled: usize, options: &Options) -> usize {
+        nb_unsampled.div_ceil(options.sample_every as usize)
+    }
 
-    eprintln!("Rendering... - Done.      ");
-    Ok(())
+    /// How many frames are yielded; this goes through all of them, without rendering them.
+    pub fn count(sequence: &'a Sequence, options: &Options) -> usize {
+        Self::nb_sampled(Self::nb_unsampled(sequence, options), options)
+    }
+
+    fn build(sequence: &'a Sequence, options: &Options, nb_unsampled: Option<usize>) -> Self {
+        let attempts = options.diagnose_settles.then(|| {
+            let mut attempts = vec![Vec::new(); sequence.sections.len()];
+            for frame in &sequence.frames {
+                if frame.kind == FrameKind::Attempt {
+                    attempts[frame.section_id].push(&frame.location);
+                }
+            }
+            attempts
+        });
+        // The last run of frames may be shorter, and it must stop on the last frame, as whatever
+        // comes after it isn't shown
+        let count = |nb_unsampled: Option<usize>| {
+            nb_unsampled.expect("Sampling and showing the largest gaps require counting frames")
+        };
+        let canvas = Canvas::new(sequence, options);
+        let largest_gaps = options.show_largest_gap.then(|| {
+            let nb_frames = Self::nb_sampled(count(nb_unsampled), options);
+            (nb_frames, canvas.largest_gap_rects(sequence))
+        });
+        // The last run of frames may be shorter, and it must stop on the last frame, as whatever
+        // comes after it isn't shown
+        let nb_unsampled = (options.sample_every != 1).then(|| (count(nb_unsampled), 0));
+        Self {
+            sequence,
+            canvas,
+            regions: options.regions.clone(),
+            mem_types: options.mem_types.clone(),
+            next_index: 0,
+            prev_frame: None,
+            pace: options.pace,
+            settled_bytes: 0,
+            nb_repeats: 0,
+            frame_skip: options.frame_skip,
+            sample_every: options.sample_every,
+            nb_unsampled,
+            nb_grow_frames: options.grow_frames,
+            nb_flash_frames: options.flash_frames,
+            just_settled: Vec::new(),
+            flash: None,
+            attempts,
+            nb_hold_frames: cmp::max(options.fps / 2, 1),
+            held: None,
+            title_card: options.title_card.clone(),
+            nb_title_frames: if options.title_card.is_empty() {
+                0
+            } else {
+                options.fps
+            },
+            kept: options.only_problems.then(|| {
+                let mut kept = vec![false; sequence.frames.len()];
+                for i in sequence.problems() {
+                    let start = i.saturating_sub(Self::PROBLEM_CONTEXT);
+                    let end = cmp::min(i + Self::PROBLEM_CONTEXT + 1, kept.len());
+                    kept[start..end].fill(true);
+                }
+                kept
+            }),
+            coalesce: options.coalesce_identical,
+            accumulate: options.no_settle,
+            nb_changes: 0,
+            last_drawn: None,
+            nb_shown: 0,
+            largest_gaps,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.canvas.width()
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.canvas.layout()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.canvas.height()
+    }
+
+    /// The total amount of frames in the sequence, including those that won't be rendered.
+    pub fn total(&self) -> usize {
+        self.sequence.frames.len()
+    }
+
+    fn section(&self, frame: &Frame) -> &'a Section {
+        &self.sequence.sections[frame.section_id]
+    }
+
+    /// The middle of where the latest attempt is drawn, in the output's coordinates, if anywhere.
+    fn focus(&self) -> Option<(f32, f32)> {
+        let frame = self.prev_frame?;
+        let rects = self
+            .canvas
+            .section_rects(self.section(frame), &frame.location);
+        let x_min = rects.iter().map(|rect| rect.x).min()?;
+        let x_max = rects.iter().map(|rect| rect.x + rect.width).max()?;
+        let y_min = rects.iter().map(|rect| rect.y).min()?;
+        let y_max = rects.iter().map(|rect| rect.y + rect.height).max()?;
+        let (x, y) = ((x_min + x_max) as f32 / 2.0, (y_min + y_max) as f32 / 2.0);
+        // A transposed canvas's columns are the output's rows
+        Some(if self.canvas.horizontal {
+            (y, x)
+        } else {
+            (x, y)
+        })
+    }
+
+    /// Writes the frame of the output each section is shown settled from over where it ends up,
+    /// centered on the first of its rectangles that the number fits in; pixels are laid out like
+    /// the canvas'.
+    fn annotate_settles(&self, pixels: &mut [u8], settled: &[Option<u64>]) {
+        let canvas = &self.canvas;
+        let placements = self.sequence.final_placements();
+        for (section_id, section) in self.sequence.sections.iter().enumerate() {
+            let (location, frame) = match (placements[section_id], settled[section_id]) {
+                (Some(location), Some(frame)) if self.is_rendered(section) => (location, frame),
+                _ => continue,
+            };
+            let label = frame.to_string();
+            let label_width = label.len() as u32 * (font::GLYPH_WIDTH + 1) - 1;
+            // Text isn't transposed, so it's laid out against the output's rectangles
+            let fitting = canvas
+                .section_rects(section, location)
+                .into_iter()
+                .map(|rect| {
+                    if canvas.horizontal {
+                        (rect.y, rect.x, rect.height, rect.width)
+                    } else {
+                        (rect.x, rect.y, rect.width, rect.height)
+                    }
+                })
+                .find(|&(_, _, width, height)| {
+                    label_width <= width && font::GLYPH_HEIGHT <= height
+                });
+            if let Some((x, y, width, height)) = fitting {
+                Canvas::draw_text(
+                    pixels,
+                    canvas.raster(),
+                    x + (width - label_width) / 2,
+                    y + (height - font::GLYPH_HEIGHT) / 2,
+                    &label,
+                    1,
+                    Canvas::ANNOTATION_COLOR,
+                );
+            }
+        }
+    }
+
+    /// Whether the section is displayed at all, depending on its region and memory type.
+    pub fn is_rendered(&self, section: &Section) -> bool {
+        self.regions.contains(&section.mem_type.region())
+            && (self.mem_types.is_empty() || self.mem_types.contains(&section.mem_type))
+    }
+
+    /// The canvas, with all sections settled so far.
+    pub fn pixels(&self) -> &[u8] {
+        &self.canvas.pixels
+    }
+
+    /// Skips the given amount of frames, without rendering them.
+    pub fn fast_forward(&mut self, nb_frames: usize) {
+        for _ in 0..nb_frames {
+            if self.next_shown().is_none() {
+                break;
+            }
+        }
+    }
+
+    /// Moves on to the next frame without rendering it, and returns what it shows.
+    pub fn skip_shown(&mut self) -> Option<Shown<'a>> {
+        self.next_shown()
+    }
+
+    /// Moves on to the next frame without rendering it, and returns the section it shows, if any.
+    pub fn skip_frame(&mut self) -> Option<Option<&'a Section>> {
+        let frame = match self.next_shown()? {
+            Shown::Frame(_, frame) => frame,
+            Shown::Grow(..) | Shown::Flash(..) => self.flash.as_ref().unwrap().settled[0],
+            Shown::Settled(_, frame) => frame,
+            Shown::Title => return Some(None),
+        };
+        Some(Some(self.section(frame)))
+    }
+
+    /// How many frames are left to yield; this consumes the iterator, but without rendering them.
+    pub fn nb_remaining(mut self) -> usize {
+        let mut nb_frames = 0;
+        while self.skip_frame().is_some() {
+            nb_frames += 1;
+        }
+        nb_frames
+    }
+
+    /// Picks what to yield next, going through (and thus settling) the frames between samples.
+    /// Each run of frames is sampled at its last one, so that the very last frame is always shown.
+    fn next_shown(&mut self) -> Option<Shown<'a>> {
+        let nb_frames = match &mut self.nb_unsampled {
+            Some((nb_unsampled, nb_gone_through)) => {
+                let nb_frames =
+                    cmp::min(self.sample_every as usize, *nb_unsampled - *nb_gone_through);
+                *nb_gone_through += nb_frames;
+                // Past the end, still let the log's end be processed
+                cmp::max(nb_frames, 1)
+            }
+            None => 1,
+        };
+        let mut shown = self.next_unsampled()?;
+        for _ in 1..nb_frames {
+            shown = self.next_unsampled()?;
+        }
+        self.nb_shown += 1;
+        Some(shown)
+    }
+
+    /// Picks what would be yielded next: growing then flashing the sections just settled, if any,
+    /// then the next frame.
+    fn next_unsampled(&mut self) -> Option<Shown<'a>> {
+        if self.nb_title_frames != 0 {
+            self.nb_title_frames -= 1;
+            return Some(Shown::Title);
+        }
+        if self.attempts.is_some() {
+            return self.next_diagnosis();
+        }
+        if self.flash.is_none() {
+            let next = self.next_kept_frame();
+            if self.just_settled.is_empty() {
+                let (i, frame) = next?;
+                return Some(Shown::Frame(i, frame));
+            }
+            self.flash = Some(Flash {
+                settled: std::mem::take(&mut self.just_settled),
+                step: 0,
+                next,
+            });
+        }
+
+        let flash = self.flash.as_mut().unwrap();
+        if flash.step < self.nb_grow_frames + self.nb_flash_frames {
+            flash.step += 1;
+            let i = flash.next.map_or(self.sequence.frames.len(), |(i, _)| i);
+            return Some(if flash.step <= self.nb_grow_frames {
+                Shown::Grow(i, flash.step)
+            } else {
+                Shown::Flash(i, flash.step - self.nb_grow_frames)
+            });
+        }
+        let (i, frame) = self.flash.take().unwrap().next?;
+        Some(Shown::Frame(i, frame))
+    }
+
+    /// Holds on each section as it gets settled, instead of showing its attempts one by one.
+    fn next_diagnosis(&mut self) -> Option<Shown<'a>> {
+        if let Some((i, frame, nb_left)) = &mut self.held {
+            if *nb_left != 0 {
+                *nb_left -= 1;
+                return Some(Shown::Settled(*i, frame));
+            }
+        }
+        // The last sections are only settled once there are no frames left
+        while self.just_settled.is_empty() {
+            if self.advance().is_none() && self.just_settled.is_empty() {
+                self.held = None;
+                return None;
+            }
+        }
+        let frame = self.just_settled.remove(0);
+        self.held = Some((self.next_index, frame, self.nb_hold_frames - 1));
+        Some(Shown::Settled(self.next_index, frame))
+    }
+
+    /// Picks which frame to yield next, dropping the ones skipped since the last one.
+    fn next_kept_frame(&mut self) -> Option<(usize, &'a Frame)> {
+        // The very first frame is always kept
+        if self.prev_frame.is_some() {
+            for _ in 0..self.frame_skip {
+                self.next_frame()?;
+            }
+        }
+        loop {
+            let (i, frame) = self.next_frame()?;
+            if !self.kept.as_ref().is_none_or(|kept| kept[i]) {
+                continue;
+            }
+            if self.coalesce {
+                let areas = self
+                    .canvas
+                    .section_areas(self.section(frame), &frame.location);
+                // Frames repeated on purpose, when pacing by bytes, are kept
+                let drawn = (i, self.nb_changes, frame.section_id, areas);
+                if let Some(last) = &self.last_drawn {
+                    if last.0 != drawn.0
+                        && (last.1, last.2, &last.3) == (drawn.1, drawn.2, &drawn.3)
+                    {
+                        continue;
+                    }
+                }
+                self.last_drawn = Some(drawn);
+            }
+            return Some((i, frame));
+        }
+    }
+
+    /// Picks which frame to yield next, depending on the pacing.
+    fn next_frame(&mut self) -> Option<(usize, &'a Frame)> {
+        match self.pace {
+            Pace::Attempts => self.advance(),
+            Pace::Bytes(bytes_per_frame) => {
+                // Hold on the current frame once per threshold crossed by the last settle
+                while self.nb_repeats == 0 {
+                    let nb_frames_before = self.settled_bytes / bytes_per_frame;
+                    self.advance()?;
+                    self.nb_repeats = self.settled_bytes / bytes_per_frame - nb_frames_before;
+                }
+                self.nb_repeats -= 1;
+                Some((self.next_index - 1, self.prev_frame.unwrap()))
+            }
+        }
+    }
+
+    fn settle(&mut self, frame: &'a Frame) {
+        let section = self.section(frame);
+        self.settled_bytes += u64::from(section.size);
+        // The attempts are all that's drawn then
+        if self.accumulate {
+            return;
+        }
+        self.nb_changes += 1;
+        self.canvas.settle(section, &frame.location);
+        if self.nb_grow_frames != 0 || self.nb_flash_frames != 0 || self.attempts.is_some() {
+            self.just_settled.push(frame);
+        }
+    }
+
+    /// Moves on to the next rendered frame, settling the previous one's section if appropriate.
+    fn advance(&mut self) -> Option<(usize, &'a Frame)> {
+        let sequence = self.sequence;
+        // Without explicit placements, the previous frame's section may still have to be settled
+        let mut unsettled = self.prev_frame.filter(|_| !sequence.has_placements);
+        let mut next = None;
+        for (i, frame) in sequence.frames.iter().enumerate().skip(self.next_index) {
+            let section = self.section(frame);
+            if !self.is_rendered(section) {
+                continue;
+            }
+            match frame.kind {
+                FrameKind::Attempt => {
+                    // Skipped frames' attempts are drawn too, so that none are missing in the end
+                    if self.accumulate {
+                        self.canvas.stamp(section, &frame.location);
+                    }
+                    next = Some((i, frame));
+                    break;
+                }
+                FrameKind::Placement => self.settle(frame),
+                FrameKind::Removal if self.accumulate => {}
+                FrameKind::Removal => {
+                    // It may be the section being removed, so it must be there first
+                    if let Some(prev_frame) = unsettled.take() {
+                        self.settle(prev_frame);
+                    }
+                    self.canvas.unsettle(section, &frame.location);
+                    self.nb_changes += 1;
+                    self.settled_bytes = self.settled_bytes.saturating_sub(section.size.into());
+                }
+            }
+        }
+
+        // Without explicit placements, "settle" the previous frame's section if the next frame
+        // uses a different one
+        if let Some(prev_frame) = unsettled {
+            if next.map(|(_, frame)| frame.section_id) != Some(prev_frame.section_id) {
+                self.settle(prev_frame);
+            }
+        }
+
+        let (i, frame) = match next {
+            Some(next) => next,
+            None => {
+                self.next_index = sequence.frames.len();
+                // Everything has been settled, don't do it again
+                self.prev_frame = None;
+                return None;
+            }
+        };
+        self.next_index = i + 1;
+        self.prev_frame = Some(frame);
+        Some((i, frame))
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = (usize, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, mut pixels) = match self.next_shown()? {
+            Shown::Frame(i, frame) => {
+                Some((i, self.canvas.overlay(self.section(frame), &frame.location)))
+            }
+            Shown::Grow(i, step) => {
+                let settled = &self.flash.as_ref().unwrap().settled;
+                let sections = settled
+                    .iter()
+                    .map(|frame| (self.section(frame), &frame.location));
+                let fraction = step as f32 / (self.nb_grow_frames + 1) as f32;
+                Some((i, self.canvas.grow(sections, fraction)))
+            }
+            Shown::Flash(i, step) => {
+                let settled = &self.flash.as_ref().unwrap().settled;
+                let sections = settled
+                    .iter()
+                    .map(|frame| (self.section(frame), &frame.location));
+                // Fade from the flash color back to the normal one
+                let alpha = 1.0 - step as f32 / (self.nb_flash_frames + 1) as f32;
+                Some((i, self.canvas.flash(sections, alpha)))
+            }
+            Shown::Settled(i, frame) => {
+                let attempts = &self.attempts.as_ref().unwrap()[frame.section_id];
+                Some((
+                    i,
+                    self.canvas
+                        .diagnosis(self.section(frame), attempts, &frame.location),
+                ))
+            }
+            Shown::Title => Some((0, self.canvas.title_card(&self.title_card))),
+        }?;
+        if let Some((nb_frames, rects)) = &self.largest_gaps {
+            if self.nb_shown == *nb_frames {
+                for rect in rects {
+                    Canvas::fill_rect(
+                        &mut pixels,
+                        self.canvas.raster(),
+                        rect,
+                        Canvas::LARGEST_GAP_COLOR,
+                    );
+                }
+            }
+        }
+        Some((i, pixels))
+    }
+}
+
+/// Addresses of interest, from `start` to `end` inclusive, in a given bank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchRange {
+    pub bank: u32,
+    pub start: u16,
+    pub end: u16,
+}
+
+impl WatchRange {
+    /// Whether a section covers any of the range at this location.
+    pub fn overlaps(&self, section: &Section, location: &Location) -> bool {
+        let start = u32::from(location.addr);
+        location.bank == self.bank
+            && start <= u32::from(self.end)
+            && u32::from(self.start) < start.saturating_add(section.size)
+    }
+}
+
+impl fmt::Display for WatchRange {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{:02x}:{:04x}-{:04x}", self.bank, self.start, self.end)
+    }
+}
+
+/// How to display ROM0 sections located past $4000, which can happen with 32 KiB ROM0 layouts.
+#[derive(FromStr, Debug, Clone, Copy, PartialEq, Eq)]
+#[display(style = "lowercase")]
+pub enum Rom0Half {
+    /// Wrap the address around to the beginning of bank 0.
+    Fold,
+    /// Display the upper half in bank 1's column, as that's where it physically is.
+    Romx,
+    /// Cut off whatever lies past the end of bank 0.
+    Clamp,
+    /// Make bank 0's column span all of ROM0, from $0000 to $7FFF, at half the scale of the
+    /// others; this is where it is in the CPU's view, which ROMX banks only share when switched in.
+    Tall,
+}
+
+impl Rom0Half {
+    /// Splits the memory covered by a section at a given location across the banks it is drawn in,
+    /// as `(bank, offset within it, size)`; this is a single bank, except for some ROM0 sections.
+    pub fn section_spans(self, section: &Section, location: &Location) -> Vec<(u32, u32, u32)> {
+        let mem_type = &section.mem_type;
+        let bank_size = u32::from(mem_type.bank_size());
+        let ofs = u32::from(mem_type.offset_of(location.addr));
+        let bank_ofs = u32::from(location.bank_offset(mem_type));
+        let size = section.size;
+        if *mem_type != MemType::Rom0 || ofs.saturating_add(size) <= bank_size {
+            return vec![(location.bank, bank_ofs, size)];
+        }
+
+        // ROM0 sections past $4000 depend on how the ROM's upper half is considered
+        match self {
+            Rom0Half::Fold => vec![(location.bank, bank_ofs, size)],
+            Rom0Half::Tall => vec![(location.bank, cmp::min(ofs, 2 * bank_size - 1), size)],
+            Rom0Half::Clamp => vec![(location.bank, cmp::min(ofs, bank_size - 1), size)],
+            Rom0Half::Romx => {
+                let mut spans = Vec::with_capacity(2);
+                if ofs < bank_size {
+                    spans.push((location.bank, ofs, bank_size - ofs));
+                }
+                let upper_ofs = cmp::max(ofs, bank_size);
+                spans.push((
+                    location.bank + 1,
+                    upper_ofs - bank_size,
+                    ofs.saturating_add(size) - upper_ofs,
+                ));
+                spans
+            }
+        }
+    }
+
+    /// How many bytes a bank's column spans; this is the region's bank size, except for ROM0's
+    /// with `--rom0-half tall`, which spans both halves of it.
+    pub fn column_size(self, region: Region, bank: u32) -> u32 {
+        let bank_size = u32::from(region.bank_size());
+        if self == Rom0Half::Tall && region == Region::Rom && bank == 0 {
+            2 * bank_size
+        } else {
+            bank_size
+        }
+    }
+}
+
+/// Which way banks are laid out.
+#[derive(FromStr, Debug, Clone, Copy, PartialEq, Eq)]
+#[display(style = "lowercase")]
+pub enum Orientation {
+    /// Each bank is a column, with addresses increasing downwards.
+    Vertical,
+    /// Each bank is a row, with addresses increasing rightwards, like a memory map.
+    Horizontal,
+}
+
+/// How sections are split into panels, each showing only its own.
+#[derive(FromStr, Debug, Clone, Copy, PartialEq, Eq)]
+#[display(style = "lowercase")]
+pub enum GroupBy {
+    /// By memory type, e.g. ROM0 and ROMX apart.
+    Type,
+    /// By name, up to the first underscore.
+    Prefix,
+}
+
+impl GroupBy {
+    /// How many panels the canvas is split into at most, across all regions.
+    const MAX_PANELS: usize = 24;
+    /// The panel holding the groups that didn't fit.
+    const OTHER_PANEL: &'static str = "(other)";
+
+    fn key(self, section: &Section) -> String {
+        match self {
+            GroupBy::Type => section.mem_type.to_string(),
+            GroupBy::Prefix => section.name.split('_').next().unwrap().to_string(),
+        }
+    }
+
+    /// The region's panels, in order; each shows the banks its sections are ever drawn in, in
+    /// the order they would be otherwise. Sections of other memory types than these, if any, are
+    /// not drawn, so they aren't grouped either.
+    ///
+    /// Past `max_panels`, only the biggest groups (by total size of their sections) keep their own
+    /// panel, and the others are merged into a last one.
+    fn panels(
+        self,
+        sequence: &Sequence,
+        region: Region,
+        mem_types: &[MemType],
+        banks: Vec<u32>,
+        max_panels: usize,
+    ) -> Vec<(Region, Option<String>, Vec<u32>)> {
+        let mut used_banks = BTreeMap::<_, HashSet<_>>::new();
+        for frame in &sequence.frames {
+            let section = &sequence.sections[frame.section_id];
+            if section.mem_type.region() == region
+                && (mem_types.is_empty() || mem_types.contains(&section.mem_type))
+            {
+                used_banks
+                    .entry(self.key(section))
+                    .or_default()
+                    .insert(frame.location.bank);
+            }
+        }
+        // Keep the region visible, even if it has nothing to split
+        if used_banks.is_empty() {
+            return vec![(region, None, banks)];
+        }
+        let mut other = HashSet::new();
+        if used_banks.len() > max_panels {
+            let mut sizes = HashMap::<_, u64>::new();
+            for section in &sequence.sections {
+                if section.mem_type.region() == region {
+                    *sizes.entry(self.key(section)).or_default() += u64::from(section.size);
+                }
+            }
+            // Bigger first, and in order of names otherwise
+            let mut keys: Vec<_> = used_banks.keys().cloned().collect();
+            keys.sort_by_key(|key| cmp::Reverse(sizes[key]));
+            for key in &keys[max_panels - 1..] {
+                other.extend(used_banks.remove(key).unwrap());
+            }
+        }
+        let panel = |key, used: &HashSet<_>| {
+            let banks = banks.iter().copied().filter(|bank| used.contains(bank));
+            (region, Some(key), banks.collect())
+        };
+        let mut panels: Vec<_> = used_banks
+            .iter()
+            .map(|(key, used)| panel(key.clone(), used))
+            .collect();
+        if !other.is_empty() {
+            panels.push(panel(Self::OTHER_PANEL.to_string(), &other));
+        }
+        panels
+    }
+}
+
+/// How sections being attempted are drawn.
+#[derive(FromStr, Debug, Clone, Copy, PartialEq, Eq)]
+#[display(style = "lowercase")]
+pub enum AttemptStyle {
+    /// Draw the whole area the section would cover.
+    Rect,
+    /// Only draw a small marker where the section would begin.
+    Dots,
+}
+
+/// How to fit the canvas into a resolution whose aspect ratio is different.
+#[derive(FromStr, Debug, Clone, Copy, PartialEq, Eq)]
+#[display(style = "lowercase")]
+pub enum ScaleMode {
+    /// Keep the aspect ratio, and fill the remaining space with black bars.
+    Letterbox,
+    /// Stretch the canvas to cover the whole frame.
+    Stretch,
+}
+
+/// Which banks to display, and in which order.
+#[derive(FromStr, Debug, Clone, Copy, PartialEq, Eq)]
+#[display(style = "lowercase")]
+pub enum BankOrder {
+    /// All banks, by number.
+    Index,
+    /// Only the banks that sections end up in, by number.
+    Used,
+    /// All banks, fullest first.
+    Utilization,
+}
+
+impl BankOrder {
+    /// The banks of the region to display, in order.
+    fn banks(self, sequence: &Sequence, region: Region, rom0_half: Rom0Half) -> Vec<u32> {
+        let mut stats = stats::bank_stats(sequence, &[region], rom0_half);
+        match self {
+            BankOrder::Index => {}
+            BankOrder::Used => {
+                stats.retain(|bank| bank.used != 0);
+                // Keep the region visible, even if it ends up empty
+                if stats.is_empty() {
+                    return vec![0];
+                }
+            }
+            // The sort is stable, so equally full banks remain in order
+            BankOrder::Utilization => stats.sort_by_key(|bank| cmp::Reverse(bank.used)),
+        }
+        stats.iter().map(|bank| bank.bank).collect()
+    }
+}
+
+/// Which kind of file to write.
+#[derive(FromStr, Debug, Clone, Copy, PartialEq, Eq)]
+#[display(style = "lowercase")]
+pub enum Format {
+    /// An H.264 video, in an MP4 container.
+    Mp4,
+    /// An animated PNG, which is lossless but much bigger.
+    Apng,
+    /// A lossless animated WebP, which is much smaller than an animated PNG.
+    Webp,
+    /// A single vector image of how everything ends up, with each section's details on hover.
+    Svg,
+    /// How everything ends up, drawn with colored characters to fit in the terminal.
+    Term,
+}
+
+/// What the frames of the video correspond to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pace {
+    /// One frame per placement attempt.
+    Attempts,
+    /// One frame each time this many more bytes have been placed.
+    Bytes(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Encode a monochrome stream instead of a full-color one.
+    pub grayscale: bool,
+    /// Darken every other row and shift colors apart before encoding, like a CRT screen would.
+    pub crt: bool,
+    /// The H.264 profile the MP4 declares.
+    pub profile: encode::Profile,
+    /// The H.264 level the MP4 declares, as its `level_idc` (ten times the level), if not the
+    /// lowest that fits.
+    pub level: Option<u8>,
+    /// The MP4's major brand.
+    pub mp4_brand: [u8; 4],
+    /// The brands the MP4 declares it's compatible with.
+    pub mp4_compatible_brands: Vec<[u8; 4]>,
+    /// If set, settled sections are colored based on their name, perturbed by this seed.
+    pub name_color_seed: Option<u64>,
+    /// Periodically save progress to a file next to the output, so that rendering can be resumed.
+    pub checkpoint: bool,
+    /// Resume rendering from the checkpoint file, if there is one.
+    pub resume: bool,
+    /// Which regions to display; they are always laid out in address order.
+    pub regions: Vec<Region>,
+    /// If not empty, only sections of these memory types are rendered.
+    pub mem_types: Vec<MemType>,
+    /// How many frames are displayed per second.
+    pub fps: u32,
+    /// If set, frames last however long it takes for the output to last this many seconds,
+    /// instead of following `fps`.
+    pub duration: Option<f64>,
+    pub pace: Pace,
+    pub rom0_half: Rom0Half,
+    pub bank_order: BankOrder,
+    pub orientation: Orientation,
+    /// If set, each region is split into panels, one per group of sections.
+    pub group_by: Option<GroupBy>,
+    pub attempt_style: AttemptStyle,
+    /// How opaque the overlay is, from 0 (invisible) to 1 (hiding what's below it).
+    pub overlay_alpha: f32,
+    /// How thick a border is drawn around the overlay, in pixels.
+    pub overlay_border: u32,
+    /// How many rows sections are drawn across at least, even if they are smaller than that.
+    pub min_section_px: u32,
+    /// Whether sections' partially-covered edge rows are blended in proportion to their coverage.
+    pub antialias: bool,
+    /// Whether banks are marked once they're completely full.
+    pub mark_full: bool,
+    /// Whether each bank's number is written above it.
+    pub bank_labels: bool,
+    /// Whether banks start out colored, with settled sections carving out the free space.
+    pub show_free: bool,
+    /// Whether the largest free block of each bank is drawn over the last frame.
+    pub show_largest_gap: bool,
+    /// Sections whose name fully matches any of these are always drawn in a distinct color.
+    pub highlight: Vec<Regex>,
+    /// Sections drawn over any of these are drawn like highlighted ones there.
+    pub watch_ranges: Vec<WatchRange>,
+    /// Colors for sections by name, taking precedence over all but highlighting.
+    pub palette: Palette,
+    /// If set, a faint line is drawn across banks every this many bytes.
+    pub grid: Option<u32>,
+    /// How many frames are inserted after each section is settled, drawing it growing into place.
+    pub grow_frames: u32,
+    /// How many frames are inserted after each section is settled, flashing it.
+    pub flash_frames: u32,
+    /// Frames to also write as PNG files, by index in the output.
+    pub snapshots: Vec<(usize, PathBuf)>,
+    /// A directory to also write every frame to as a PNG file, if any.
+    pub png_dir: Option<PathBuf>,
+    /// A zip archive to also write every frame into as a PNG file, if any.
+    pub png_zip: Option<PathBuf>,
+    /// Where to also write the last frame as a PNG file, if anywhere.
+    pub still: Option<PathBuf>,
+    /// Where to write how many bytes each encoded frame takes, if anywhere.
+    pub frame_sizes: Option<PathBuf>,
+    /// How many bytes the video may take at most, if there is a limit.
+    pub max_output_bytes: Option<u64>,
+    /// How many encoded keyframes are kept around to reuse for frames drawn the same, if any.
+    pub frame_cache: usize,
+    pub format: Format,
+    /// How many frames a contact sheet shows, at most.
+    pub contact_sheet_frames: u32,
+    /// If set, each frame is scaled to this resolution, as `(width, height)`.
+    pub scale: Option<(u32, u32)>,
+    pub scale_mode: ScaleMode,
+    /// Whether attempts drawn exactly like the frame before them are dropped.
+    pub coalesce_identical: bool,
+    /// Whether every attempt stays drawn, instead of sections being settled.
+    pub no_settle: bool,
+    /// Whether only the frames around sections settled over others or out of bounds are shown.
+    pub only_problems: bool,
+    /// Whether the scaled output only shows a window of the canvas, following the latest attempt.
+    pub follow: bool,
+    /// How many frames are dropped after each one rendered, to shorten the output.
+    pub frame_skip: u32,
+    /// Only every this many frames are kept, counting all of them (not just attempts).
+    pub sample_every: u32,
+    /// Instead of animating attempts, hold on each section as it's settled, showing everywhere
+    /// it was attempted; pacing, frame skipping and flashes don't apply then.
+    pub diagnose_settles: bool,
+    /// If not empty, the lines of a card shown for a second before anything else.
+    pub title_card: Vec<String>,
+    /// Report more details about the rendering.
+    pub verbose: bool,
+    /// Whether stills are annotated with the frame each section was settled at.
+    pub annotate_settle_frame: bool,
+    /// Whether banks are drawn as wide as they may be, however wide that makes the canvas,
+    /// instead of fitting them within `Canvas::MAX_WIDTH`.
+    pub uncapped: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            grayscale: false,
+            crt: false,
+            profile: encode::Profile::Baseline,
+            level: None,
+            mp4_brand: encode::DEFAULT_BRAND,
+            mp4_compatible_brands: encode::DEFAULT_COMPATIBLE_BRANDS.to_vec(),
+            name_color_seed: None,
+            checkpoint: false,
+            resume: false,
+            regions: vec![Region::Rom],
+            mem_types: Vec::new(),
+            fps: 60,
+            duration: None,
+            pace: Pace::Attempts,
+            rom0_half: Rom0Half::Fold,
+            bank_order: BankOrder::Index,
+            orientation: Orientation::Vertical,
+            group_by: None,
+            attempt_style: AttemptStyle::Rect,
+            overlay_alpha: 1.0,
+            overlay_border: 0,
+            min_section_px: 1,
+            antialias: false,
+            mark_full: false,
+            bank_labels: false,
+            show_free: false,
+            show_largest_gap: false,
+            highlight: Vec::new(),
+            watch_ranges: Vec::new(),
+            palette: Palette::default(),
+            grid: None,
+            grow_frames: 0,
+            flash_frames: 0,
+            snapshots: Vec::new(),
+            png_dir: None,
+            png_zip: None,
+            still: None,
+            frame_sizes: None,
+            max_output_bytes: None,
+            frame_cache: 0,
+            format: Format::Mp4,
+            contact_sheet_frames: 16,
+            scale: None,
+            scale_mode: ScaleMode::Letterbox,
+            follow: false,
+            only_problems: false,
+            coalesce_identical: false,
+            no_settle: false,
+            frame_skip: 0,
+            sample_every: 1,
+            diagnose_settles: false,
+            title_card: Vec::new(),
+            verbose: false,
+            annotate_settle_frame: false,
+            uncapped: false,
+        }
+    }
+}
+
+/// When each frame of the output begins, in ticks; this lets frames last fractions of ticks, so
+/// that the output can be fit to a given duration.
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    /// How many ticks there are in a second.
+    pub timescale: u32,
+    /// Frame `i` begins at `i * num / den` ticks.
+    num: u64,
+    den: u64,
+}
+
+impl Clock {
+    /// How many ticks there are in a second when fitting a duration; this is the usual for video.
+    const DURATION_TIMESCALE: u32 = 90000;
+
+    /// Frames last one tick each, unless fitting a duration, which requires counting them first.
+    pub fn new(sequence: &Sequence, options: &Options) -> Self {
+        let nb_frames = options
+            .duration
+            .map_or(0, |_| Frames::count(sequence, options));
+        Self::counted(options, nb_frames)
+    }
+
+    /// Like [`Clock::new`], given how many frames there are.
+    pub fn counted(options: &Options, nb_frames: usize) -> Self {
+        match options.duration {
+            None => Self {
+                timescale: options.fps,
+                num: 1,
+                den: 1,
+            },
+            Some(duration) => Self::fitting(duration, nb_frames),
+        }
+    }
+
+    /// Spreads this many frames over the duration, in seconds; each lasts at least a tick though,
+    /// as samples that last 0 are invalid, so the output may last longer.
+    fn fitting(duration: f64, nb_frames: usize) -> Self {
+        let den = cmp::max(nb_frames, 1) as u64;
+        Self {
+            timescale: Self::DURATION_TIMESCALE,
+            num: cmp::max(
+                (duration * f64::from(Self::DURATION_TIMESCALE)).round() as u64,
+                den,
+            ),
+            den,
+        }
+    }
+
+    /// When a frame begins, in ticks.
+    pub fn start(&self, frame: u64) -> u64 {
+        self.checked_start(frame).unwrap_or(u64::MAX)
+    }
+
+    /// When a frame begins, in ticks, if that fits in 64 bits.
+    pub fn checked_start(&self, frame: u64) -> Option<u64> {
+        (u128::from(frame) * u128::from(self.num) / u128::from(self.den))
+            .try_into()
+            .ok()
+    }
+
+    /// When a frame begins, in a different timescale.
+    pub fn start_in(&self, frame: u64, timescale: u32) -> u64 {
+        (u128::from(self.start(frame)) * u128::from(timescale) / u128::from(self.timescale))
+            .try_into()
+            .unwrap_or(u64::MAX)
+    }
+
+    /// How long a frame lasts, as a fraction of a second with a 16-bit numerator and denominator.
+    pub fn delay(&self, frame: u64) -> (u16, u16) {
+        let (ticks, timescale) = match u16::try_from(self.timescale) {
+            Ok(timescale) => (self.start(frame + 1) - self.start(frame), timescale),
+            // Milliseconds are not as precise, but they're precise enough for a single frame
+            Err(_) => (self.delay_ms(frame), 1000),
+        };
+        (ticks.try_into().unwrap_or(u16::MAX), timescale)
+    }
+
+    /// How long a frame lasts, in milliseconds; at least 1, as delays of 0 tend to mean "as fast
+    /// as possible" instead.
+    pub fn delay_ms(&self, frame: u64) -> u64 {
+        cmp::max(
+            self.start_in(frame + 1, 1000) - self.start_in(frame, 1000),
+            1,
+        )
+    }
+}
+
+/// Where rendered frames end up.
+pub trait VideoSink {
+    fn write_frame(&mut self, pixels: &[u8]) -> Result<(), RenderError>;
+
+    fn finish(self) -> Result<(), RenderError>
+    where
+        Self: Sized;
+}
+
+/// Renders the output, adding anything worth pointing out to `warnings`, and reporting progress
+/// on stderr.
+pub fn render(
+    sequence: &Sequence,
+    out_path: &Path,
+    options: &Options,
+    warnings: &mut Vec<Warning>,
+) -> Result<(), RenderError> {
+    if options.verbose {
+        eprintln!("Layout: {}", Frames::new(sequence, options).layout());
+    }
+    eprint!("Rendering...\r");
+
+    render_with_progress(
+        sequence,
+        out_path,
+        options,
+        warnings,
+        &mut |current, total| eprint!("Rendering... {} / {}\r", current, total),
+    )?;
+
+    eprintln!("Rendering... - Done.      ");
+    Ok(())
+}
+
+/// Like `render`, but reports progress by calling `progress` before rendering each frame, with
+/// the index of the log's frame it shows and the amount of them, instead of printing anything.
+pub fn render_with_progress(
+    sequence: &Sequence,
+    out_path: &Path,
+    options: &Options,
+    warnings: &mut Vec<Warning>,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(), RenderError> {
+    let out = File::create(out_path)?;
+    // Only MP4 output supports checkpoints
+    let result = if options.checkpoint || options.resume {
+        render_mp4(sequence, out, Some(out_path), options, warnings, progress)
+    } else {
+        render_to(sequence, out, options, warnings, progress)
+    };
+    // Don't leave a truncated video behind
+    if let Err(RenderErrorKind::TooLarge(_)) = result.as_ref().map_err(RenderError::kind) {
+        fs::remove_file(out_path)?;
+    }
+    result
+}
+
+/// Like `render_with_progress`, but writes the output to any seekable writer instead of a file,
+/// e.g. a `Cursor` to keep it in memory. Checkpoints are saved next to the output file, so they
+/// are not supported then.
+pub fn render_to<W: Write + Seek>(
+    sequence: &Sequence,
+    out: W,
+    options: &Options,
+    warnings: &mut Vec<Warning>,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(), RenderError> {
+    assert!(
+        !options.checkpoint && !options.resume,
+        "Checkpoints require an output file"
+    );
+    match options.format {
+        Format::Mp4 => render_mp4(sequence, out, None, options, warnings, progress),
+        Format::Apng => render_apng(sequence, out, options, warnings, progress),
+        Format::Webp => render_webp(sequence, out, options, warnings, progress),
+        Format::Svg => render_svg(sequence, out, options),
+        Format::Term => draw_in_terminal(sequence, options, term::size(), out),
+    }
+}
+
+/// Writes the frames that are also requested as PNG files, as they get rendered, so that they
+/// don't require rendering again.
+struct Snapshots<'a> {
+    pending: Vec<&'a (usize, PathBuf)>,
+    png_dir: Option<&'a Path>,
+    png_zip: Option<ZipWriter<BufWriter<File>>>,
+    still: Option<&'a Path>,
+    /// The last frame rendered so far, if it's going to be the still.
+    last: Option<Vec<u8>>,
+    /// If the still is annotated, what to draw it from again, as the frames are scaled already.
+    annotated: Option<(&'a Sequence, &'a Options)>,
+    width: u32,
+    height: u32,
+}
+
+impl<'a> Snapshots<'a> {
+    fn new(
+        sequence: &'a Sequence,
+        options: &'a Options,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, RenderError> {
+        if let Some(dir) = &options.png_dir {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(Self {
+            pending: options.snapshots.iter().collect(),
+            png_dir: options.png_dir.as_deref(),
+            png_zip: match &options.png_zip {
+                Some(path) => Some(ZipWriter::new(BufWriter::new(File::create(path)?))),
+                None => None,
+            },
+            still: options.still.as_deref(),
+            last: None,
+            annotated: options.annotate_settle_frame.then_some((sequence, options)),
+            width,
+            height,
+        })
+    }
+
+    fn frame(&mut self, index: usize, pixels: &[u8]) -> Result<(), RenderError> {
+        for &(_, path) in self.pending.iter().filter(|&&&(i, _)| i == index) {
+            apng::write_png(path, self.width, self.height, pixels)?;
+        }
+        self.pending.retain(|&&(i, _)| i != index);
+        if let Some(dir) = self.png_dir {
+            let path = dir.join(format!("{:06}.png", index));
+            apng::write_png(&path, self.width, self.height, pixels)?;
+        }
+        if let Some(zip) = &mut self.png_zip {
+            let mut png = Vec::new();
+            apng::encode_png(&mut png, self.width, self.height, pixels)?;
+            zip.add(&format!("frame_{:05}.png", index), &png)?;
+        }
+        if self.still.is_some() && self.annotated.is_none() {
+            let last = self.last.get_or_insert_with(Vec::new);
+            last.clear();
+            last.extend_from_slice(pixels);
+        }
+        Ok(())
+    }
+
+    /// Writes the still, falling back to the given pixels if no frames were rendered, and warns
+    /// about the snapshots that could not be taken, e.g. because there are not enough frames.
+    fn finish(self, fallback: &[u8], warnings: &mut Vec<Warning>) -> Result<(), RenderError> {
+        if let Some(zip) = self.png_zip {
+            zip.finish()?;
+        }
+        match (self.still, self.annotated) {
+            (Some(path), Some((sequence, options))) => write_last_frame(sequence, options, path)?,
+            (Some(path), None) => {
+                let pixels = self.last.as_deref().unwrap_or(fallback);
+                apng::write_png(path, self.width, self.height, pixels)?;
+            }
+            (None, _) => {}
+        }
+        warnings.extend(
+            self.pending
+                .into_iter()
+                .map(|&(index, ref path)| Warning::MissingSnapshot(index, path.clone())),
+        );
+        Ok(())
+    }
+}
+
+/// Scales frames to the requested resolution, using nearest-neighbor to keep pixels crisp.
+struct Scaler {
+    width: u32,
+    height: u32,
+    /// For each column of the output, the column of the canvas it shows, if any.
+    columns: Vec<Option<u32>>,
+    /// Same as `columns`, for rows.
+    rows: Vec<Option<u32>>,
+    /// How wide the canvas' rows are, which may be wider than what's shown with `--follow`.
+    stride: u32,
+}
+
+impl Scaler {
+    const BACKGROUND_COLOR: Color = (0, 0, 0);
+
+    fn new(width: u32, height: u32, mode: ScaleMode, src_width: u32, src_height: u32) -> Self {
+        let (content_width, content_height) = match mode {
+            ScaleMode::Stretch => (width, height),
+            // Fit whichever dimension is the most constraining
+            ScaleMode::Letterbox => {
+                if u64::from(width) * u64::from(src_height)
+                    <= u64::from(height) * u64::from(src_width)
+                {
+                    let content_height =
+                        u64::from(src_height) * u64::from(width) / u64::from(src_width);
+                    (width, cmp::max(content_height.try_into().unwrap(), 1))
+                } else {
+                    let content_width =
+                        u64::from(src_width) * u64::from(height) / u64::from(src_height);
+                    (cmp::max(content_width.try_into().unwrap(), 1), height)
+                }
+            }
+        };
+
+        let map = |size: u32, content_size: u32, src_size: u32| -> Vec<Option<u32>> {
+            let ofs = (size - content_size) / 2;
+            (0..size)
+                .map(|i| {
+                    let i = i.checked_sub(ofs).filter(|&i| i < content_size)?;
+                    let src = u64::from(i) * u64::from(src_size) / u64::from(content_size);
+                    Some(src.try_into().unwrap())
+                })
+                .collect()
+        };
+        Self {
+            width,
+            height,
+            columns: map(width, content_width, src_width),
+            rows: map(height, content_height, src_height),
+            stride: src_width,
+        }
+    }
+
+    fn scale(&self, pixels: &[u8]) -> Vec<u8> {
+        self.scale_window(pixels, 0, 0)
+    }
+
+    /// Scales the part of the canvas whose top-left corner is at the given coordinates.
+    fn scale_window(&self, pixels: &[u8], x_ofs: u32, y_ofs: u32) -> Vec<u8> {
+        let mut scaled = Vec::with_capacity(usize::try_from(self.width * self.height).unwrap() * 3);
+        for row in &self.rows {
+            for column in &self.columns {
+                match (row, column) {
+                    (Some(y), Some(x)) => {
+                        let idx =
+                            usize::try_from(x + x_ofs + (y + y_ofs) * self.stride).unwrap() * 3;
+                        scaled.extend_from_slice(&pixels[idx..idx + 3]);
+                    }
+                    _ => {
+                        let (r, g, b) = Self::BACKGROUND_COLOR;
+                        scaled.extend_from_slice(&[r, g, b]);
+                    }
+                }
+            }
+        }
+        scaled
+    }
+}
+
+/// With `--follow`, shows only a window of the canvas as big as the output, which pans along with
+/// the section being attempted.
+struct Follow {
+    scaler: Scaler,
+    /// How big the window is, in the canvas' pixels.
+    window: (u32, u32),
+    bounds: (u32, u32),
+    /// Where the window is centered, which only moves part of the way to its target each frame,
+    /// staying within a quarter of the window from it.
+    center: Option<(f32, f32)>,
+}
+
+impl Follow {
+    /// How much of the way to the section being attempted the window moves each frame.
+    const EASING: f32 = 0.15;
+
+    fn new(frames: &Frames, width: u32, height: u32, mode: ScaleMode) -> Self {
+        let bounds = (frames.width(), frames.height());
+        let window = (cmp::min(width, bounds.0), cmp::min(height, bounds.1));
+        let mut scaler = Scaler::new(width, height, mode, window.0, window.1);
+        scaler.stride = bounds.0;
+        Self {
+            scaler,
+            window,
+            bounds,
+            center: None,
+        }
+    }
+
+    /// Crops the frame that was just yielded, then scales it to the output's resolution.
+    fn frame(&mut self, frames: &Frames, pixels: &[u8]) -> Vec<u8> {
+        if let Some(target) = frames.focus() {
+            // Lagging behind is fine, but not so far that the attempt gets out of view
+            let ease = |center: f32, target: f32, window: u32| {
+                let lag = window as f32 / 4.0;
+                (center + (target - center) * Self::EASING).clamp(target - lag, target + lag)
+            };
+            self.center = Some(match self.center {
+                Some((x, y)) => (
+                    ease(x, target.0, self.window.0),
+                    ease(y, target.1, self.window.1),
+                ),
+                None => target,
+            });
+        }
+        // Keep the window within the canvas
+        let ofs = |center: f32, window: u32, bound: u32| {
+            (center - window as f32 / 2.0)
+                .round()
+                .clamp(0.0, (bound - window) as f32) as u32
+        };
+        let (x, y) = self.center.map_or((0, 0), |(x, y)| {
+            (
+                ofs(x, self.window.0, self.bounds.0),
+                ofs(y, self.window.1, self.bounds.1),
+            )
+        });
+        self.scaler.scale_window(pixels, x, y)
+    }
+}
+
+/// The output's resolution, and how to get frames to it.
+fn output_size(frames: &Frames, options: &Options) -> (Option<Scaler>, u32, u32) {
+    scaled_size(frames.width(), frames.height(), options)
+}
+
+/// Like `output_size`, for frames of this size.
+fn scaled_size(src_width: u32, src_height: u32, options: &Options) -> (Option<Scaler>, u32, u32) {
+    let scaler = options.scale.map(|(width, height)| {
+        Scaler::new(width, height, options.scale_mode, src_width, src_height)
+    });
+    let (width, height) = scaler.as_ref().map_or((src_width, src_height), |scaler| {
+        (scaler.width, scaler.height)
+    });
+    (scaler, width, height)
+}
+
+/// Scales the frame if the output requires it, and passes it through unchanged otherwise.
+fn scaled<'p>(scaler: &Option<Scaler>, pixels: &'p [u8]) -> Cow<'p, [u8]> {
+    match scaler {
+        Some(scaler) => Cow::Owned(scaler.scale(pixels)),
+        None => Cow::Borrowed(pixels),
+    }
+}
+
+/// Checkpoints are saved next to `out_path`, which is only required with them.
+fn render_mp4<W: Write + Seek>(
+    sequence: &Sequence,
+    mut out: W,
+    out_path: Option<&Path>,
+    options: &Options,
+    warnings: &mut Vec<Warning>,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(), RenderError> {
+    // Catch this now, since the MP4 writer would only complain at the very end, and confusingly
+    if let Err(err) = out.stream_position() {
+        return Err(RenderError {
+            kind: RenderErrorKind::NotSeekable(err),
+            frame: None,
+        });
+    }
+    let out = BufWriter::new(out);
+    let mut frames = Frames::new(sequence, options);
+    let total = frames.total();
+    let (scaler, width, height) = output_size(&frames, options);
+    let clock = Clock::new(sequence, options);
+    let mut sink = Mp4Sink::new(out, width, height, clock, options)?;
+
+    let mut checkpoint = if options.checkpoint || options.resume {
+        let path = Checkpoint::path_for(out_path.expect("Checkpoints require an output file"));
+        Some(if options.resume {
+            Checkpoint::resume(&path, &mut frames, &mut sink)?
+        } else {
+            Checkpoint::create(&path)?
+        })
+    } else {
+        None
+    };
+    // The samples written back from the checkpoint count too, as the limit may have been lowered
+    let over_limit = |sink: &Mp4Sink<_>| {
+        options
+            .max_output_bytes
+            .is_some_and(|max| sink.nb_bytes() > max)
+    };
+    // The checkpoint is kept, so that rendering can be resumed with a higher limit
+    let too_large = || Err(RenderError::too_large(options.max_output_bytes.unwrap()));
+    if over_limit(&sink) {
+        return too_large();
+    }
+
+    let mut frame_sizes = match &options.frame_sizes {
+        Some(path) => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            writeln!(writer, "Frame\tBytes\tKeyframe")?;
+            Some(writer)
+        }
+        None => None,
+    };
+
+    let mut snapshots = Snapshots::new(sequence, options, width, height)?;
+    let mut follow = options
+        .follow
+        .then(|| Follow::new(&frames, width, height, options.scale_mode));
+    let mut nb_written = checkpoint.as_ref().map_or(0, Checkpoint::nb_frames);
+    while let Some((i, pixels)) = frames.next() {
+        progress(i, total);
+        let pixels = match &mut follow {
+            Some(follow) => Cow::Owned(follow.frame(&frames, &pixels)),
+            None => scaled(&scaler, &pixels),
+        };
+        snapshots.frame(nb_written, &pixels)?;
+
+        let bytes = sink.encode(&pixels)?;
+        if let Some(writer) = &mut frame_sizes {
+            writeln!(
+                writer,
+                "{}\t{}\t{}",
+                nb_written,
+                bytes.len(),
+                if sink.is_keyframe() { "yes" } else { "no" }
+            )?;
+        }
+        if let Some(checkpoint) = &mut checkpoint {
+            checkpoint.record_sample(nb_written, &bytes)?;
+        }
+        sink.write_sample(bytes)?;
+        if over_limit(&sink) {
+            return too_large();
+        }
+        if let Some(checkpoint) = &mut checkpoint {
+            checkpoint.frame_done(frames.pixels())?;
+        }
+        nb_written += 1;
+    }
+
+    if let (true, Some((nb_hits, nb_lookups))) = (options.verbose, sink.cache_hits()) {
+        eprintln!(
+            "Frame cache: {} hits, {} misses",
+            nb_hits,
+            nb_lookups - nb_hits
+        );
+    }
+    sink.finish()?;
+    if let Some(mut writer) = frame_sizes {
+        writer.flush()?;
+    }
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.remove()?;
+    }
+    snapshots.finish(&scaled(&scaler, frames.pixels()), warnings)?;
+    Ok(())
+}
+
+fn render_apng<W: Write>(
+    sequence: &Sequence,
+    out: W,
+    options: &Options,
+    warnings: &mut Vec<Warning>,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(), RenderError> {
+    let out = BufWriter::new(out);
+    render_animation(
+        sequence,
+        options,
+        warnings,
+        progress,
+        |width, height, nb_frames, clock| ApngSink::new(out, width, height, nb_frames, clock),
+    )
+}
+
+fn render_webp<W: Write>(
+    sequence: &Sequence,
+    out: W,
+    options: &Options,
+    warnings: &mut Vec<Warning>,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(), RenderError> {
+    let out = BufWriter::new(out);
+    render_animation(
+        sequence,
+        options,
+        warnings,
+        progress,
+        |width, height, _, clock| WebpSink::new(out, width, height, clock),
+    )
+}
+
+/// Renders the frames into a sink other than MP4's, which needs no checkpoints nor frame sizes;
+/// the sink is created from the frames' dimensions, how many there are, and their timing.
+fn render_animation<S: VideoSink>(
+    sequence: &Sequence,
+    options: &Options,
+    warnings: &mut Vec<Warning>,
+    progress: &mut dyn FnMut(usize, usize),
+    new_sink: impl FnOnce(u32, u32, usize, Clock) -> Result<S, RenderError>,
+) -> Result<(), RenderError> {
+    let nb_unsampled = Frames::nb_unsampled(sequence, options);
+    let mut frames = Frames::counted(sequence, options, nb_unsampled);
+    let total = frames.total();
+    let nb_frames = Frames::nb_sampled(nb_unsampled, options);
+    let (scaler, width, height) = output_size(&frames, options);
+    let clock = Clock::counted(options, nb_frames);
+    let mut sink = new_sink(width, height, nb_frames, clock)?;
+
+    if nb_frames == 0 {
+        let mut frames = Frames::new(sequence, options);
+        frames.fast_forward(usize::MAX);
+        sink.write_frame(&scaled(&scaler, frames.pixels()))?;
+    }
+    let mut snapshots = Snapshots::new(sequence, options, width, height)?;
+    let mut follow = options
+        .follow
+        .then(|| Follow::new(&frames, width, height, options.scale_mode));
+    let mut nb_written = 0;
+    while let Some((i, pixels)) = frames.next() {
+        progress(i, total);
+        let pixels = match &mut follow {
+            Some(follow) => Cow::Owned(follow.frame(&frames, &pixels)),
+            None => scaled(&scaler, &pixels),
+        };
+        snapshots.frame(nb_written, &pixels)?;
+        sink.write_frame(&pixels)?;
+        nb_written += 1;
+    }
+
+    sink.finish()?;
+    snapshots.finish(&scaled(&scaler, frames.pixels()), warnings)?;
+    Ok(())
+}
+
+/// Writes how everything ends up as an SVG image, laid out like the canvas but to scale, with each
+/// section being a rectangle titled with its details, which browsers show when hovering over it.
+fn render_svg<W: Write>(sequence: &Sequence, out: W, options: &Options) -> Result<(), RenderError> {
+    let frames = Frames::new(sequence, options);
+    let canvas = &frames.canvas;
+    let mut out = BufWriter::new(out);
+
+    let (width, height) = (canvas.width(), canvas.height());
+    let (out_width, out_height) = options.scale.unwrap_or((width, height));
+    let aspect = match options.scale_mode {
+        ScaleMode::Letterbox => "xMidYMid meet",
+        ScaleMode::Stretch => "none",
+    };
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}" preserveAspectRatio="{}">"#,
+        out_width, out_height, width, height, aspect
+    )?;
+    // Spacers are simply the background showing between banks
+    let (r, g, b) = Canvas::SPACER_COLOR;
+    writeln!(
+        out,
+        r##"<rect width="{}" height="{}" fill="#{:02x}{:02x}{:02x}"/>"##,
+        width, height, r, g, b
+    )?;
+
+    let bank_color = if canvas.show_free {
+        Canvas::FREE_COLOR
+    } else {
+        Canvas::EMPTY_COLOR
+    };
+    for (i, group) in canvas.groups.iter().enumerate() {
+        if canvas.has_band(i) {
+            let x = group.x - Canvas::REGION_SEPARATOR_WIDTH;
+            let (width, height) = (Canvas::REGION_SEPARATOR_WIDTH, Canvas::HEIGHT);
+            writeln!(
+                out,
+                "{}/>",
+                svg_rect(canvas, x, 0, width, height, Canvas::REGION_SEPARATOR_COLOR)
+            )?;
+            // Text reads along the band, sideways if it's vertical
+            let (x, y, rotation) = if canvas.horizontal {
+                (1, x + width - 1, 0)
+            } else {
+                (x + 1, 1, 90)
+            };
+            writeln!(
+                out,
+                r#"<text x="{}" y="{}" transform="rotate({} {} {})" font-family="monospace" font-size="{}" fill="white">{}</text>"#,
+                x,
+                y,
+                rotation,
+                x,
+                y,
+                width - 2,
+                escape_xml(&group.label())
+            )?;
+        }
+
+        for (slot, &bank) in group.banks.iter().enumerate() {
+            let bank_size = canvas.column_size(group.region, bank);
+            let (x, y) = canvas.bank_origin(group, slot as u32);
+            writeln!(
+                out,
+                "{}><title>{} bank {:x}</title></rect>",
+                svg_rect(
+                    canvas,
+                    x,
+                    y,
+                    canvas.bank_width,
+                    group.bank_height,
+                    bank_color
+                ),
+                group.region,
+                bank
+            )?;
+            if let Some(step) = options.grid {
+                for ofs in (step..bank_size).step_by(step.try_into().unwrap()) {
+                    let y =
+                        f64::from(y) + f64::from(ofs * group.bank_height) / f64::from(bank_size);
+                    writeln!(
+                        out,
+                        "{}/>",
+                        svg_rect(canvas, x, y, canvas.bank_width, 1, Canvas::GRID_COLOR)
+                    )?;
+                }
+            }
+        }
+    }
+
+    for (section, location) in sequence.sections.iter().zip(sequence.final_placements()) {
+        let location = match location {
+            Some(location) if frames.is_rendered(section) => location,
+            _ => continue,
+        };
+        let group = &canvas.groups[canvas.group_index(section)];
+        let name = escape_xml(&section.name);
+        for (bank, ofs, size) in canvas.section_spans(section, location) {
+            let slot = match group.slots.get(bank as usize) {
+                Some(&Some(slot)) => slot,
+                _ => continue,
+            };
+            let bank_size = canvas.column_size(group.region, bank);
+            let (x, y) = canvas.bank_origin(group, slot);
+            // Exactly to scale, as there are no pixels to round to
+            let row_height = f64::from(group.bank_height) / f64::from(bank_size);
+            let end = cmp::min(ofs.saturating_add(size), bank_size);
+            writeln!(
+                out,
+                "{}><title>{}\n{} {}, {} bytes</title></rect>",
+                svg_rect(
+                    canvas,
+                    x,
+                    f64::from(y) + f64::from(ofs) * row_height,
+                    canvas.bank_width,
+                    f64::from(end.saturating_sub(ofs)) * row_height,
+                    canvas.settled_color(section, location)
+                ),
+                name,
+                section.mem_type,
+                section.format_location(location, crate::AddrStyle::Absolute),
+                section.size
+            )?;
+        }
+    }
+
+    writeln!(out, "</svg>")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Keeps markup in names from being interpreted as such.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// The beginning of a `<rect>` element, positioned like on the canvas, so that attributes or
+/// children can still be added.
+fn svg_rect(
+    canvas: &Canvas,
+    x: impl Into<f64>,
+    y: impl Into<f64>,
+    width: impl Into<f64>,
+    height: impl Into<f64>,
+    color: Color,
+) -> String {
+    let (mut x, mut y, mut width, mut height) = (x.into(), y.into(), width.into(), height.into());
+    if canvas.horizontal {
+        std::mem::swap(&mut x, &mut y);
+        std::mem::swap(&mut width, &mut height);
+    }
+    format!(
+        r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#{:02x}{:02x}{:02x}""##,
+        x, y, width, height, color.0, color.1, color.2
+    )
+}
+
+/// Writes the last frame of the output, with the latest attempt overlaid, as a still PNG.
+pub fn write_last_frame(
+    sequence: &Sequence,
+    options: &Options,
+    path: &Path,
+) -> Result<(), RenderError> {
+    let (frames, pixels) = last_frame(sequence, options);
+    let (scaler, width, height) = output_size(&frames, options);
+    apng::write_png(path, width, height, &scaled(&scaler, &pixels))
+}
+
+/// Draws the last frame as `--format term` does, shrunk to fit in this many columns and lines,
+/// save for one to leave room for the cursor.
+pub fn draw_in_terminal<W: Write>(
+    sequence: &Sequence,
+    options: &Options,
+    (nb_columns, nb_lines): (u32, u32),
+    out: W,
+) -> Result<(), RenderError> {
+    let (frames, pixels) = last_frame(sequence, options);
+    draw_pixels_in_terminal(
+        frames.width(),
+        frames.height(),
+        &pixels,
+        (nb_columns, nb_lines),
+        out,
+    )
+}
+
+/// Draws this frame like `draw_in_terminal` does.
+fn draw_pixels_in_terminal<W: Write>(
+    src_width: u32,
+    src_height: u32,
+    pixels: &[u8],
+    (nb_columns, nb_lines): (u32, u32),
+    out: W,
+) -> Result<(), RenderError> {
+    // Each character is two pixels tall, which makes them about square
+    let (max_width, max_height) = (nb_columns, cmp::max(nb_lines.saturating_sub(1), 1) * 2);
+    let (width, height) = if u64::from(max_width) * u64::from(src_height)
+        <= u64::from(max_height) * u64::from(src_width)
+    {
+        let height = u64::from(src_height) * u64::from(max_width) / u64::from(src_width);
+        (max_width, cmp::max(height.try_into().unwrap(), 1))
+    } else {
+        let width = u64::from(src_width) * u64::from(max_height) / u64::from(src_height);
+        (cmp::max(width.try_into().unwrap(), 1), max_height)
+    };
+    let scaler = Scaler::new(width, height, ScaleMode::Stretch, src_width, src_height);
+
+    let mut out = BufWriter::new(out);
+    term::write_frame(&mut out, width, height, &scaler.scale(pixels))?;
+    out.flush()?;
+    Ok(())
+}
+
+/// The last frame of a sequence that's still growing, as `write_last_frame` renders it, but only
+/// drawing the frames added since the last update instead of going through all of them again.
+///
+/// What can only be drawn once the sequence is complete, like settle annotations or the largest
+/// gaps, is left out; `write_last_frame` draws it all then.
+pub struct LiveFrame<'a> {
+    options: &'a Options,
+    /// The canvas, laid out for the sequence as it was when it was created.
+    canvas: Option<Canvas>,
+    has_placements: bool,
+    /// How many of the sequence's frames have been drawn.
+    nb_drawn: usize,
+    /// The latest attempt, which is overlaid.
+    latest: Option<usize>,
+    /// Without explicit placements, the latest attempt whose section hasn't been settled yet, as
+    /// that's only done once the next frame moves on to another section.
+    unsettled: Option<usize>,
+}
+
+impl<'a> LiveFrame<'a> {
+    pub fn new(options: &'a Options) -> Self {
+        Self {
+            options,
+            canvas: None,
+            has_placements: false,
+            nb_drawn: 0,
+            latest: None,
+            unsettled: None,
+        }
+    }
+
+    /// Draws the frames added to the sequence since the last update.
+    pub fn update(&mut self, sequence: &Sequence) {
+        let options = self.options;
+        // New banks may move everything around, and placements showing up change what attempts
+        // mean, so start over then
+        let (bank_width, groups, width) = Canvas::arrange(sequence, options);
+        let outdated = self.canvas.as_ref().is_none_or(|canvas| {
+            (canvas.bank_width, &canvas.groups, canvas.width) != (bank_width, &groups, width)
+        });
+        if outdated || sequence.has_placements != self.has_placements {
+            *self = Self {
+                canvas: Some(Canvas::new(sequence, options)),
+                has_placements: sequence.has_placements,
+                ..Self::new(options)
+            };
+        }
+        let canvas = self.canvas.as_mut().unwrap();
+
+        for (i, frame) in sequence.frames.iter().enumerate().skip(self.nb_drawn) {
+            let section = &sequence.sections[frame.section_id];
+            if !options.regions.contains(&section.mem_type.region())
+                || !(options.mem_types.is_empty() || options.mem_types.contains(&section.mem_type))
+            {
+                continue;
+            }
+            // Like `Frames::advance`, which the rest of this mirrors
+            let settle = |canvas: &mut Canvas, i: usize| {
+                let frame = &sequence.frames[i];
+                canvas.settle(&sequence.sections[frame.section_id], &frame.location);
+            };
+            match frame.kind {
+                FrameKind::Attempt => {
+                    if options.no_settle {
+                        canvas.stamp(section, &frame.location);
+                    } else if let Some(prev) = self.unsettled.take() {
+                        if sequence.frames[prev].section_id != frame.section_id {
+                            settle(canvas, prev);
+                        }
+                    }
+                    if !self.has_placements {
+                        self.unsettled = Some(i);
+                    }
+                    self.latest = Some(i);
+                }
+                FrameKind::Placement if options.no_settle => {}
+                FrameKind::Placement => canvas.settle(section, &frame.location),
+                FrameKind::Removal if options.no_settle => {}
+                FrameKind::Removal => {
+                    if let Some(prev) = self.unsettled.take() {
+                        settle(canvas, prev);
+                    }
+                    canvas.unsettle(section, &frame.location);
+                }
+            }
+        }
+        self.nb_drawn = sequence.frames.len();
+    }
+
+    /// The canvas' size, before scaling; 0 by 0 before the first update.
+    fn size(&self) -> (u32, u32) {
+        self.canvas
+            .as_ref()
+            .map_or((0, 0), |canvas| (canvas.width(), canvas.height()))
+    }
+
+    fn pixels(&self, sequence: &Sequence) -> Vec<u8> {
+        let canvas = match &self.canvas {
+            Some(canvas) => canvas,
+            None => return Vec::new(),
+        };
+        match self.latest.map(|i| &sequence.frames[i]) {
+            Some(frame) => canvas.overlay(&sequence.sections[frame.section_id], &frame.location),
+            None => canvas.frame_pixels(),
+        }
+    }
+
+    /// Writes the frame as a still PNG, like `write_last_frame`.
+    pub fn write_png(&self, sequence: &Sequence, path: &Path) -> Result<(), RenderError> {
+        let (src_width, src_height) = self.size();
+        let (scaler, width, height) = scaled_size(src_width, src_height, self.options);
+        let pixels = self.pixels(sequence);
+        apng::write_png(path, width, height, &scaled(&scaler, &pixels))
+    }
+
+    /// Draws the frame like `draw_in_terminal`.
+    pub fn draw_in_terminal<W: Write>(
+        &self,
+        sequence: &Sequence,
+        size: (u32, u32),
+        out: W,
+    ) -> Result<(), RenderError> {
+        let (width, height) = self.size();
+        draw_pixels_in_terminal(width, height, &self.pixels(sequence), size, out)
+    }
+}
+
+/// A frame of the output (or its last one), scaled like the video, as a PNG to embed as the
+/// MP4's cover art; `None` if the output has no such frame.
+pub fn poster(
+    sequence: &Sequence,
+    options: &Options,
+    index: Option<usize>,
+) -> Result<Option<Vec<u8>>, RenderError> {
+    let nb_unsampled = Frames::nb_unsampled(sequence, options);
+    let nb_frames = Frames::nb_sampled(nb_unsampled, options);
+    let index = match index {
+        Some(index) if index >= nb_frames => return Ok(None),
+        Some(index) => index,
+        None => nb_frames.saturating_sub(1),
+    };
+    let mut frames = Frames::counted(sequence, options, nb_unsampled);
+    frames.fast_forward(index);
+    let pixels = match frames.next() {
+        Some((_, pixels)) => pixels,
+        // Without any frames, the video shows how everything ends up
+        None => frames.pixels().to_vec(),
+    };
+    let (scaler, width, height) = output_size(&frames, options);
+    let mut png = vec![];
+    apng::encode_png(&mut png, width, height, &scaled(&scaler, &pixels))?;
+    Ok(Some(png))
+}
+
+/// The last frame of the output, as it's rendered, before scaling it.
+fn last_frame<'a>(sequence: &'a Sequence, options: &Options) -> (Frames<'a>, Vec<u8>) {
+    let nb_unsampled = Frames::nb_unsampled(sequence, options);
+    let mut frames = Frames::counted(sequence, options, nb_unsampled);
+    frames.fast_forward(Frames::nb_sampled(nb_unsampled, options).saturating_sub(1));
+    let mut pixels = match frames.next() {
+        Some((_, pixels)) => pixels,
+        None => frames.pixels().to_vec(),
+    };
+    if options.annotate_settle_frame {
+        frames.annotate_settles(&mut pixels, &export::settled_frames(sequence, options));
+    }
+    (frames, pixels)
+}
+
+/// Renders and encodes all frames like `render`, but discards them instead of writing a file, and
+/// reports how fast that went.
+pub fn benchmark(sequence: &Sequence, options: &Options) -> Result<(), RenderError> {
+    let nb_unsampled = Frames::nb_unsampled(sequence, options);
+    let nb_remaining = Frames::nb_sampled(nb_unsampled, options);
+    let mut frames = Frames::counted(sequence, options, nb_unsampled);
+    let total = frames.total();
+    let (scaler, width, height) = output_size(&frames, options);
+
+    let start = Instant::now();
+    let mut nb_frames = 0;
+    let mut drain = |sink: &mut dyn VideoSink| -> Result<(), RenderError> {
+        for (i, pixels) in &mut frames {
+            eprint!("Benchmarking... {} / {}\r", i, total);
+            sink.write_frame(&scaled(&scaler, &pixels))?;
+            nb_frames += 1;
+        }
+        Ok(())
+    };
+    match options.format {
+        Format::Mp4 => {
+            let mut sink = NullSink::new(width, height, options)?;
+            drain(&mut sink)?;
+            sink.finish()?;
+        }
+        Format::Apng => {
+            let clock = Clock::counted(options, nb_remaining);
+            let mut sink = ApngSink::new(io::sink(), width, height, nb_remaining, clock)?;
+            if nb_remaining == 0 {
+                let mut frames = Frames::counted(sequence, options, nb_unsampled);
+                frames.fast_forward(usize::MAX);
+                sink.write_frame(&scaled(&scaler, frames.pixels()))?;
+            }
+            drain(&mut sink)?;
+            sink.finish()?;
+        }
+        Format::Webp => {
+            let clock = Clock::counted(options, nb_remaining);
+            let mut sink = WebpSink::new(io::sink(), width, height, clock)?;
+            if nb_remaining == 0 {
+                let mut frames = Frames::counted(sequence, options, nb_unsampled);
+                frames.fast_forward(usize::MAX);
+                sink.write_frame(&scaled(&scaler, frames.pixels()))?;
+            }
+            drain(&mut sink)?;
+            sink.finish()?;
+        }
+        Format::Svg | Format::Term => unreachable!("Stills are always written at once"),
+    }
+    let elapsed = start.elapsed();
+    eprintln!("Benchmarking... - Done.      ");
+
+    println!(
+        "Rendered {} frames in {:.3} s ({:.1} frames/s)",
+        nb_frames,
+        elapsed.as_secs_f64(),
+        nb_frames as f64 / elapsed.as_secs_f64()
+    );
+    // Only Linux makes this easy to find out
+    if let Some(peak) = fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| Some(line.strip_prefix("VmHWM:")?.trim().to_string()))
+        })
+    {
+        println!("Peak memory: {}", peak);
+    }
+    Ok(())
+}
+
+/// Writes a single image showing evenly-spaced frames of the output side by side, each labelled
+/// with its index, for a quick overview.
+pub fn contact_sheet(
+    sequence: &Sequence,
+    options: &Options,
+    path: &Path,
+) -> Result<(), RenderError> {
+    const NB_COLUMNS: u32 = 4;
+    const GAP: u32 = 4;
+    const LABEL_HEIGHT: u32 = font::GLYPH_HEIGHT + 2;
+    // Thumbnails are shrunk by this factor
+    const SHRINK: u32 = 4;
+
+    eprint!("Writing contact sheet...\r");
+
+    let nb_unsampled = Frames::nb_unsampled(sequence, options);
+    let nb_frames = Frames::nb_sampled(nb_unsampled, options);
+    let nb_thumbs = cmp::max(
+        cmp::min(
+            nb_frames,
+            usize::try_from(options.contact_sheet_frames).unwrap(),
+        ),
+        1,
+    );
+    // Always include both the first and last frames
+    let picks: Vec<usize> = (0..nb_thumbs)
+        .map(|i| match nb_thumbs {
+            1 => nb_frames.saturating_sub(1),
+            _ => i * (nb_frames - 1) / (nb_thumbs - 1),
+        })
+        .collect();
+
+    let mut frames = Frames::counted(sequence, options, nb_unsampled);
+    let (src_width, src_height) = (frames.width(), frames.height());
+    let (thumb_width, thumb_height) = (
+        cmp::max(src_width / SHRINK, 1),
+        cmp::max(src_height / SHRINK, 1),
+    );
+    let scaler = Scaler::new(
+        thumb_width,
+        thumb_height,
+        ScaleMode::Stretch,
+        src_width,
+        src_height,
+    );
+
+    let nb_columns = cmp::min(NB_COLUMNS, nb_thumbs as u32);
+    let nb_rows = (nb_thumbs as u32).div_ceil(nb_columns);
+    let (cell_width, cell_height) = (thumb_width + GAP, thumb_height + LABEL_HEIGHT + GAP);
+    let width = GAP + nb_columns * cell_width;
+    let height = GAP + nb_rows * cell_height;
+    let mut pixels = vec![0; usize::try_from(width * height).unwrap() * 3];
+
+    let mut next_index = 0;
+    for (i, &index) in picks.iter().enumerate() {
+        frames.fast_forward(index - next_index);
+        let frame = match frames.next() {
+            Some((_, frame)) => frame,
+            // Without any frames, show how everything ends up
+            None => frames.pixels().to_vec(),
+        };
+        next_index = index + 1;
+
+        let thumb = scaler.scale(&frame);
+        let (x, y) = (
+            GAP + i as u32 % nb_columns * cell_width,
+            GAP + i as u32 / nb_columns * cell_height,
+        );
+        for (row, line) in thumb
+            .chunks_exact(usize::try_from(thumb_width).unwrap() * 3)
+            .enumerate()
+        {
+            let start = usize::try_from(x + (y + row as u32) * width).unwrap() * 3;
+            pixels[start..start + line.len()].copy_from_slice(line);
+        }
+        // Cut the label off rather than letting it run into the next thumbnail
+        let label: String = format!("#{}", index)
+            .chars()
+            .take(usize::try_from((thumb_width + 1) / (font::GLYPH_WIDTH + 1)).unwrap())
+            .collect();
+        Canvas::draw_text(
+            &mut pixels,
+            Raster {
+                width,
+                transposed: false,
+            },
+            x,
+            y + thumb_height + 1,
+            &label,
+            1,
+            Canvas::LABEL_COLOR,
+        );
+    }
+
+    apng::write_png(path, width, height, &pixels)?;
+    eprintln!("Writing contact sheet - Done.");
+    Ok(())
+}
+
+/// Writes a single image summing up what packing changed between the first frame of the output
+/// and the last one: space only occupied by then is green, space occupied on both is faintly so,
+/// and space that got freed (by sections being removed) is orange.
+pub fn net_diff(sequence: &Sequence, options: &Options, path: &Path) -> Result<(), RenderError> {
+    const KEPT_ALPHA: f32 = 0.25;
+    const FREED_COLOR: Color = (255, 128, 0);
+
+    eprint!("Writing net diff...\r");
+
+    let mut frames = Frames::new(sequence, options);
+    let (scaler, width, height) = output_size(&frames, options);
+    // Space is occupied wherever the canvas isn't as it started out
+    let occupied = |canvas: &Canvas| -> Vec<bool> {
+        canvas
+            .pixels
+            .chunks_exact(3)
+            .zip(canvas.background.chunks_exact(3))
+            .map(|(pixel, background)| pixel != background)
+            .collect()
+    };
+    frames.next();
+    let at_start = occupied(&frames.canvas);
+    frames.fast_forward(usize::MAX);
+    let at_end = occupied(&frames.canvas);
+
+    // Only removals free space up
+    let canvas = &frames.canvas;
+    let mut freed = at_start.clone();
+    for frame in &sequence.frames {
+        let section = &sequence.sections[frame.section_id];
+        if frame.kind != FrameKind::Removal || !frames.is_rendered(section) {
+            continue;
+        }
+        for rect in canvas.section_rects(section, &frame.location) {
+            for y in rect.y..rect.y + rect.height {
+                for x in rect.x..rect.x + rect.width {
+                    freed[canvas.raster().index(x, y) / 3] = true;
+                }
+            }
+        }
+    }
+
+    let mut pixels = canvas.background.clone();
+    for (i, pixel) in pixels.chunks_exact_mut(3).enumerate() {
+        match (at_start[i], at_end[i], freed[i]) {
+            (false, true, _) => Canvas::blend_pixel(pixel, Canvas::FILLED_COLOR, 1.0),
+            (true, true, _) => Canvas::blend_pixel(pixel, Canvas::FILLED_COLOR, KEPT_ALPHA),
+            (_, false, true) => Canvas::blend_pixel(pixel, FREED_COLOR, 1.0),
+            (_, false, false) => {}
+        }
+    }
+
+    apng::write_png(path, width, height, &scaled(&scaler, &pixels))?;
+    eprintln!("Writing net diff - Done.");
+    Ok(())
+}
+
+/// Writes a PNG explaining what each color means with the given options, one swatch per line,
+/// followed by the colors that sections get based on their name, if any.
+pub fn legend(sequence: &Sequence, options: &Options, path: &Path) -> Result<(), RenderError> {
+    const MARGIN: u32 = 4;
+    const SCALE: u32 = 2;
+    const SWATCH_SIZE: u32 = font::GLYPH_HEIGHT * SCALE;
+    const LINE_HEIGHT: u32 = SWATCH_SIZE + MARGIN;
+
+    let frames = Frames::new(sequence, options);
+    let canvas = &frames.canvas;
+    let mut entries = Vec::new();
+    if canvas.show_free {
+        entries.push((Canvas::FREE_COLOR, "Free".to_string()));
+        entries.push((Canvas::EMPTY_COLOR, "Used".to_string()));
+    } else {
+        entries.push((Canvas::EMPTY_COLOR, "Empty".to_string()));
+        entries.push((Canvas::FILLED_COLOR, "Used".to_string()));
+    }
+    entries.push((Canvas::OVERLAY_COLOR, "Attempt".to_string()));
+    if !canvas.highlight.is_empty() || !canvas.watch_ranges.is_empty() {
+        entries.push((Canvas::HIGHLIGHT_COLOR, "Highlighted".to_string()));
+    }
+    if options.flash_frames != 0 {
+        entries.push((Canvas::FLASH_COLOR, "Just settled".to_string()));
+    }
+    if canvas.mark_full {
+        entries.push((Canvas::FULL_MARK_COLOR, "Full bank".to_string()));
+    }
+    if options.show_largest_gap {
+        entries.push((Canvas::LARGEST_GAP_COLOR, "Largest gap".to_string()));
+    }
+    // Free space hides sections' colors, so there are none to tell apart then
+    if !canvas.show_free {
+        let mut names = HashSet::new();
+        for section in &sequence.sections {
+            let color = canvas.section_color(section);
+            if color != Canvas::FILLED_COLOR
+                && frames.is_rendered(section)
+                && names.insert(&section.name)
+            {
+                entries.push((color, section.name.clone()));
+            }
+        }
+    }
+
+    let advance = (font::GLYPH_WIDTH + 1) * SCALE;
+    let text_x = MARGIN + SWATCH_SIZE + MARGIN;
+    let max_chars = entries
+        .iter()
+        .map(|(_, text)| text.chars().count() as u32)
+        .max()
+        .unwrap_or(0);
+    let width = text_x + max_chars * advance - SCALE + MARGIN;
+    let height = MARGIN + entries.len() as u32 * LINE_HEIGHT;
+    let raster = Raster {
+        width,
+        transposed: false,
+    };
+    let mut pixels = vec![0; usize::try_from(width * height).unwrap() * 3];
+    for (i, (color, text)) in entries.iter().enumerate() {
+        let y = MARGIN + i as u32 * LINE_HEIGHT;
+        Canvas::fill_rect(
+            &mut pixels,
+            raster,
+            &Rect {
+                x: MARGIN,
+                y,
+                width: SWATCH_SIZE,
+                height: SWATCH_SIZE,
+            },
+            *color,
+        );
+        Canvas::draw_text(
+            &mut pixels,
+            raster,
+            text_x,
+            y,
+            text,
+            SCALE,
+            Canvas::LABEL_COLOR,
+        );
+    }
+
+    apng::write_png(path, width, height, &pixels)?;
+    Ok(())
+}
+
+/// Writes the final canvas as a PNG, with banks as wide as they may be instead of fitting them
+/// within the usual width, and without any scaling; the image may then get huge.
+pub fn megastill(sequence: &Sequence, options: &Options, path: &Path) -> Result<(), RenderError> {
+    let options = Options {
+        uncapped: true,
+        ..options.clone()
+    };
+    // The canvas is allocated before being drawn into, then copied as its background
+    let (_, _, width) = Canvas::arrange(sequence, &options);
+    let size = u64::from(width) * u64::from(Canvas::HEIGHT) * 3;
+    eprintln!(
+        "Note: the megastill is {} pixels long, which takes about {} MiB of memory",
+        width,
+        (size * 2) >> 20
+    );
+
+    let mut frames = Frames::new(sequence, &options);
+    frames.fast_forward(usize::MAX);
+    let mut pixels = frames.pixels().to_vec();
+    if options.annotate_settle_frame {
+        frames.annotate_settles(&mut pixels, &export::settled_frames(sequence, &options));
+    }
+    apng::write_png(path, frames.width(), frames.height(), &pixels)?;
+    Ok(())
+}
+
+/// Writes the last frame's pixels as-is, for inspecting them without any encoder in the way: the
+/// width then the height, as little-endian 32-bit integers, then the pixels, row by row from the
+/// top, each as 3 bytes (red, green, blue), without any padding.
+pub fn dump_rgb(sequence: &Sequence, options: &Options, path: &Path) -> Result<(), RenderError> {
+    let (frames, pixels) = last_frame(sequence, options);
+    // A transposed canvas is laid out column by column, which are the output's rows
+    let (width, height) = (frames.width(), frames.height());
+    debug_assert_eq!(pixels.len(), (width * height * 3) as usize);
+
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(&width.to_le_bytes())?;
+    out.write_all(&height.to_le_bytes())?;
+    out.write_all(&pixels)?;
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn sequence(log: &str) -> Sequence {
+        parse::parse_input(log.as_bytes(), &Default::default(), &mut Vec::new()).unwrap()
+    }
+
+    /// Where the log's first section is drawn, at its last location; ROMX banks are 512 pixels
+    /// tall when alone, so each row is 32 bytes.
+    fn areas(log: &str, options: &Options) -> Vec<(Rect, Coverage)> {
+        let sequence = sequence(log);
+        let canvas = Canvas::new(&sequence, options);
+        let location = &sequence.frames.last().unwrap().location;
+        canvas.section_areas(&sequence.sections[0], location)
+    }
+
+    fn antialiased() -> Options {
+        Options {
+            antialias: true,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn antialias_covers_part_of_the_top_row() {
+        let areas = areas("[ROMX @ 01:ffff & 0 + 0] 48 A\n=01:4010\n", &antialiased());
+        let (rect, coverage) = &areas[0];
+        assert_eq!((rect.y, rect.height), (0, 2));
+        assert_eq!(
+            *coverage,
+            Coverage {
+                top: 0.5,
+                bottom: 1.0
+            }
+        );
+    }
+
+    #[test]
+    fn antialias_covers_part_of_both_edge_rows() {
+        let areas = areas("[ROMX @ 01:ffff & 0 + 0] 40 A\n=01:4008\n", &antialiased());
+        let (rect, coverage) = &areas[0];
+        assert_eq!((rect.y, rect.height), (0, 2));
+        assert_eq!(
+            *coverage,
+            Coverage {
+                top: 0.75,
+                bottom: 0.5
+            }
+        );
+        assert_eq!(coverage.of_row(rect, 0), 0.75);
+        assert_eq!(coverage.of_row(rect, 1), 0.5);
+    }
+
+    #[test]
+    fn antialias_counts_a_single_row_once() {
+        let areas = areas("[ROMX @ 01:ffff & 0 + 0] 8 A\n=01:4008\n", &antialiased());
+        let (rect, coverage) = &areas[0];
+        assert_eq!(rect.height, 1);
+        assert_eq!(coverage.of_row(rect, rect.y), 0.25);
+    }
+
+    #[test]
+    fn aligned_sections_cover_whole_rows() {
+        let areas = areas("[ROMX @ 01:ffff & 0 + 0] 64 A\n=01:4020\n", &antialiased());
+        let (rect, coverage) = &areas[0];
+        assert_eq!((rect.y, rect.height), (1, 2));
+        assert_eq!(*coverage, Coverage::FULL);
+    }
+
+    #[test]
+    fn without_antialias_rows_are_whole() {
+        let areas = areas(
+            "[ROMX @ 01:ffff & 0 + 0] 40 A\n=01:4008\n",
+            &Options::default(),
+        );
+        assert_eq!(areas[0].1, Coverage::FULL);
+    }
+
+    fn canvas_bank_width(log: &str) -> u32 {
+        Canvas::new(&sequence(log), &Options::default()).bank_width
+    }
+
+    /// How many pixels the full marks cover, once all of the log's sections are settled.
+    fn nb_marked_pixels(log: &str) -> usize {
+        let sequence = sequence(log);
+        let options = Options {
+            mark_full: true,
+            ..Options::default()
+        };
+        let mut canvas = Canvas::new(&sequence, &options);
+        for frame in &sequence.frames {
+            canvas.settle(&sequence.sections[frame.section_id], &frame.location);
+        }
+        let pixels = canvas.frame_pixels();
+        pixels
+            .chunks_exact(3)
+            .zip(canvas.pixels.chunks_exact(3))
+            .filter(|(marked, pixel)| marked != pixel)
+            .count()
+    }
+
+    #[test]
+    fn mark_full_marks_a_bank_filled_exactly() {
+        let log = "[ROMX @ 01:ffff & 0 + 0] 8192 A\n=01:4000\n\
+                   [ROMX @ 01:ffff & 0 + 0] 8192 B\n=01:6000\n";
+        assert_eq!(
+            nb_marked_pixels(log),
+            (canvas_bank_width(log) * Canvas::FULL_MARK_HEIGHT) as usize
+        );
+    }
+
+    #[test]
+    fn mark_full_ignores_a_bank_one_byte_short() {
+        let log = "[ROMX @ 01:ffff & 0 + 0] 8192 A\n=01:4000\n\
+                   [ROMX @ 01:ffff & 0 + 0] 8191 B\n=01:6000\n";
+        assert_eq!(nb_marked_pixels(log), 0);
+    }
+
+    #[test]
+    fn group_by_merges_the_smallest_groups() {
+        // More prefixes than panels fit, growing in size
+        let mut log = String::new();
+        for i in 0..200 {
+            log.push_str(&format!(
+                "[ROMX @ 01:ffff & 0 + 0] {} P{:03}_A\n",
+                16 + i,
+                i
+            ));
+            log.push_str(&format!("={:02x}:4000\n", 1 + i % 64));
+        }
+        let sequence = sequence(&log);
+        let options = Options {
+            group_by: Some(GroupBy::Prefix),
+            ..Options::default()
+        };
+        let mut canvas = Canvas::new(&sequence, &options);
+        let panels: Vec<_> = canvas
+            .groups
+            .iter()
+            .map(|group| group.panel.as_deref().unwrap())
+            .collect();
+        assert_eq!(panels.len(), GroupBy::MAX_PANELS);
+        assert_eq!(panels[0], "P177");
+        assert_eq!(panels[GroupBy::MAX_PANELS - 2], "P199");
+        assert_eq!(panels[GroupBy::MAX_PANELS - 1], GroupBy::OTHER_PANEL);
+        assert_ne!(canvas.bank_width, 0);
+        for frame in &sequence.frames {
+            canvas.settle(&sequence.sections[frame.section_id], &frame.location);
+        }
+    }
+
+    #[test]
+    fn filter_type_leaves_rom0_out() {
+        let romx = "[ROMX @ 01:ffff & 0 + 0] 256 B\n01:4000\n=01:4000\n";
+        let both = format!("[ROM0 @ 00:ffff & 0 + 0] 256 A\n=00:0000\n{}", romx);
+        let options = Options {
+            mem_types: vec![MemType::Romx],
+            ..Options::default()
+        };
+        let (sequence, romx_only) = (sequence(&both), sequence(romx));
+        assert!(!Frames::new(&sequence, &options).is_rendered(&sequence.sections[0]));
+        assert!(Frames::new(&sequence, &options).is_rendered(&sequence.sections[1]));
+        // As if the ROM0 section wasn't there at all
+        assert_eq!(
+            Frames::new(&sequence, &options).nb_remaining(),
+            Frames::new(&romx_only, &options).nb_remaining()
+        );
+        assert!(last_frame(&sequence, &options).1 == last_frame(&romx_only, &options).1);
+        assert!(last_frame(&sequence, &options).1 != last_frame(&sequence, &Options::default()).1);
+    }
+
+    #[test]
+    fn sampling_ends_on_the_last_frame() {
+        let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n01:4010\n01:4020\n01:4030\n\
+                   [ROMX @ 01:ffff & 0 + 0] 16 B\n01:4040\n01:4050\n=01:4060\n";
+        let sequence = sequence(log);
+        // Flashes come after the last attempt
+        let flashing = Options {
+            grow_frames: 2,
+            flash_frames: 3,
+            ..Options::default()
+        };
+        for unsampled in [Options::default(), flashing] {
+            let nb_frames = Frames::new(&sequence, &unsampled).nb_remaining() as u32;
+            let last = last_frame(&sequence, &unsampled).1;
+            for sample_every in 1..=nb_frames + 1 {
+                let options = Options {
+                    sample_every,
+                    ..unsampled.clone()
+                };
+                assert_eq!(
+                    Frames::new(&sequence, &options).nb_remaining(),
+                    nb_frames.div_ceil(sample_every) as usize
+                );
+                assert!(
+                    last_frame(&sequence, &options).1 == last,
+                    "Sampling every {} of {} frames",
+                    sample_every,
+                    nb_frames
+                );
+            }
+        }
+    }
+
+    /// How many frames the log makes, with and without `--coalesce-identical`.
+    fn nb_coalesced(log: &str, options: Options) -> (usize, usize) {
+        let sequence = sequence(log);
+        let coalesced = Options {
+            coalesce_identical: true,
+            ..options.clone()
+        };
+        let nb_frames = Frames::new(&sequence, &coalesced).nb_remaining();
+        assert!(last_frame(&sequence, &coalesced).1 == last_frame(&sequence, &options).1);
+        (nb_frames, Frames::new(&sequence, &options).nb_remaining())
+    }
+
+    #[test]
+    fn coalesce_identical_drops_attempts_drawn_alike() {
+        // A byte apart is well within a row
+        let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n01:4001\n01:4002\n01:4100\n01:4101\n";
+        assert_eq!(nb_coalesced(log, Options::default()), (2, 5));
+        // Another section's attempts aren't the same, even if drawn at the same place
+        let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n01:4001\n\
+                   [ROMX @ 01:ffff & 0 + 0] 16 B\n01:4000\n01:4001\n";
+        assert_eq!(nb_coalesced(log, Options::default()), (2, 4));
+    }
+
+    #[test]
+    fn coalesce_identical_keeps_attempts_around_changes() {
+        // The section is settled then removed in between, so the canvas changed twice
+        let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n=01:4000\n-01:4000\n01:4001\n=01:4001\n";
+        assert_eq!(nb_coalesced(log, Options::default()), (2, 2));
+        // Frames repeated to pace by bytes are kept too
+        let pace = Options {
+            pace: Pace::Bytes(4),
+            ..Options::default()
+        };
+        let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n=01:4000\n\
+                   [ROMX @ 01:ffff & 0 + 0] 16 B\n01:4100\n=01:4100\n";
+        assert_eq!(nb_coalesced(log, pace), (4, 4));
+    }
+
+    /// Feeds the log to a `LiveFrame` a line at a time, and checks that it ends up drawing the
+    /// same last frame as rendering the whole log does.
+    fn check_live_frame(log: &str) {
+        let options = Options::default();
+        let mut live = LiveFrame::new(&options);
+        let mut lines = String::new();
+        for line in log.lines() {
+            lines.push_str(line);
+            lines.push('\n');
+            live.update(&sequence(&lines));
+        }
+        let sequence = sequence(log);
+        let (frames, pixels) = last_frame(&sequence, &options);
+        assert_eq!(live.size(), (frames.width(), frames.height()));
+        assert!(live.pixels(&sequence) == pixels);
+    }
+
+    #[test]
+    fn live_frame_settles_attempts() {
+        check_live_frame(
+            "[ROM0 @ 00:ffff & 0 + 0] 256 A\n00:0000\n00:0100\n\
+             [ROMX @ 01:ffff & 0 + 0] 4096 B\n01:4000\n\
+             [ROMX @ ff:ffff & 0 + 0] 512 C\n02:4000\n03:4000\n",
+        );
+    }
+
+    #[test]
+    fn live_frame_follows_placements_and_removals() {
+        check_live_frame(
+            "[ROMX @ 01:ffff & 0 + 0] 256 A\n01:4000\n=01:4000\n\
+             [ROMX @ 01:ffff & 0 + 0] 512 B\n01:4000\n-01:4000\n=01:4000\n\
+             [ROMX @ 01:ffff & 0 + 0] 128 C\n01:4000\n01:4200\n",
+        );
+    }
+
+    /// Where the log's only section spans, at its last location.
+    #[test]
+    fn gridlines_fall_on_multiples_of_the_step() {
+        let sequence = sequence("[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n");
+        let options = Options {
+            grid: Some(0x1000),
+            ..Options::default()
+        };
+        let canvas = Canvas::new(&sequence, &options);
+        let group = &canvas.groups[0];
+        let (x, y) = canvas.bank_origin(group, group.slots[1].unwrap());
+        let is_gridline = |row: u32| {
+            let ofs = ((y + row) * canvas.width() + x) as usize * 3;
+            canvas.pixels[ofs..ofs + 3] == <[u8; 3]>::from(Canvas::GRID_COLOR)
+        };
+        // $1000, $2000, and $3000 are a quarter of the way down the bank each
+        let gridlines: Vec<_> = (0..group.bank_height)
+            .filter(|&row| is_gridline(row))
+            .collect();
+        let quarter = group.bank_height / 4;
+        assert_eq!(gridlines, [quarter, quarter * 2, quarter * 3]);
+    }
+
+    #[test]
+    fn tiny_sections_cover_min_section_px_rows() {
+        for min_section_px in [1, 3, 8] {
+            let options = Options {
+                min_section_px,
+                ..Options::default()
+            };
+            // 4 bytes are far less than a row at the default scale
+            let areas = areas("[ROMX @ 01:ffff & 0 + 0] 4 A\n=01:5000\n", &options);
+            assert_eq!(areas[0].0.height, min_section_px);
+            // Even against the end of the bank, without spilling out of it
+            let sequence = sequence("[ROMX @ 01:ffff & 0 + 0] 4 A\n=01:7ffc\n");
+            let canvas = Canvas::new(&sequence, &options);
+            let bank_height = canvas.groups[0].bank_height;
+            let location = &sequence.frames[0].location;
+            let (rect, _) = &canvas.section_areas(&sequence.sections[0], location)[0];
+            assert_eq!(
+                (rect.y, rect.height),
+                (bank_height - min_section_px, min_section_px)
+            );
+        }
+    }
+
+    #[test]
+    fn sizes_past_the_bank_are_capped() {
+        for options in [Options::default(), antialiased()] {
+            assert_eq!(
+                areas("[ROMX @ 01:ffff & 0 + 0] 65536 A\n01:4000\n", &options),
+                areas("[ROMX @ 01:ffff & 0 + 0] 16384 A\n01:4000\n", &options)
+            );
+            // The end of the section is past 32 bits, too
+            assert_eq!(
+                areas("[ROMX @ 01:ffff & 0 + 0] 4294967295 A\n01:7fff\n", &options),
+                areas("[ROMX @ 01:ffff & 0 + 0] 1 A\n01:7fff\n", &options)
+            );
+        }
+    }
+
+    #[test]
+    fn largest_gap_follows_the_tall_rom0_column() {
+        // The gap is $6000-$7FFF, the bottom quarter of the column
+        let sequence = sequence("[ROM0 @ 00:ffff & 0 + 0] 24576 A\n=00:0000\n");
+        let options = Options {
+            rom0_half: Rom0Half::Tall,
+            ..Options::default()
+        };
+        let canvas = Canvas::new(&sequence, &options);
+        let group = &canvas.groups[0];
+        let (x, y) = canvas.bank_origin(group, group.slots[0].unwrap());
+        // Bank 1, being empty, is a gap of its own
+        assert_eq!(
+            canvas.largest_gap_rects(&sequence)[0],
+            Rect {
+                x,
+                y: y + group.bank_height * 3 / 4,
+                width: canvas.bank_width,
+                height: group.bank_height / 4,
+            }
+        );
+    }
+
+    #[test]
+    fn largest_gap_is_drawn_over_the_last_frame() {
+        let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n01:4010\n01:4020\n01:4030\n01:4040\n";
+        let sequence = sequence(log);
+        for sample_every in [1, 2] {
+            let options = Options {
+                show_largest_gap: true,
+                sample_every,
+                ..Options::default()
+            };
+            let nb_gap_pixels: Vec<_> = Frames::new(&sequence, &options)
+                .map(|(_, pixels)| {
+                    pixels
+                        .chunks_exact(3)
+                        .filter(|pixel| *pixel == <[u8; 3]>::from(Canvas::LARGEST_GAP_COLOR))
+                        .count()
+                })
+                .collect();
+            let (last, others) = nb_gap_pixels.split_last().unwrap();
+            assert_ne!(*last, 0);
+            assert!(others.iter().all(|&nb| nb == 0), "{:?}", nb_gap_pixels);
+        }
+    }
+
+    fn spans(log: &str, rom0_half: Rom0Half) -> Vec<(u32, u32, u32)> {
+        let sequence = sequence(log);
+        let location = &sequence.frames.last().unwrap().location;
+        rom0_half.section_spans(&sequence.sections[0], location)
+    }
+
+    #[test]
+    fn spans_are_within_the_bank() {
+        let rom0 = "[ROM0 @ 00:ffff & 0 + 0] 16 A\n00:0150\n";
+        let romx = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4150\n";
+        let vram = "[VRAM @ 01:ffff & 0 + 0] 16 A\n01:9800\n";
+        for rom0_half in [
+            Rom0Half::Fold,
+            Rom0Half::Romx,
+            Rom0Half::Clamp,
+            Rom0Half::Tall,
+        ] {
+            assert_eq!(spans(rom0, rom0_half), [(0, 0x150, 16)]);
+            assert_eq!(spans(romx, rom0_half), [(1, 0x150, 16)]);
+            assert_eq!(spans(vram, rom0_half), [(1, 0x1800, 16)]);
+        }
+    }
+
+    #[test]
+    fn rom0_upper_half_spans() {
+        let straddling = "[ROM0 @ 00:ffff & 0 + 0] 32 A\n00:3ff0\n";
+        let upper = "[ROM0 @ 00:ffff & 0 + 0] 16 A\n00:4150\n";
+        assert_eq!(spans(straddling, Rom0Half::Fold), [(0, 0x3ff0, 32)]);
+        assert_eq!(spans(upper, Rom0Half::Fold), [(0, 0x150, 16)]);
+        assert_eq!(
+            spans(straddling, Rom0Half::Romx),
+            [(0, 0x3ff0, 16), (1, 0, 16)]
+        );
+        assert_eq!(spans(upper, Rom0Half::Romx), [(1, 0x150, 16)]);
+        assert_eq!(spans(upper, Rom0Half::Clamp), [(0, 0x3fff, 16)]);
+        assert_eq!(spans(upper, Rom0Half::Tall), [(0, 0x4150, 16)]);
+        assert_eq!(Rom0Half::Tall.column_size(Region::Rom, 0), 0x8000);
+        assert_eq!(Rom0Half::Tall.column_size(Region::Rom, 1), 0x4000);
+        assert_eq!(Rom0Half::Fold.column_size(Region::Rom, 0), 0x4000);
+    }
+
+    #[test]
+    fn fitting_spreads_frames_evenly() {
+        let clock = Clock::fitting(2.0, 120);
+        assert_eq!(clock.start(120), 2 * u64::from(clock.timescale));
+        for frame in 0..120 {
+            assert_eq!(clock.start(frame + 1) - clock.start(frame), 1500);
+        }
+    }
+
+    #[test]
+    fn fitting_frames_last_at_least_a_tick() {
+        // 0.1 ms for a million frames would be a tenth of a tick each
+        let clock = Clock::fitting(0.000_1, 1_000_000);
+        for frame in [0, 1, 999_999] {
+            assert_eq!(clock.start(frame + 1) - clock.start(frame), 1);
+            assert_eq!(clock.delay(frame), (1, 1000));
+            assert_eq!(clock.delay_ms(frame), 1);
+        }
+    }
 }
@@ -0,0 +1,209 @@
+//! Statistics about how well the sections ended up packed.
+
+use crate::render::Rom0Half;
+use crate::{AddrStyle, Region, Sequence};
+use std::cmp;
+
+/// How a bank is used once all sections have been placed.
+#[derive(Debug)]
+pub struct BankStats {
+    pub region: Region,
+    pub bank: u32,
+    pub size: u32,
+    pub used: u32,
+    /// The offset within the bank and size of each free block, in address order.
+    pub gaps: Vec<(u32, u32)>,
+}
+
+impl BankStats {
+    pub fn free(&self) -> u32 {
+        self.size - self.used
+    }
+
+    pub fn largest_gap(&self) -> u32 {
+        self.largest_gap_block().map_or(0, |(_, size)| size)
+    }
+
+    /// The largest free block, as `(offset, size)`; the first one, if several are as large.
+    pub fn largest_gap_block(&self) -> Option<(u32, u32)> {
+        // `max_by_key` picks the last of the largest
+        self.gaps
+            .iter()
+            .rev()
+            .copied()
+            .max_by_key(|&(_, size)| size)
+    }
+
+    /// How much of the free space is in the largest free block, from 0 to 1; the lower, the more
+    /// fragmented the bank is. A full bank is considered to not be fragmented at all.
+    pub fn contiguity(&self) -> f64 {
+        match self.free() {
+            0 => 1.0,
+            free => f64::from(self.largest_gap()) / f64::from(free),
+        }
+    }
+}
+
+/// Computes the stats of every bank of the given regions, from the sections' final placements;
+/// ROM0 sections past $4000 count towards the banks they're displayed in.
+pub fn bank_stats(sequence: &Sequence, regions: &[Region], rom0_half: Rom0Half) -> Vec<BankStats> {
+    // The areas covered in each bank, as `(region, bank, start, end)`, the same as when rendering
+    let mut areas: Vec<(Region, u32, u32, u32)> = Vec::new();
+    for (location, section) in sequence
+        .final_placements()
+        .into_iter()
+        .zip(&sequence.sections)
+    {
+        let location = match location {
+            Some(location) => location,
+            None => continue,
+        };
+        let region = section.mem_type.region();
+        for (bank, ofs, size) in rom0_half.section_spans(section, location) {
+            let end = cmp::min(
+                ofs.saturating_add(size),
+                rom0_half.column_size(region, bank),
+            );
+            areas.push((region, bank, cmp::min(ofs, end), end));
+        }
+    }
+    areas.sort_unstable_by_key(|&(region, bank, start, _)| (region, bank, start));
+
+    let mut stats = Vec::new();
+    for region in Region::ALL.iter().copied() {
+        if !regions.contains(&region) {
+            continue;
+        }
+        for bank in 0..sequence.nb_banks(region) {
+            let size = rom0_half.column_size(region, bank);
+            let mut bank_stats = BankStats {
+                region,
+                bank,
+                size,
+                used: 0,
+                gaps: Vec::new(),
+            };
+            // Merge overlapping areas as we go, so that they are only counted once
+            let mut free_from = 0;
+            for &(_, _, start, end) in areas
+                .iter()
+                .filter(|&&(reg, bnk, _, _)| reg == region && bnk == bank)
+            {
+                if start > free_from {
+                    bank_stats.gaps.push((free_from, start - free_from));
+                }
+                if end > free_from {
+                    bank_stats.used += end - cmp::max(start, free_from);
+                    free_from = end;
+                }
+            }
+            if free_from < size {
+                bank_stats.gaps.push((free_from, size - free_from));
+            }
+            stats.push(bank_stats);
+        }
+    }
+    stats
+}
+
+/// Prints a table of the banks' stats to standard output.
+pub fn print_stats(sequence: &Sequence, regions: &[Region], rom0_half: Rom0Half) {
+    println!("Region\tBank\tUsed\tFree\tGaps\tLargest gap\tContiguity");
+    for bank in bank_stats(sequence, regions, rom0_half) {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{:.1}%",
+            bank.region,
+            bank.bank,
+            bank.used,
+            bank.free(),
+            bank.gaps.len(),
+            bank.largest_gap(),
+            bank.contiguity() * 100.0
+        );
+    }
+}
+
+/// Prints a table of the sections and where they end up to standard output, sorted by location;
+/// sections that are never placed come last, in the order they were seen.
+pub fn print_sections(sequence: &Sequence, addr_style: AddrStyle) {
+    let mut sections: Vec<_> = sequence
+        .final_placements()
+        .into_iter()
+        .zip(&sequence.sections)
+        .collect();
+    // `None` sorts first, but should come last
+    sections.sort_by_key(|(location, _)| {
+        (
+            location.is_none(),
+            location.map(|location| (location.bank, location.addr)),
+        )
+    });
+
+    println!("Name\tType\tLocation\tSize");
+    for (location, section) in sections {
+        let location = match location {
+            Some(location) => section.format_location(location, addr_style),
+            None => "-".to_string(),
+        };
+        println!(
+            "{}\t{}\t{}\t{}",
+            section.name, section.mem_type, location, section.size
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    /// A bank's size, how many bytes of it are used, and its gaps.
+    type Usage = (u32, u32, Vec<(u32, u32)>);
+
+    /// How ROM banks 0 and 1 end up used.
+    fn rom_stats(log: &str, rom0_half: Rom0Half) -> Vec<Usage> {
+        let sequence =
+            parse::parse_input(log.as_bytes(), &Default::default(), &mut Vec::new()).unwrap();
+        bank_stats(&sequence, &[Region::Rom], rom0_half)
+            .into_iter()
+            .take(2)
+            .map(|bank| (bank.size, bank.used, bank.gaps))
+            .collect()
+    }
+
+    const UPPER_HALF: &str = "[ROM0 @ 00:ffff & 0 + 0] 256 A\n00:4100\n";
+
+    #[test]
+    fn rom0_upper_half_is_counted_where_it_is_drawn() {
+        assert_eq!(
+            rom_stats(UPPER_HALF, Rom0Half::Fold),
+            [
+                (0x4000, 0x100, vec![(0, 0x100), (0x200, 0x3e00)]),
+                (0x4000, 0, vec![(0, 0x4000)])
+            ]
+        );
+        assert_eq!(
+            rom_stats(UPPER_HALF, Rom0Half::Romx),
+            [
+                (0x4000, 0, vec![(0, 0x4000)]),
+                (0x4000, 0x100, vec![(0, 0x100), (0x200, 0x3e00)])
+            ]
+        );
+        assert_eq!(
+            rom_stats(UPPER_HALF, Rom0Half::Tall),
+            [
+                (0x8000, 0x100, vec![(0, 0x4100), (0x4200, 0x3e00)]),
+                (0x4000, 0, vec![(0, 0x4000)])
+            ]
+        );
+    }
+
+    #[test]
+    fn sizes_past_the_bank_are_capped() {
+        let log = "[ROMX @ 01:ffff & 0 + 0] 4294967295 A\n01:7ff0\n";
+        assert_eq!(
+            rom_stats(log, Rom0Half::Fold)[1],
+            (0x4000, 0x10, vec![(0, 0x3ff0)])
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Drawing frames in a terminal, as ANSI art: each character is an upper half block, whose
+//! foreground and background colors are two pixels on top of each other.
+
+use std::convert::TryFrom;
+use std::env;
+use std::io::{self, Write};
+
+/// Clears the whole screen.
+pub const CLEAR: &str = "\x1b[2J";
+/// Moves the cursor to the top-left corner.
+pub const HOME: &str = "\x1b[H";
+/// Clears everything after the cursor.
+pub const CLEAR_BELOW: &str = "\x1b[J";
+
+/// The size of the terminal that standard output goes to, in characters, if it is one.
+#[cfg(unix)]
+fn terminal_size() -> Option<(u32, u32)> {
+    // SAFETY: `winsize` is plain old data, which `TIOCGWINSZ` only writes into
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    (ok && size.ws_col != 0 && size.ws_row != 0)
+        .then(|| (u32::from(size.ws_col), u32::from(size.ws_row)))
+}
+
+#[cfg(not(unix))]
+fn terminal_size() -> Option<(u32, u32)> {
+    None
+}
+
+/// How many columns and lines drawings should fit in: the terminal's, or else `$COLUMNS` by
+/// `$LINES`, or else 80 by 24. This is checked again before each drawing, to follow resizes.
+pub fn size() -> (u32, u32) {
+    terminal_size().unwrap_or_else(|| {
+        let var = |name: &str, default: u32| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .filter(|&value| value != 0)
+                .unwrap_or(default)
+        };
+        (var("COLUMNS", 80), var("LINES", 24))
+    })
+}
+
+/// Writes the pixels, two rows per line; if there's an odd amount, the last line's bottom halves
+/// are left blank. Colors are only written when they change, to keep the output small.
+pub fn write_frame(out: &mut impl Write, width: u32, height: u32, pixels: &[u8]) -> io::Result<()> {
+    let pixel = |x: u32, y: u32| {
+        let idx = usize::try_from(x + y * width).unwrap() * 3;
+        (pixels[idx], pixels[idx + 1], pixels[idx + 2])
+    };
+    for y in (0..height).step_by(2) {
+        let (mut fg, mut bg) = (None, None);
+        for x in 0..width {
+            let top = pixel(x, y);
+            if fg != Some(top) {
+                write!(out, "\x1b[38;2;{};{};{}m", top.0, top.1, top.2)?;
+                fg = Some(top);
+            }
+            let bottom = (y + 1 < height).then(|| pixel(x, y + 1));
+            if bg != Some(bottom) {
+                match bottom {
+                    Some((r, g, b)) => write!(out, "\x1b[48;2;{};{};{}m", r, g, b)?,
+                    None => write!(out, "\x1b[49m")?,
+                }
+                bg = Some(bottom);
+            }
+            write!(out, "\u{2580}")?;
+        }
+        writeln!(out, "\x1b[0m")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//! Things worth pointing out, which don't keep the output from being rendered; they're collected
+//! instead of being printed right away, so that `--strict` can turn them all into an error.
+
+use crate::parse::{self, OnDuplicate, ParseError};
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Warning {
+    /// A location outside of its section's memory type, or in another bank than 0 although it's
+    /// not banked.
+    Location(ParseError),
+    /// A malformed line that `--keep-going` skipped.
+    SkippedLine(ParseError),
+    /// How many attempts were dropped for a section whose line was skipped, and which line that is.
+    SkippedAttempts(u64, u64),
+    /// How many attempts `--skip-orphan-attempts` counted before any section, and on which lines
+    /// (only the first few).
+    OrphanAttempts(u64, Vec<u64>),
+    /// A section defined again, and what was done about it.
+    DuplicateSection(ParseError, Option<OnDuplicate>),
+    /// A ROM bank that the log uses, although the ROM has only so many banks.
+    BeyondRom(u32, u32),
+    /// A `--snapshot` of a frame that the output doesn't have.
+    MissingSnapshot(usize, PathBuf),
+    /// The `--poster-frame` asked for is past the end of the output.
+    MissingPoster(usize),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::Location(err) => write!(fmt, "{}", err),
+            Warning::SkippedLine(err) => write!(fmt, "Skipped line: {}", err),
+            Warning::SkippedAttempts(nb, line_no) => write!(
+                fmt,
+                "Skipped {} location attempt(s) for the section skipped on line {}",
+                nb, line_no
+            ),
+            Warning::OrphanAttempts(nb, line_nos) => write!(
+                fmt,
+                "Skipped {} location attempt(s) before any sections, on line(s) {}",
+                nb,
+                parse::list_line_nos(*nb, line_nos)
+            ),
+            Warning::DuplicateSection(err, on_duplicate) => write!(
+                fmt,
+                "{}; {}",
+                err,
+                match on_duplicate {
+                    None => "keeping both",
+                    Some(OnDuplicate::Merge) => "merging them",
+                    Some(OnDuplicate::Last) => "only keeping the new one",
+                    Some(OnDuplicate::Error) => unreachable!("Duplicates are errors then"),
+                }
+            ),
+            Warning::BeyondRom(bank, nb_banks) => write!(
+                fmt,
+                "The log uses ROM bank {}, but the ROM only has {} banks",
+                bank, nb_banks
+            ),
+            Warning::MissingSnapshot(index, path) => write!(
+                fmt,
+                "Frame {} was not rendered, so {} was not written",
+                index,
+                path.display()
+            ),
+            Warning::MissingPoster(index) => write!(
+                fmt,
+                "Frame {} was not rendered, so the MP4 has no cover art",
+                index
+            ),
+        }
+    }
+}
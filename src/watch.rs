@@ -0,0 +1,72 @@
+//! Re-rendering whenever the input changes.
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How long to wait for writes to settle down before re-rendering.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Calls `run` once, then again every time the file at `path` changes, until the watch fails.
+/// Changes that happen while `run` is running trigger a new run once it's finished.
+pub fn watch(path: &Path, mut run: impl FnMut()) -> notify::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    // Many editors replace the file instead of writing to it, which would end a watch on the file
+    // itself; so, watch its directory instead, and only keep events about the file.
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    let is_relevant = |event: &Event| {
+        matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Any
+        ) && event
+            .paths
+            .iter()
+            .any(|event_path| event_path.file_name() == path.file_name())
+    };
+
+    loop {
+        eprintln!("[{}] Rendering {}", timestamp(), path.display());
+        run();
+        eprintln!("[{}] Waiting for changes...", timestamp());
+
+        // Wait for the file to change...
+        while !is_relevant(&rx.recv().map_err(|_| hung_up())??) {}
+        // ...then for it to stop changing, which other files changing in the meantime don't delay
+        let mut deadline = Instant::now() + DEBOUNCE;
+        loop {
+            match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(event) => {
+                    if is_relevant(&event?) {
+                        deadline = Instant::now() + DEBOUNCE;
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err(hung_up()),
+            }
+        }
+    }
+}
+
+/// The error for when the watcher stops sending events, which it shouldn't do while it's alive.
+fn hung_up() -> notify::Error {
+    notify::Error::generic("File watcher hung up")
+}
+
+/// The current time of day, as `HH:MM:SS` (UTC).
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
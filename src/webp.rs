@@ -0,0 +1,486 @@
+//! Writing frames as a lossless animated WebP, which browsers support and is much smaller than an
+//! APNG, as frames are made of few colors, in large uniform areas.
+//!
+//! Each frame only stores the rectangle that changed since the previous one, as a VP8L (lossless)
+//! bitstream. Those are encoded without any transforms nor color cache, only with prefix codes and
+//! backward references to the pixel above or to the left, which is what frames mostly repeat.
+
+use crate::render::{Clock, RenderError, VideoSink};
+use std::cmp;
+use std::collections::BinaryHeap;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+/// VP8L bitstreams store dimensions on 14 bits.
+const MAX_SIZE: u32 = 1 << 14;
+
+/// Writes frames as an animated WebP. The file begins with its size, so it's only written once
+/// finished; until then, the (compressed) frames are kept in memory.
+pub struct WebpSink<W: Write> {
+    out: W,
+    width: u32,
+    height: u32,
+    clock: Clock,
+    /// How many frames have been written so far.
+    nb_frames: u64,
+    /// The previous frame's pixels, to only store what changed since.
+    prev: Vec<u8>,
+    /// The frames' chunks, in order.
+    chunks: Vec<u8>,
+}
+
+impl<W: Write> WebpSink<W> {
+    pub fn new(out: W, width: u32, height: u32, clock: Clock) -> Result<Self, RenderError> {
+        if width > MAX_SIZE || height > MAX_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("WebP frames can't be larger than {0}x{0}", MAX_SIZE),
+            )
+            .into());
+        }
+        Ok(Self {
+            out,
+            width,
+            height,
+            clock,
+            nb_frames: 0,
+            prev: Vec::new(),
+            chunks: Vec::new(),
+        })
+    }
+
+    /// The smallest rectangle containing every pixel that changed since the previous frame, as
+    /// `(x, y, width, height)`; its top-left corner must be on even coordinates.
+    fn changed_rect(&self, pixels: &[u8]) -> (u32, u32, u32, u32) {
+        if self.prev.is_empty() {
+            return (0, 0, self.width, self.height);
+        }
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+        let row_len = self.width as usize * 3;
+        for (y, (row, prev_row)) in pixels
+            .chunks_exact(row_len)
+            .zip(self.prev.chunks_exact(row_len))
+            .enumerate()
+        {
+            if row == prev_row {
+                continue;
+            }
+            let y = y as u32;
+            let changed = |x: &u32| row[*x as usize * 3..][..3] != prev_row[*x as usize * 3..][..3];
+            let first = (0..self.width).find(changed).unwrap();
+            let last = (0..self.width).rev().find(changed).unwrap();
+            min_x = cmp::min(min_x, first);
+            max_x = cmp::max(max_x, last);
+            min_y = cmp::min(min_y, y);
+            max_y = y;
+        }
+        if min_y == u32::MAX {
+            // Nothing changed, but frames can't be empty
+            return (0, 0, 1, 1);
+        }
+        let (x, y) = (min_x & !1, min_y & !1);
+        (x, y, max_x + 1 - x, max_y + 1 - y)
+    }
+}
+
+impl<W: Write> VideoSink for WebpSink<W> {
+    fn write_frame(&mut self, pixels: &[u8]) -> Result<(), RenderError> {
+        let (x, y, width, height) = self.changed_rect(pixels);
+        let stride = self.width;
+        let argb: Vec<u32> = (y..y + height)
+            .flat_map(|y| (x..x + width).map(move |x| (y * stride + x) as usize * 3))
+            .map(|i| {
+                0xFF00_0000
+                    | u32::from(pixels[i]) << 16
+                    | u32::from(pixels[i + 1]) << 8
+                    | u32::from(pixels[i + 2])
+            })
+            .collect();
+        let bitstream = encode_vp8l(width, height, &argb);
+
+        // Frames may not all last the same when fitting a duration
+        let duration = self.clock.delay_ms(self.nb_frames);
+        let mut frame = Vec::with_capacity(16 + 8 + bitstream.len() + 1);
+        frame.extend_from_slice(&u24(x / 2));
+        frame.extend_from_slice(&u24(y / 2));
+        frame.extend_from_slice(&u24(width - 1));
+        frame.extend_from_slice(&u24(height - 1));
+        frame.extend_from_slice(&u24(cmp::min(duration, 0xFF_FFFF) as u32));
+        // Overwrite the previous frame's pixels instead of blending, and leave the rest as-is
+        frame.push(0b10);
+        write_chunk(&mut frame, b"VP8L", &bitstream);
+        write_chunk(&mut self.chunks, b"ANMF", &frame);
+
+        self.nb_frames += 1;
+        self.prev.clear();
+        self.prev.extend_from_slice(pixels);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), RenderError> {
+        let mut header = Vec::with_capacity(30);
+        // Only animated
+        header.push(0b10);
+        header.extend_from_slice(&[0; 3]);
+        header.extend_from_slice(&u24(self.width - 1));
+        header.extend_from_slice(&u24(self.height - 1));
+        let mut vp8x = Vec::new();
+        write_chunk(&mut vp8x, b"VP8X", &header);
+        // Black background, looping forever
+        let mut anim = Vec::new();
+        write_chunk(&mut anim, b"ANIM", &[0, 0, 0, 0xFF, 0, 0]);
+
+        let size = u32::try_from(4 + vp8x.len() + anim.len() + self.chunks.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "WebP animation too large"))?;
+        self.out.write_all(b"RIFF")?;
+        self.out.write_all(&size.to_le_bytes())?;
+        self.out.write_all(b"WEBP")?;
+        self.out.write_all(&vp8x)?;
+        self.out.write_all(&anim)?;
+        self.out.write_all(&self.chunks)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn u24(value: u32) -> [u8; 3] {
+    let [a, b, c, _] = value.to_le_bytes();
+    [a, b, c]
+}
+
+/// Appends a RIFF chunk, padded to an even size as required.
+fn write_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&u32::try_from(data.len()).unwrap().to_le_bytes());
+    out.extend_from_slice(data);
+    if !data.len().is_multiple_of(2) {
+        out.push(0);
+    }
+}
+
+/// The sizes of the five alphabets: green and backward reference lengths, red, blue, alpha, and
+/// distances.
+const ALPHABET_SIZES: [usize; 5] = [256 + 24, 256, 256, 256, 40];
+/// Backward references shorter than this are not worth it over literal pixels.
+const MIN_COPY_LEN: usize = 3;
+const MAX_COPY_LEN: usize = 4096;
+/// Distance codes for the pixel above and the one to the left.
+const DIST_UP: u32 = 1;
+const DIST_LEFT: u32 = 2;
+
+enum Token {
+    Literal(u32),
+    /// A length and a distance code.
+    Copy(u32, u32),
+}
+
+/// Encodes pixels, as ARGB, into a VP8L bitstream.
+fn encode_vp8l(width: u32, height: u32, argb: &[u32]) -> Vec<u8> {
+    let tokens = backward_refs(width as usize, argb);
+
+    let mut freqs: [Vec<u32>; 5] = ALPHABET_SIZES.map(|size| vec![0; size]);
+    for token in &tokens {
+        match *token {
+            Token::Literal(pixel) => {
+                freqs[0][(pixel >> 8 & 0xFF) as usize] += 1;
+                freqs[1][(pixel >> 16 & 0xFF) as usize] += 1;
+                freqs[2][(pixel & 0xFF) as usize] += 1;
+                freqs[3][(pixel >> 24) as usize] += 1;
+            }
+            Token::Copy(len, dist) => {
+                freqs[0][256 + prefix(len).0 as usize] += 1;
+                freqs[4][prefix(dist).0 as usize] += 1;
+            }
+        }
+    }
+
+    let mut bits = BitWriter::default();
+    bits.write(0x2F, 8);
+    bits.write(width - 1, 14);
+    bits.write(height - 1, 14);
+    // No alpha, and version 0
+    bits.write(0, 1);
+    bits.write(0, 3);
+    // No transforms, color cache, nor meta prefix codes
+    bits.write(0, 1);
+    bits.write(0, 1);
+    bits.write(0, 1);
+    let codes: Vec<PrefixCode> = freqs
+        .iter()
+        .map(|freqs| PrefixCode::write(&mut bits, freqs))
+        .collect();
+
+    for token in &tokens {
+        match *token {
+            Token::Literal(pixel) => {
+                codes[0].write_symbol(&mut bits, pixel >> 8 & 0xFF);
+                codes[1].write_symbol(&mut bits, pixel >> 16 & 0xFF);
+                codes[2].write_symbol(&mut bits, pixel & 0xFF);
+                codes[3].write_symbol(&mut bits, pixel >> 24);
+            }
+            Token::Copy(len, dist) => {
+                let (symbol, nb_extra, extra) = prefix(len);
+                codes[0].write_symbol(&mut bits, 256 + symbol);
+                bits.write(extra, nb_extra);
+                let (symbol, nb_extra, extra) = prefix(dist);
+                codes[4].write_symbol(&mut bits, symbol);
+                bits.write(extra, nb_extra);
+            }
+        }
+    }
+    bits.finish()
+}
+
+/// Greedily replaces runs of pixels repeating the row above, or the pixel to the left, with
+/// backward references.
+fn backward_refs(width: usize, argb: &[u32]) -> Vec<Token> {
+    let run_len = |i: usize, dist: usize| {
+        argb[i..]
+            .iter()
+            .zip(&argb[i - dist..])
+            .take(MAX_COPY_LEN)
+            .take_while(|(pixel, prev)| pixel == prev)
+            .count()
+    };
+
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < argb.len() {
+        let up = if i >= width { run_len(i, width) } else { 0 };
+        let left = if i >= 1 { run_len(i, 1) } else { 0 };
+        let (len, dist) = if up >= left {
+            (up, DIST_UP)
+        } else {
+            (left, DIST_LEFT)
+        };
+        if len >= MIN_COPY_LEN {
+            tokens.push(Token::Copy(len as u32, dist));
+            i += len;
+        } else {
+            tokens.push(Token::Literal(argb[i]));
+            i += 1;
+        }
+    }
+    tokens
+}
+
+/// Splits a length or distance code into the symbol coding it, and how many extra bits follow
+/// with which value.
+fn prefix(value: u32) -> (u32, u8, u32) {
+    let value = value - 1;
+    if value < 4 {
+        return (value, 0, 0);
+    }
+    let highest_bit = 31 - value.leading_zeros();
+    let second_bit = value >> (highest_bit - 1) & 1;
+    let nb_extra = highest_bit - 1;
+    (
+        2 * highest_bit + second_bit,
+        nb_extra as u8,
+        value & ((1 << nb_extra) - 1),
+    )
+}
+
+/// A canonical prefix code, as each symbol's code and length; codes are stored bit-reversed, as
+/// they are written starting from their most significant bit, but bits are packed LSB-first.
+struct PrefixCode {
+    codes: Vec<(u16, u8)>,
+}
+
+impl PrefixCode {
+    /// How long codes of the main alphabets can be.
+    const MAX_LEN: u8 = 15;
+    /// How long codes of the code used to write code lengths can be.
+    const MAX_CODE_LENGTH_LEN: u8 = 7;
+    /// In which order the lengths of the code length code are written.
+    const CODE_LENGTH_ORDER: [usize; 19] = [
+        17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+    ];
+
+    /// Picks a code for symbols occurring this often, and writes it.
+    fn write(bits: &mut BitWriter, freqs: &[u32]) -> Self {
+        let used: Vec<usize> = (0..freqs.len()).filter(|&i| freqs[i] != 0).collect();
+        // A "simple" code, of one or two symbols that fit in 8 bits, is much shorter to write
+        if used.len() <= 2 && used.iter().all(|&symbol| symbol < 256) {
+            let mut lengths = vec![0; freqs.len()];
+            bits.write(1, 1);
+            match *used.as_slice() {
+                [] | [_] => {
+                    // A single symbol, which takes no bits at all
+                    let symbol = used.first().copied().unwrap_or(0);
+                    bits.write(0, 1);
+                    Self::write_simple_symbol(bits, symbol);
+                    return Self {
+                        codes: vec![(0, 0); freqs.len()],
+                    };
+                }
+                [first, second] => {
+                    bits.write(1, 1);
+                    Self::write_simple_symbol(bits, first);
+                    bits.write(second as u32, 8);
+                    lengths[first] = 1;
+                    lengths[second] = 1;
+                }
+                _ => unreachable!(),
+            }
+            return Self::from_lengths(&lengths);
+        }
+
+        let mut freqs = freqs.to_vec();
+        // Decoders view single-symbol codes as taking no bits, so make sure there are two
+        if used.len() == 1 {
+            freqs[if used[0] == 0 { 1 } else { 0 }] = 1;
+        }
+        let lengths = code_lengths(&freqs, Self::MAX_LEN);
+
+        // Code lengths are themselves written with a prefix code, runs of zeros being shortened
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < lengths.len() {
+            let nb_zeros = lengths[i..].iter().take_while(|&&len| len == 0).count();
+            match nb_zeros {
+                11.. => {
+                    let len = cmp::min(nb_zeros, 138);
+                    tokens.push((18, 7, len as u32 - 11));
+                    i += len;
+                }
+                3.. => {
+                    tokens.push((17, 3, nb_zeros as u32 - 3));
+                    i += nb_zeros;
+                }
+                _ => {
+                    tokens.push((usize::from(lengths[i]), 0, 0));
+                    i += 1;
+                }
+            }
+        }
+        let mut length_freqs = [0; 19];
+        for &(symbol, _, _) in &tokens {
+            length_freqs[symbol] += 1;
+        }
+        if length_freqs.iter().filter(|&&freq| freq != 0).count() == 1 {
+            length_freqs[if tokens[0].0 == 0 { 1 } else { 0 }] = 1;
+        }
+        let length_lengths = code_lengths(&length_freqs, Self::MAX_CODE_LENGTH_LEN);
+        let length_code = Self::from_lengths(&length_lengths);
+
+        bits.write(0, 1);
+        let nb_length_lengths = Self::CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&symbol| length_lengths[symbol] != 0)
+            .map_or(0, |i| i + 1)
+            .max(4);
+        bits.write(nb_length_lengths as u32 - 4, 4);
+        for &symbol in &Self::CODE_LENGTH_ORDER[..nb_length_lengths] {
+            bits.write(u32::from(length_lengths[symbol]), 3);
+        }
+        // Lengths are given for the whole alphabet
+        bits.write(0, 1);
+        for (symbol, nb_extra, extra) in tokens {
+            length_code.write_symbol(bits, symbol as u32);
+            bits.write(extra, nb_extra);
+        }
+
+        Self::from_lengths(&lengths)
+    }
+
+    /// Writes the first symbol of a simple code, on a single bit if possible.
+    fn write_simple_symbol(bits: &mut BitWriter, symbol: usize) {
+        if symbol < 2 {
+            bits.write(0, 1);
+            bits.write(symbol as u32, 1);
+        } else {
+            bits.write(1, 1);
+            bits.write(symbol as u32, 8);
+        }
+    }
+
+    /// Assigns codes to symbols by increasing length, then by symbol, as decoders do.
+    fn from_lengths(lengths: &[u8]) -> Self {
+        let mut nb_per_len = [0_u16; 16];
+        for &len in lengths {
+            nb_per_len[usize::from(len)] += 1;
+        }
+        nb_per_len[0] = 0;
+        let mut next_code = [0_u16; 16];
+        let mut code = 0;
+        for len in 1..16 {
+            code = (code + nb_per_len[len - 1]) << 1;
+            next_code[len] = code;
+        }
+        let codes = lengths
+            .iter()
+            .map(|&len| {
+                if len == 0 {
+                    return (0, 0);
+                }
+                let code = next_code[usize::from(len)];
+                next_code[usize::from(len)] += 1;
+                (code.reverse_bits() >> (16 - len), len)
+            })
+            .collect();
+        Self { codes }
+    }
+
+    fn write_symbol(&self, bits: &mut BitWriter, symbol: u32) {
+        let (code, len) = self.codes[symbol as usize];
+        bits.write(u32::from(code), len);
+    }
+}
+
+/// Computes Huffman code lengths for symbols occurring this often, none longer than `max_len`;
+/// if some would be, rare symbols are made to look more common until none are.
+fn code_lengths(freqs: &[u32], max_len: u8) -> Vec<u8> {
+    let mut min_freq = 1;
+    loop {
+        let mut heap: BinaryHeap<_> = freqs
+            .iter()
+            .enumerate()
+            .filter(|(_, &freq)| freq != 0)
+            .map(|(symbol, &freq)| {
+                cmp::Reverse((u64::from(cmp::max(freq, min_freq)), vec![symbol]))
+            })
+            .collect();
+        let mut lengths = vec![0; freqs.len()];
+        while heap.len() > 1 {
+            let cmp::Reverse((freq_a, mut symbols)) = heap.pop().unwrap();
+            let cmp::Reverse((freq_b, other)) = heap.pop().unwrap();
+            symbols.extend(other);
+            for &symbol in &symbols {
+                lengths[symbol] += 1;
+            }
+            heap.push(cmp::Reverse((freq_a + freq_b, symbols)));
+        }
+        if lengths.iter().all(|&len| len <= max_len) {
+            return lengths;
+        }
+        min_freq *= 2;
+    }
+}
+
+/// Packs bits LSB-first, as VP8L requires.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    nb_bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, nb_bits: u8) {
+        self.acc |= u64::from(value) << self.nb_bits;
+        self.nb_bits += nb_bits;
+        while self.nb_bits >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.nb_bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.nb_bits != 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
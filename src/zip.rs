@@ -0,0 +1,273 @@
+//! Writing files into a zip archive one by one, so that e.g. thousands of frames make up a single
+//! file, without having to hold them all.
+//!
+//! PNGs are compressed already, so entries are simply stored, which is all that is supported.
+//! Past 65535 entries or 4 GiB, the archive is made Zip64, but each entry must remain smaller than
+//! 4 GiB.
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+/// Signatures of the records making up the archive.
+const LOCAL_HEADER_SIG: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIG: u32 = 0x02014b50;
+const END_SIG: u32 = 0x06054b50;
+const ZIP64_END_SIG: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x07064b50;
+/// Version 1.0 of the format, which is enough to extract stored entries.
+const VERSION: u16 = 10;
+/// Version 4.5 of the format, which introduced Zip64.
+const ZIP64_VERSION: u16 = 45;
+/// The extra field holding a central header's values that don't fit in it.
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+/// 1980-01-01 at midnight, the earliest MS-DOS date, so that archives don't depend on when they
+/// were written.
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = 1 << 5 | 1;
+
+pub struct ZipWriter<W: Write> {
+    out: W,
+    /// How many bytes have been written so far.
+    ofs: u64,
+    /// What the central directory needs to know about each entry written so far.
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    /// Where the entry's local header begins.
+    ofs: u64,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            ofs: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds a file to the archive, with the given contents.
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let size = u32::try_from(data.len()).map_err(|_| too_large("Zip entry too large"))?;
+        let entry = Entry {
+            name: name.to_string(),
+            crc: crc32fast::hash(data),
+            size,
+            ofs: self.ofs,
+        };
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&LOCAL_HEADER_SIG.to_le_bytes());
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        entry.write_common(&mut header);
+        // No extra field
+        header.extend_from_slice(&0_u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        self.write(&header)?;
+        self.write(data)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Writes the central directory, which lists the entries, and returns the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        let dir_ofs = self.ofs;
+        let mut dir = Vec::new();
+        for entry in &self.entries {
+            // Entries past 4 GiB have their offset in an extra field instead
+            let ofs = u32::try_from(entry.ofs).ok().filter(|&ofs| ofs != u32::MAX);
+            dir.extend_from_slice(&CENTRAL_HEADER_SIG.to_le_bytes());
+            // Made by MS-DOS, which means no special file attributes
+            let version = if ofs.is_some() {
+                VERSION
+            } else {
+                ZIP64_VERSION
+            };
+            dir.extend_from_slice(&version.to_le_bytes());
+            dir.extend_from_slice(&version.to_le_bytes());
+            entry.write_common(&mut dir);
+            let extra_len: u16 = if ofs.is_some() { 0 } else { 12 };
+            dir.extend_from_slice(&extra_len.to_le_bytes());
+            // No comment, disk number, nor attributes
+            dir.extend_from_slice(&[0; 10]);
+            dir.extend_from_slice(&ofs.unwrap_or(u32::MAX).to_le_bytes());
+            dir.extend_from_slice(entry.name.as_bytes());
+            if ofs.is_none() {
+                dir.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+                dir.extend_from_slice(&8_u16.to_le_bytes());
+                dir.extend_from_slice(&entry.ofs.to_le_bytes());
+            }
+        }
+        let dir_size = dir.len() as u64;
+        self.write(&dir)?;
+
+        // Values that don't fit are maxed out, and given by the Zip64 records instead
+        let nb_entries = u16::try_from(self.entries.len())
+            .ok()
+            .filter(|&nb| nb != u16::MAX);
+        let dir_size_32 = u32::try_from(dir_size)
+            .ok()
+            .filter(|&size| size != u32::MAX);
+        let dir_ofs_32 = u32::try_from(dir_ofs).ok().filter(|&ofs| ofs != u32::MAX);
+        if nb_entries.is_none() || dir_size_32.is_none() || dir_ofs_32.is_none() {
+            let zip64_end_ofs = self.ofs;
+            let nb_entries = self.entries.len() as u64;
+            let mut zip64_end = Vec::with_capacity(56 + 20);
+            zip64_end.extend_from_slice(&ZIP64_END_SIG.to_le_bytes());
+            // The size of the rest of the record
+            zip64_end.extend_from_slice(&44_u64.to_le_bytes());
+            zip64_end.extend_from_slice(&ZIP64_VERSION.to_le_bytes());
+            zip64_end.extend_from_slice(&ZIP64_VERSION.to_le_bytes());
+            zip64_end.extend_from_slice(&[0; 8]);
+            zip64_end.extend_from_slice(&nb_entries.to_le_bytes());
+            zip64_end.extend_from_slice(&nb_entries.to_le_bytes());
+            zip64_end.extend_from_slice(&dir_size.to_le_bytes());
+            zip64_end.extend_from_slice(&dir_ofs.to_le_bytes());
+
+            zip64_end.extend_from_slice(&ZIP64_LOCATOR_SIG.to_le_bytes());
+            zip64_end.extend_from_slice(&0_u32.to_le_bytes());
+            zip64_end.extend_from_slice(&zip64_end_ofs.to_le_bytes());
+            // A single disk in total
+            zip64_end.extend_from_slice(&1_u32.to_le_bytes());
+            self.write(&zip64_end)?;
+        }
+
+        let nb_entries = nb_entries.unwrap_or(u16::MAX);
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&END_SIG.to_le_bytes());
+        // All of this is on the first and only disk
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&nb_entries.to_le_bytes());
+        end.extend_from_slice(&nb_entries.to_le_bytes());
+        end.extend_from_slice(&dir_size_32.unwrap_or(u32::MAX).to_le_bytes());
+        end.extend_from_slice(&dir_ofs_32.unwrap_or(u32::MAX).to_le_bytes());
+        // No comment
+        end.extend_from_slice(&0_u16.to_le_bytes());
+        self.write(&end)?;
+
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.ofs += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+impl Entry {
+    /// Writes the fields that local and central headers share, up to the name's length included.
+    fn write_common(&self, header: &mut Vec<u8>) {
+        // No flags, and stored as-is
+        header.extend_from_slice(&[0; 4]);
+        header.extend_from_slice(&DOS_TIME.to_le_bytes());
+        header.extend_from_slice(&DOS_DATE.to_le_bytes());
+        header.extend_from_slice(&self.crc.to_le_bytes());
+        // Entries aren't compressed, so both sizes are the same
+        header.extend_from_slice(&self.size.to_le_bytes());
+        header.extend_from_slice(&self.size.to_le_bytes());
+        header.extend_from_slice(&u16::try_from(self.name.len()).unwrap().to_le_bytes());
+    }
+}
+
+fn too_large(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn u16_at(bytes: &[u8], ofs: usize) -> u16 {
+        u16::from_le_bytes(bytes[ofs..ofs + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], ofs: usize) -> u32 {
+        u32::from_le_bytes(bytes[ofs..ofs + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], ofs: usize) -> u64 {
+        u64::from_le_bytes(bytes[ofs..ofs + 8].try_into().unwrap())
+    }
+
+    /// The archive's Zip64 end record, checking that it's pointed to as it should.
+    fn zip64_end(archive: &[u8], start_ofs: u64) -> &[u8] {
+        let end = &archive[archive.len() - 22..];
+        let locator = &archive[archive.len() - 42..archive.len() - 22];
+        assert_eq!(u32_at(end, 0), END_SIG);
+        assert_eq!(u32_at(locator, 0), ZIP64_LOCATOR_SIG);
+        let ofs = (u64_at(locator, 8) - start_ofs) as usize;
+        let zip64_end = &archive[ofs..archive.len() - 42];
+        assert_eq!(u32_at(zip64_end, 0), ZIP64_END_SIG);
+        assert_eq!(u64_at(zip64_end, 4), zip64_end.len() as u64 - 12);
+        zip64_end
+    }
+
+    #[test]
+    fn small_archives_are_not_zip64() {
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add("0.png", b"0").unwrap();
+        zip.add("1.png", b"1").unwrap();
+        let archive = zip.finish().unwrap();
+        let end = &archive[archive.len() - 22..];
+        assert_eq!(u32_at(end, 0), END_SIG);
+        assert_eq!((u16_at(end, 8), u16_at(end, 10)), (2, 2));
+        assert!(!archive
+            .windows(4)
+            .any(|sig| sig == ZIP64_END_SIG.to_le_bytes()));
+    }
+
+    #[test]
+    fn many_entries_make_the_archive_zip64() {
+        let mut zip = ZipWriter::new(Vec::new());
+        for i in 0..70_000 {
+            zip.add(&format!("{}.png", i), &[i as u8]).unwrap();
+        }
+        let archive = zip.finish().unwrap();
+        let end = &archive[archive.len() - 22..];
+        assert_eq!((u16_at(end, 8), u16_at(end, 10)), (u16::MAX, u16::MAX));
+
+        let zip64_end = zip64_end(&archive, 0);
+        assert_eq!(
+            (u64_at(zip64_end, 24), u64_at(zip64_end, 32)),
+            (70_000, 70_000)
+        );
+        // The central directory still fits, so it's where both records say
+        let dir_ofs = u64_at(zip64_end, 48);
+        assert_eq!(u64::from(u32_at(end, 16)), dir_ofs);
+        assert_eq!(u32_at(&archive, dir_ofs as usize), CENTRAL_HEADER_SIG);
+    }
+
+    #[test]
+    fn entries_past_4_gib_have_their_offset_in_zip64() {
+        // As if the entries came after 5 GiB of others
+        let start_ofs = 5 << 30;
+        let mut zip = ZipWriter {
+            out: Vec::new(),
+            ofs: start_ofs,
+            entries: Vec::new(),
+        };
+        zip.add("0.png", b"0").unwrap();
+        let archive = zip.finish().unwrap();
+        let zip64_end = zip64_end(&archive, start_ofs);
+        assert_eq!(u64_at(zip64_end, 32), 1);
+        let dir_ofs = u64_at(zip64_end, 48);
+        assert_eq!(u32_at(&archive[archive.len() - 22..], 16), u32::MAX);
+
+        let header = &archive[(dir_ofs - start_ofs) as usize..];
+        assert_eq!(u32_at(header, 0), CENTRAL_HEADER_SIG);
+        assert_eq!(u16_at(header, 6), ZIP64_VERSION);
+        assert_eq!(u32_at(header, 42), u32::MAX);
+        // The extra field follows the name
+        assert_eq!((u16_at(header, 28), u16_at(header, 30)), (5, 12));
+        let extra = &header[46 + 5..];
+        assert_eq!((u16_at(extra, 0), u16_at(extra, 2)), (ZIP64_EXTRA_ID, 8));
+        assert_eq!(u64_at(extra, 4), start_ofs);
+    }
+}
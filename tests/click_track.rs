@@ -0,0 +1,55 @@
+//! Renders a log with `--click-track`, and checks the audio track muxed into the MP4.
+
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+#[test]
+fn click_track_is_muxed_along_the_video() {
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("click_track");
+    fs::create_dir_all(&out_dir).unwrap();
+    let out = out_dir.join("eight_banks.mp4");
+    let status = Command::new(env!("CARGO_BIN_EXE_gb-packing-visualizer"))
+        .args(["-i", "tests/data/eight_banks.log", "--click-track"])
+        .arg(&out)
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    // No sidecar anymore
+    assert!(!out.with_extension("wav").exists());
+
+    let mut mp4 = mp4::read_mp4(File::open(&out).unwrap()).unwrap();
+    let mut tracks: Vec<_> = mp4.tracks().values().collect();
+    tracks.sort_by_key(|track| track.track_id());
+    assert_eq!(tracks.len(), 2);
+    let (video, audio) = (tracks[0], tracks[1]);
+    assert_eq!(video.track_type().unwrap(), mp4::TrackType::Video);
+    assert_eq!(audio.track_type().unwrap(), mp4::TrackType::Audio);
+    assert_eq!(audio.track_id(), video.track_id() + 1);
+    assert_eq!(audio.timescale(), 48000);
+    assert_eq!(audio.duration(), video.duration());
+    let nb_samples = audio.sample_count();
+    // As many samples as the video lasts, to within a frame
+    let audio_ns = u128::from(nb_samples) * 1_000_000_000 / 48000;
+    let video_ns = video.duration().as_nanos();
+    assert!(
+        audio_ns.abs_diff(video_ns) < 1_000_000_000 / 60,
+        "{} vs {}",
+        audio_ns,
+        video_ns
+    );
+
+    let track_id = audio.track_id();
+    let mut nb_loud = 0;
+    for i in 1..=nb_samples {
+        let sample = mp4.read_sample(track_id, i).unwrap().unwrap();
+        assert_eq!(sample.bytes.len(), 2);
+        if sample.bytes[..] != [0, 0] {
+            nb_loud += 1;
+        }
+    }
+    assert_ne!(nb_loud, 0);
+    // Clicks are short, and the video mostly silent
+    assert!(nb_loud < nb_samples / 2);
+}
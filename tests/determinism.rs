@@ -0,0 +1,53 @@
+//! Renders the same log twice, and checks that the outputs are byte-for-byte identical.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Renders the log at this path to an MP4 next to it, and returns its contents.
+fn render(log_path: &Path, args: &[&str]) -> Vec<u8> {
+    let out = log_path.with_extension("mp4");
+    let status = Command::new(env!("CARGO_BIN_EXE_gb-packing-visualizer"))
+        .arg("-i")
+        .arg(log_path)
+        .args(args)
+        .arg(&out)
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    fs::read(out).unwrap()
+}
+
+/// Copies the canned log to these names, in a fresh directory.
+fn copies(test_name: &str, names: &[&str]) -> Vec<PathBuf> {
+    let log = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/eight_banks.log");
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("determinism")
+        .join(test_name);
+    fs::create_dir_all(&out_dir).unwrap();
+    names
+        .iter()
+        .map(|name| {
+            let path = out_dir.join(name);
+            fs::copy(&log, &path).unwrap();
+            path
+        })
+        .collect()
+}
+
+#[test]
+fn rendering_twice_is_identical() {
+    let logs = copies("twice", &["build.log"]);
+    let args = ["--color-by-name", "--flash-on-settle", "--title-card"];
+    assert!(render(&logs[0], &args) == render(&logs[0], &args));
+}
+
+#[test]
+fn deterministic_ignores_the_file_name() {
+    let logs = copies("names", &["a.log", "b.log"]);
+    let args = ["--title-card", "--deterministic"];
+    assert!(render(&logs[0], &args) == render(&logs[1], &args));
+    // Otherwise, the file name is stored in the video
+    assert!(render(&logs[0], &args[..1]) != render(&logs[1], &args[..1]));
+}
@@ -0,0 +1,39 @@
+//! Dumps a canned log's last frame as raw pixels, and checks them against the still.
+
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[test]
+fn dump_is_the_size_then_the_still_pixels() {
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("dump_rgb");
+    fs::create_dir_all(&out_dir).unwrap();
+    let (dump, still) = (out_dir.join("last.rgb"), out_dir.join("last.png"));
+    let status = Command::new(env!("CARGO_BIN_EXE_gb-packing-visualizer"))
+        .arg("-i")
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/eight_banks.log"))
+        .arg("--dump-rgb")
+        .arg(&dump)
+        .arg("--still")
+        .arg(&still)
+        .args(["--format", "apng"])
+        .arg(out_dir.join("last.apng"))
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let dump = fs::read(dump).unwrap();
+    let width = u32::from_le_bytes(dump[0..4].try_into().unwrap());
+    let height = u32::from_le_bytes(dump[4..8].try_into().unwrap());
+    assert_eq!(dump.len(), 8 + (width * height * 3) as usize);
+
+    let mut reader = png::Decoder::new(File::open(still).unwrap())
+        .read_info()
+        .unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!((info.width, info.height), (width, height));
+    assert!(dump[8..] == pixels[..info.buffer_size()]);
+}
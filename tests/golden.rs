@@ -0,0 +1,73 @@
+//! Renders canned logs to a still, and compares it against checked-in images, to catch changes to
+//! where banks and sections are drawn.
+//!
+//! After an intended change to the rendering, run with `UPDATE_GOLDEN=1` to write the new images,
+//! and look at them before committing them.
+
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// The image's width, height, and RGB pixels.
+fn read_png(path: &Path) -> (u32, u32, Vec<u8>) {
+    let decoder = png::Decoder::new(File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgb);
+    pixels.truncate(info.buffer_size());
+    (info.width, info.height, pixels)
+}
+
+fn check_still(name: &str) {
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&out_dir).unwrap();
+    let still = out_dir.join(format!("{}.png", name));
+    let status = Command::new(env!("CARGO_BIN_EXE_gb-packing-visualizer"))
+        .arg("-i")
+        .arg(tests_dir().join("data").join(format!("{}.log", name)))
+        .arg("--still")
+        .arg(&still)
+        .args(["--format", "apng"])
+        .arg(out_dir.join(format!("{}.apng", name)))
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let golden = tests_dir().join("golden").join(format!("{}.png", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::copy(&still, &golden).unwrap();
+        return;
+    }
+    let (width, height, pixels) = read_png(&still);
+    let (golden_width, golden_height, golden_pixels) = read_png(&golden);
+    assert_eq!((width, height), (golden_width, golden_height));
+    let nb_different = pixels
+        .chunks_exact(3)
+        .zip(golden_pixels.chunks_exact(3))
+        .filter(|(pixel, golden)| pixel != golden)
+        .count();
+    assert!(
+        nb_different == 0,
+        "{} pixels differ from {}, see {}",
+        nb_different,
+        golden.display(),
+        still.display()
+    );
+}
+
+#[test]
+fn two_banks() {
+    check_still("two_banks");
+}
+
+#[test]
+fn eight_banks() {
+    check_still("eight_banks");
+}
@@ -0,0 +1,22 @@
+//! Renders a log with a size limit that it can't fit in, and checks that nothing is left behind.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[test]
+fn tiny_limit_aborts() {
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("max_output_bytes");
+    fs::create_dir_all(&out_dir).unwrap();
+    let out = out_dir.join("tiny.mp4");
+    let status = Command::new(env!("CARGO_BIN_EXE_gb-packing-visualizer"))
+        .arg("-i")
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/eight_banks.log"))
+        .args(["--max-output-bytes", "1"])
+        .arg(&out)
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    assert!(!out.exists());
+}
@@ -0,0 +1,34 @@
+//! Renders an MP4 into a FIFO, which can't be seeked back into to finish the file.
+#![cfg(unix)]
+
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn mp4_into_a_pipe_is_refused() {
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("not_seekable");
+    fs::create_dir_all(&out_dir).unwrap();
+    let fifo = out_dir.join("out.mp4");
+    let _ = fs::remove_file(&fifo);
+    let path = CString::new(fifo.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o600) }, 0);
+    // Holding both ends open keeps opening it from blocking
+    let _fifo = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&fifo)
+        .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gb-packing-visualizer"))
+        .arg("-i")
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/two_banks.log"))
+        .arg(&fifo)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("requires a seekable file"), "{}", stderr);
+}
@@ -0,0 +1,40 @@
+//! Renders a log into memory through the library, and checks the MP4 it makes.
+
+use gb_packing_visualizer::{parse, render};
+use std::convert::TryInto;
+use std::io::Cursor;
+
+#[test]
+fn renders_an_mp4_into_memory() {
+    let log = "[ROMX @ 01:ffff & 0 + 0] 16 A\n01:4000\n01:4010\n=01:4020\n";
+    let mut warnings = Vec::new();
+    let sequence = parse::parse_input(log.as_bytes(), &Default::default(), &mut warnings).unwrap();
+    let mut out = Cursor::new(Vec::new());
+    let mut progress = Vec::new();
+    render::render_to(
+        &sequence,
+        &mut out,
+        &render::Options::default(),
+        &mut warnings,
+        &mut |i, total| progress.push((i, total)),
+    )
+    .unwrap();
+    assert_eq!(progress.last(), Some(&(1, 3)));
+
+    // The top-level boxes, each being its size then its type
+    let mp4 = out.into_inner();
+    let mut types = Vec::new();
+    let mut ofs = 0;
+    while ofs < mp4.len() {
+        let size = u32::from_be_bytes(mp4[ofs..ofs + 4].try_into().unwrap());
+        types.push(String::from_utf8_lossy(&mp4[ofs + 4..ofs + 8]).into_owned());
+        ofs += size as usize;
+    }
+    assert_eq!(ofs, mp4.len());
+    assert_eq!(types, ["ftyp", "mdat", "moov"]);
+
+    let size = mp4.len() as u64;
+    let reader = mp4::Mp4Reader::read_header(Cursor::new(mp4), size).unwrap();
+    let track = reader.tracks().values().next().unwrap();
+    assert_eq!(track.sample_count(), 2);
+}
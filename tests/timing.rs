@@ -0,0 +1,81 @@
+//! Renders generated logs, and checks how long the output says it lasts.
+
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// A log with a single section, attempted at this many different places.
+fn attempts_log(nb_attempts: u32) -> String {
+    let mut log = String::from("[ROMX @ 01:ffff & 0 + 0] 16 A\n");
+    for i in 0..nb_attempts {
+        log.push_str(&format!("01:{:04x}\n", 0x4000 + i * 16));
+    }
+    log
+}
+
+/// Renders the log as an MP4, and returns its path.
+fn render(name: &str, log: &str, args: &[&str]) -> PathBuf {
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("timing");
+    fs::create_dir_all(&out_dir).unwrap();
+    let log_path = out_dir.join(format!("{}.log", name));
+    fs::write(&log_path, log).unwrap();
+    let out = out_dir.join(format!("{}.mp4", name));
+    let status = Command::new(env!("CARGO_BIN_EXE_gb-packing-visualizer"))
+        .arg("-i")
+        .arg(&log_path)
+        .args(args)
+        .arg(&out)
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    out
+}
+
+#[test]
+fn frames_last_one_tick_each() {
+    let path = render("120_frames", &attempts_log(120), &["--fps", "60"]);
+    let mut mp4 = mp4::read_mp4(File::open(path).unwrap()).unwrap();
+    assert_eq!(mp4.timescale(), 60);
+    assert_eq!(mp4.duration(), Duration::from_secs(2));
+
+    let track = mp4.tracks().values().next().unwrap();
+    assert_eq!(track.timescale(), 60);
+    assert_eq!(track.sample_count(), 120);
+    assert_eq!(track.duration(), Duration::from_secs(2));
+
+    let track_id = track.track_id();
+    for i in 0..120 {
+        let sample = mp4.read_sample(track_id, i + 1).unwrap().unwrap();
+        assert_eq!((sample.start_time, sample.duration), (u64::from(i), 1));
+    }
+}
+
+#[test]
+fn duration_is_fit() {
+    let path = render("fit", &attempts_log(100), &["--duration", "3"]);
+    let mp4 = mp4::read_mp4(File::open(path).unwrap()).unwrap();
+    assert_eq!(mp4.duration(), Duration::from_secs(3));
+}
+
+#[test]
+fn samples_never_last_0() {
+    // Far less than a tick per frame
+    let path = render("too_short", &attempts_log(100), &["--duration", "0.0001"]);
+    let mut mp4 = mp4::read_mp4(File::open(path).unwrap()).unwrap();
+    let track_id = *mp4.tracks().keys().next().unwrap();
+    for i in 0..100 {
+        let sample = mp4.read_sample(track_id, i + 1).unwrap().unwrap();
+        assert_eq!(sample.duration, 1);
+    }
+}
+
+#[test]
+fn sampling_keeps_the_last_frame() {
+    // 10 frames, sampled at 2, 5, 8, then 9
+    let path = render("sampled", &attempts_log(10), &["--sample-every", "3"]);
+    let mp4 = mp4::read_mp4(File::open(path).unwrap()).unwrap();
+    let track = mp4.tracks().values().next().unwrap();
+    assert_eq!(track.sample_count(), 4);
+}